@@ -0,0 +1,8 @@
+#![no_main]
+use gpro::parser::Playlist;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &str| {
+    Playlist::from(data);
+    Playlist::get_name(data);
+});
@@ -0,0 +1,14 @@
+#![no_main]
+use gpro::parser::Song;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &str| {
+    let mut song = Song::from(data.to_string());
+    for line in &song.content {
+        line.wrap(10);
+    }
+    if song.key.is_some() {
+        song.change_key(-13);
+    }
+    Song::get_name(data);
+});
@@ -1,91 +1,1109 @@
 use crate::{
-    conf::Config,
-    parser::{Playlist, Song},
+    collation::{self, Collation},
+    conf::{Config, SortMode},
+    editor::{Editor, MetadataForm},
+    encoding::{self, Decoded},
+    files::{FileAction, Prompt, Undo},
+    fuzzy,
+    index::Index,
+    layout::Shown,
+    lint::{self, Lint},
+    parser::{parse_key, Mode, ParseError, Playlist, Song},
+    query::Query,
+    state::SavedState,
+    stats::ChordStats,
 };
 use lazy_static::lazy_static;
-use regex::Regex;
+use regex::{Regex, RegexBuilder};
 use rust_music_theory::note::PitchClass;
 use std::{
+    borrow::Cow,
+    cell::{OnceCell, Ref, RefCell},
+    cmp::Reverse,
     collections::HashMap,
     fs::{self, DirEntry},
+    io,
+    ops::Range,
     path::{Path, PathBuf},
+    time::Instant,
 };
 use tui::widgets::ListState;
 
+/// Search input starting with this searches the text of the songs instead of their names
+pub const TEXT_SEARCH_PREFIX: char = '/';
+/// Seconds a notification is shown before it goes away by itself
+const NOTIFICATION_SECONDS: u64 = 6;
+
 lazy_static! {
-    static ref RE_SONG_TRANSPOSITION: Regex = Regex::new(r" \[([ABCDEFG][b#]?)\]").unwrap();
+    static ref RE_SONG_TRANSPOSITION: Regex = Regex::new(r" \[([ABCDEFG][b#]?m?)\]").unwrap();
 }
 
-#[derive(PartialEq)]
+#[derive(Default, PartialEq)]
 pub enum AppState {
+    #[default]
     Default,
     Searching,
     Transposing,
+    Editing,
+    EditingMetadata,
+    Prompting,
+    Linting,
+    Stats,
+    Help,
+    /// Picking a part of the song to jump to
+    Sections,
+    /// Showing the lyrics one part at a time, for a projector
+    Presenting,
 }
 
-impl Default for AppState {
-    fn default() -> AppState {
-        AppState::Default
+impl AppState {
+    pub fn label(&self) -> &'static str {
+        match self {
+            AppState::Default => "Browse",
+            AppState::Searching => "Search",
+            AppState::Transposing => "Transpose",
+            AppState::Editing => "Edit",
+            AppState::EditingMetadata => "Metadata",
+            AppState::Prompting => "Prompt",
+            AppState::Linting => "Lint",
+            AppState::Stats => "Stats",
+            AppState::Help => "Help",
+            AppState::Sections => "Sections",
+            AppState::Presenting => "Present",
+        }
     }
 }
 
 #[derive(Default)]
 pub struct App {
-    files: HashMap<FileType, String>,
+    index: Index,
+    collation: Collation,
+    files: HashMap<FileType, LazyFile>,
     pub file_nav: FileNavigator,
     pub search_nav: FileNavigator,
     pub state: AppState,
+    /// Order of the songs in the open folders
+    pub sort: SortMode,
+    /// Whether the library is still being scanned in the background
+    pub indexing: bool,
     pub config: Config,
     pub song: Option<Song>,
+    /// First problem in the file of the open song, which is shown as far as it could be
+    /// parsed
+    pub song_error: Option<ParseError>,
+    /// Lines of the open song scrolled past
+    pub scroll: usize,
+    /// Whether the open song scrolls by itself
+    pub auto_scroll: bool,
+    /// Seconds between lines when auto-scrolling
+    pub scroll_interval: f64,
+    /// When the song last auto-scrolled
+    last_scroll: Option<Instant>,
     pub input: String,
-    pub path: Vec<String>,
+    /// Characters of the search results' names that matched the search, by index
+    pub search_matches: HashMap<FileType, Vec<usize>>,
+    /// Line of every search result that contains the search, if it wasn't found by name
+    pub search_snippets: HashMap<FileType, String>,
+    /// Whether the search input is a regular expression
+    pub regex_search: bool,
+    /// Why the search input isn't a valid regular expression
+    pub search_error: Option<String>,
+    /// Something that went wrong, with when it happened
+    pub notification: Option<(String, Instant)>,
+    /// Key typed into the transpose box
+    pub key_input: String,
+    pub saved: SavedState,
+    pub editor: Option<Editor>,
+    pub metadata: Option<MetadataForm>,
+    pub prompt: Option<Prompt>,
+    /// File operations of this session, most recent last
+    undo: Vec<Undo>,
+    /// Problems found in the open song
+    pub lints: Vec<Lint>,
+    pub stats: Option<ChordStats>,
+    /// Whether the chord statistics are about the open playlist instead of the song
+    pub stats_playlist: bool,
+    pub show_notes: bool,
+    /// Whether the `#` comments in the song file are shown, for debugging songs
+    pub show_source_comments: bool,
+    /// Whether the chords that couldn't be read are listed next to the song
+    pub show_diagnostics: bool,
+    /// Whether diagrams of every chord in the song are shown next to it
+    pub show_chord_diagrams: bool,
+    /// Whether chords are shown as Nashville numbers
+    pub nashville: bool,
+    /// Whether transposed chords are shown along with the chords as written
+    pub dual_chords: bool,
+    /// Whether the Roman numeral of every chord is shown below it
+    pub roman_numerals: bool,
+    /// Whether only the lyrics of the song are shown
+    pub lyrics_only: bool,
+    /// Whether the song is shown as a chart of only its chords
+    pub chords_only: bool,
+    /// Whether the song list is hidden while browsing so the song gets the full width
+    pub hide_sidebar: bool,
+    pub sidebar_width: u16,
+    /// Highlighted part in the list of parts of the song
+    pub section_selected: usize,
+    /// Part of the song shown when presenting
+    pub slide: usize,
+    /// Index of the open song in the tabs of `saved.session.tabs`
+    pub tab: usize,
+    /// Song shown next to the open song, which doesn't have focus
+    pub split: Option<SplitPane>,
+    /// Whether the open song is the left one in split view
+    pub split_left: bool,
+    /// Playlist that is being played through song by song
+    pub setlist: Option<Setlist>,
+    /// Songs opened this session in the order they were first opened, which can be
+    /// saved as a playlist
+    pub queue: Vec<String>,
+    /// What of the open song fit on screen when it was last drawn
+    pub shown: RefCell<Shown>,
+    /// Name of the song last previewed with the song read for it, so it's only read once
+    /// while it stays highlighted
+    previewed: RefCell<Option<(String, Option<Song>)>>,
 }
 
-impl<'a> App {
-    pub fn new(config: Config) -> Self {
-        let files = App::create_filemap(&config.path);
-        let mut all_files: Vec<FileType> = files.keys().cloned().collect();
-        all_files.sort_by_key(FileType::name);
-        App {
-            file_nav: FileNavigator::from_path(&config.path),
-            search_nav: FileNavigator(vec![Folder {
-                name: String::from("Search"),
-                files: all_files,
-                state: ListState::default(),
-            }]),
-            files,
+impl App {
+    pub fn new(config: Config, index: Index, saved: SavedState) -> Self {
+        let collation = Collation::new(&config.locale);
+        let mut app = App {
+            nashville: config.nashville,
+            dual_chords: config.dual_chords,
+            roman_numerals: config.roman_numerals,
+            lyrics_only: config.lyrics_only,
+            sidebar_width: saved.session.sidebar_width.unwrap_or(config.sidebar_width),
+            chords_only: config.chords_only && !config.lyrics_only,
+            sort: config.sort,
+            file_nav: FileNavigator(vec![Folder::library(&config, &index, &collation)]),
+            index,
+            collation,
             config,
+            saved,
             ..Default::default()
+        };
+        app.load_files();
+        app.sort_folders();
+        if let Some(name) = app.saved.session.song.clone() {
+            app.load_song(&name);
         }
+        app
     }
 
     pub fn load_selected(&mut self) {
         let file = self.get_nav().selected().cloned();
         if let Some(file) = file {
             match &file {
-                FileType::Folder(path) => self.get_nav_mut().open_path(&path),
-                FileType::Playlist(_) => {
-                    let playlist = Playlist::from(self.files.get(&file).unwrap());
-                    self.get_nav_mut().open_playlist(playlist)
+                FileType::Folder(path) => {
+                    let folder = Folder::from_path(path, &self.index, &self.collation);
+                    self.get_nav_mut().open_folder(folder);
+                    self.sort_folders();
                 }
-                FileType::Song(name) => match self.files.get(&file) {
-                    Some(song) => self.song = Some(Song::from(song.clone())),
-                    None => {
-                        if let Some(key) = RE_SONG_TRANSPOSITION.captures(&name) {
-                            let actual_name = RE_SONG_TRANSPOSITION.replace(&name, "");
-                            if let Some(song) =
-                                self.files.get(&FileType::Song(actual_name.to_string()))
-                            {
-                                self.song = Some(Song::in_key(
-                                    song.clone(),
-                                    PitchClass::from_str(key.get(1).unwrap().as_str()).unwrap(),
-                                ))
-                            }
+                FileType::Playlist(name) => {
+                    let file = match self.files.get(&file) {
+                        Some(file) => file,
+                        None => {
+                            self.notify(format!("Couldn't find the playlist \"{}\"", name));
+                            return;
                         }
+                    };
+                    let mut playlist = Playlist::from(file.contents());
+                    let missing = self.resolve_playlist(&mut playlist, file.path.parent());
+                    if !missing.is_empty() {
+                        let message = format!("Not in the library: {}", missing.join(", "));
+                        self.notify(message);
                     }
-                },
+                    self.get_nav_mut().open_playlist(playlist)
+                }
+                FileType::Song(name) => self.load_song(name),
+                FileType::Tag(tag) => self.open_view(View::Tagged(tag.clone())),
+            }
+        }
+    }
+
+    /// Finds the file of the song called `name`. Playlist entries can ask for a
+    /// specific key by appending it to the name, like `Song title [G]`.
+    fn song_file(&self, name: &str) -> Option<(&LazyFile, Option<PitchClass>)> {
+        if let Some(file) = self.files.get(&FileType::Song(name.to_string())) {
+            return Some((file, None));
+        }
+        let key = RE_SONG_TRANSPOSITION.captures(name)?;
+        let (key, _) = parse_key(key.get(1).unwrap().as_str())?;
+        let actual_name = RE_SONG_TRANSPOSITION.replace(name, "");
+        self.files
+            .get(&FileType::Song(actual_name.to_string()))
+            .map(|file| (file, Some(key)))
+    }
+
+    /// Whether there is a song called `name`, which can ask for a key like a playlist entry
+    pub fn has_song(&self, name: &str) -> bool {
+        self.song_file(name).is_some()
+    }
+
+    /// Name of the song a playlist entry refers to. Besides the name of a song, an entry
+    /// can be the path of its file relative to the playlist in `dir`, with or without
+    /// `.txt`, or its title written differently, like `amazing grace`.
+    fn resolve_song(&self, entry: &str, dir: Option<&Path>) -> Option<String> {
+        if self.has_song(entry) {
+            return Some(entry.to_string());
+        }
+        let (entry, key) = match RE_SONG_TRANSPOSITION.find(entry) {
+            Some(key) => (&entry[..key.start()], &entry[key.start()..]),
+            None => (entry, ""),
+        };
+        let by_path = dir.and_then(|dir| {
+            [dir.join(entry), dir.join(format!("{}.txt", entry))]
+                .iter()
+                .find_map(|path| match self.index.get(path)?.first()? {
+                    FileType::Song(name) => Some(name.clone()),
+                    _ => None,
+                })
+        });
+        let folded = collation::fold(entry.trim());
+        let name = by_path.or_else(|| {
+            self.files.keys().find_map(|file| match file {
+                FileType::Song(name) if collation::fold(name) == folded => Some(name.clone()),
+                _ => None,
+            })
+        })?;
+        Some(name + key)
+    }
+
+    /// Replaces every entry of a playlist in `dir` by the name of the song it refers to,
+    /// see `resolve_song`, and returns the entries that aren't in the library
+    fn resolve_playlist(&self, playlist: &mut Playlist, dir: Option<&Path>) -> Vec<String> {
+        let mut missing = vec![];
+        for song in playlist.songs.iter_mut() {
+            if let FileType::Song(name) = song {
+                match self.resolve_song(name, dir) {
+                    Some(resolved) => *name = resolved,
+                    None => missing.push(name.clone()),
+                }
+            }
+        }
+        missing
+    }
+
+    /// Reads the playlist file at `path`, which doesn't have to be in the library, with
+    /// the entries that aren't in the library. Those are left out of the playlist.
+    pub fn read_playlist(&self, path: &Path) -> io::Result<(Playlist, Vec<String>)> {
+        let mut playlist = Playlist::from(&encoding::read_to_string(path)?.text);
+        let missing = self.resolve_playlist(&mut playlist, path.parent());
+        playlist
+            .songs
+            .retain(|song| !matches!(song, FileType::Song(name) if missing.contains(name)));
+        Ok((playlist, missing))
+    }
+
+    /// The songs of `playlist` as a single ChordPro file, separated by `{new_song}`. Each
+    /// song is written in the key the playlist asks for, or else the key it was last
+    /// transposed to.
+    pub fn playlist_chordpro(&self, playlist: &Playlist) -> String {
+        let mut songs = vec![];
+        for song in &playlist.songs {
+            let name = match song {
+                FileType::Song(name) => name,
+                _ => continue,
+            };
+            let (file, key) = match self.song_file(name) {
+                Some(file) => file,
+                None => continue,
+            };
+            let written = Song::from(file.contents().to_string()).key;
+            let transposition = match (key, written) {
+                (Some(key), Some(written)) => key.into_u8() as i32 - written.into_u8() as i32,
+                (Some(_), None) => 0,
+                (None, _) => self.saved.transpositions.get(name).copied().unwrap_or(0),
+            };
+            let song = Song::transpose_source(file.contents(), transposition);
+            songs.push(song.trim_end().to_string());
+        }
+        songs.join("\n{new_song}\n") + "\n"
+    }
+
+    /// The songs of `playlist` the way they are shown, each in the key the playlist asks
+    /// for or else the key it was last transposed to
+    pub fn playlist_songs(&self, playlist: &Playlist) -> Vec<Song> {
+        playlist
+            .songs
+            .iter()
+            .filter_map(|song| match song {
+                FileType::Song(name) => self.read_song(name).map(|(song, _)| song),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Reads the song called `name` in the key it was last transposed to, along with the
+    /// first problem in its file
+    fn read_song(&self, name: &str) -> Option<(Song, Option<ParseError>)> {
+        self.read_song_in(name, None)
+    }
+
+    /// Reads the song called `name` like `read_song`, but shown in `key` or the key its
+    /// playlist entry asks for if there is one
+    fn read_song_in(
+        &self,
+        name: &str,
+        key: Option<PitchClass>,
+    ) -> Option<(Song, Option<ParseError>)> {
+        let (file, listed_key) = self.song_file(name)?;
+        let key = key.or(listed_key);
+        let (mut song, error) = Song::parse(
+            file.contents().to_string(),
+            key,
+            self.config.instrument.as_deref(),
+        );
+        song.set_accidentals(self.config.accidentals);
+        song.converted_from = file.converted_from();
+        if let Ok(notes) = encoding::read_to_string(&file.path.with_extension("notes")) {
+            song.notes.extend(notes.text.lines().map(String::from));
+        }
+        match self.saved.transpositions.get(name) {
+            Some(&transposition) if key.is_none() => song.change_key(transposition),
+            _ => (),
+        }
+        Some((song, error))
+    }
+
+    /// The open song as it should be shown, with Nashville numbers, or the original chords
+    /// and Roman numerals if those are enabled, or only its lyrics or chords
+    pub fn displayed_song(&self) -> Option<Cow<'_, Song>> {
+        let song = self.song.as_ref()?;
+        if self.lyrics_only {
+            return Some(Cow::Owned(song.lyrics_only()));
+        }
+        let mut song = Cow::Borrowed(song);
+        if self.nashville {
+            song = Cow::Owned(song.to_nashville());
+        } else {
+            if self.dual_chords {
+                song = Cow::Owned(song.with_original_chords());
+            }
+            if self.roman_numerals {
+                song = Cow::Owned(song.with_roman_numerals());
+            }
+        }
+        if self.chords_only {
+            song = Cow::Owned(song.chords_only());
+        }
+        Some(song)
+    }
+
+    /// Opens the song called `name`, in the current tab unless it's open in another tab
+    pub fn load_song(&mut self, name: &str) {
+        let unreadable = match self.song_file(name) {
+            Some((file, _)) => file
+                .error()
+                .map(|error| (file.path.clone(), error.to_string())),
+            None => {
+                self.notify(format!("Couldn't find the song \"{}\"", name));
+                return;
+            }
+        };
+        if let Some((path, error)) = unreadable {
+            self.notify(format!("Couldn't read {}: {}", path.display(), error));
+        }
+        if let Some((song, error)) = self.read_song(name) {
+            let tabs = &mut self.saved.session.tabs;
+            match tabs.iter().position(|tab| tab == name) {
+                Some(tab) => self.tab = tab,
+                None if tabs.is_empty() => tabs.push(name.to_string()),
+                None => tabs[self.tab] = name.to_string(),
+            }
+            self.song = Some(song);
+            self.song_error = error;
+            self.scroll = self.saved.scroll.get(name).copied().unwrap_or(0);
+            self.saved.opened(name);
+            if !self.queue.iter().any(|song| song == name) {
+                self.queue.push(name.to_string());
+            }
+            self.auto_scroll = false;
+        }
+    }
+
+    /// Opens the highlighted song in a new tab after the current one
+    pub fn open_in_new_tab(&mut self) {
+        let name = match self.get_nav().selected() {
+            Some(FileType::Song(name)) => name.clone(),
+            _ => return,
+        };
+        let tabs = &mut self.saved.session.tabs;
+        if !tabs.contains(&name) {
+            let position = match tabs.is_empty() {
+                true => 0,
+                false => self.tab + 1,
+            };
+            tabs.insert(position, name.clone());
+        }
+        self.load_song(&name);
+    }
+
+    /// Switches to the next tab, or the previous one, going around at the ends
+    pub fn cycle_tabs(&mut self, forward: bool) {
+        let count = self.saved.session.tabs.len();
+        if count < 2 {
+            return;
+        }
+        let tab = match forward {
+            true => (self.tab + 1) % count,
+            false => (self.tab + count - 1) % count,
+        };
+        let name = self.saved.session.tabs[tab].clone();
+        self.load_song(&name);
+    }
+
+    /// Closes the current tab and switches to the one after it. The last tab stays open.
+    pub fn close_tab(&mut self) {
+        let tabs = &mut self.saved.session.tabs;
+        if tabs.len() < 2 {
+            return;
+        }
+        tabs.remove(self.tab);
+        self.tab = self.tab.min(tabs.len() - 1);
+        let name = tabs[self.tab].clone();
+        self.load_song(&name);
+    }
+
+    /// Plays through the open playlist, or the highlighted one, starting at the highlighted
+    /// song, or stops doing so
+    pub fn toggle_setlist(&mut self) {
+        if self.setlist.take().is_some() {
+            return;
+        }
+        if !self.get_nav().current().playlist {
+            match self.get_nav().selected() {
+                Some(FileType::Playlist(_)) => self.load_selected(),
+                _ => {
+                    self.notify("Open or highlight a playlist to play it as a setlist");
+                    return;
+                }
+            }
+        }
+        let folder = self.get_nav().current();
+        let songs: Vec<String> = folder.files.iter().map(FileType::name).collect();
+        if songs.is_empty() {
+            return;
+        }
+        let position = folder
+            .state
+            .selected()
+            .filter(|&i| i < songs.len())
+            .unwrap_or(0);
+        self.setlist = Some(Setlist {
+            title: folder.name.clone(),
+            songs,
+            position,
+        });
+        self.setlist_step_to(position);
+    }
+
+    /// Opens the next song of the setlist, or the previous one
+    pub fn setlist_step(&mut self, forward: bool) {
+        let (position, count) = match &self.setlist {
+            Some(setlist) => (setlist.position, setlist.songs.len()),
+            None => return,
+        };
+        match forward {
+            true if position + 1 < count => self.setlist_step_to(position + 1),
+            false if position > 0 => self.setlist_step_to(position - 1),
+            _ => (),
+        }
+    }
+
+    fn setlist_step_to(&mut self, position: usize) {
+        let name = match &mut self.setlist {
+            Some(setlist) => {
+                setlist.position = position;
+                setlist.songs[position].clone()
+            }
+            None => return,
+        };
+        let folder = self.get_nav_mut().current_mut();
+        if folder.playlist {
+            folder.state.select(Some(position));
+        }
+        self.load_song(&name);
+    }
+
+    /// Shows the highlighted song, or else the next tab, next to the open song, or closes
+    /// split view when it's open
+    pub fn toggle_split(&mut self) {
+        if self.split.take().is_some() {
+            return;
+        }
+        let tabs = &self.saved.session.tabs;
+        let name = match self.get_nav().selected() {
+            Some(FileType::Song(name)) if self.saved.session.song.as_ref() != Some(name) => {
+                name.clone()
+            }
+            _ if tabs.len() > 1 => tabs[(self.tab + 1) % tabs.len()].clone(),
+            _ => return,
+        };
+        if let Some((song, _)) = self.read_song(&name) {
+            let scroll = self.saved.scroll.get(&name).copied().unwrap_or(0);
+            self.split = Some(SplitPane { name, song, scroll });
+            self.split_left = true;
+        }
+    }
+
+    /// Moves focus to the other song in split view, so keys like scrolling and
+    /// transposing apply to it
+    pub fn switch_split_focus(&mut self) {
+        let (split, song, name) = match (self.split.take(), self.song.take()) {
+            (Some(split), Some(song)) => match self.saved.session.song.clone() {
+                Some(name) => (split, song, name),
+                None => return,
+            },
+            (split, song) => {
+                self.split = split;
+                self.song = song;
+                return;
+            }
+        };
+        self.split = Some(SplitPane {
+            name,
+            song,
+            scroll: self.scroll,
+        });
+        let SplitPane { name, song, scroll } = split;
+        self.song = Some(song);
+        self.song_error = None;
+        self.scroll = scroll;
+        self.auto_scroll = false;
+        if let Some(tab) = self.saved.session.tabs.iter().position(|tab| *tab == name) {
+            self.tab = tab;
+        }
+        self.saved.session.song = Some(name);
+        self.split_left = !self.split_left;
+    }
+
+    /// Starts or stops scrolling the open song by itself. The speed is worked out from
+    /// its `{duration}` or `{tempo}` if it has one.
+    pub fn toggle_auto_scroll(&mut self) {
+        let song = match &self.song {
+            Some(song) if !self.auto_scroll => song,
+            _ => {
+                self.auto_scroll = false;
+                return;
+            }
+        };
+        self.scroll_interval = song_scroll_interval(song).unwrap_or(self.config.scroll_interval);
+        self.auto_scroll = true;
+        self.last_scroll = Some(Instant::now());
+    }
+
+    /// Makes the song list `change` columns wider, or narrower when it's negative. The
+    /// width is remembered for the next run.
+    pub fn resize_sidebar(&mut self, change: i16) {
+        self.sidebar_width = self
+            .sidebar_width
+            .saturating_add_signed(change)
+            .clamp(10, 80);
+        self.saved.session.sidebar_width = Some(self.sidebar_width);
+    }
+
+    /// Switches to the next of the configured themes, going back to the first one after
+    /// the last
+    pub fn next_theme(&mut self) {
+        if self.config.themes.is_empty() {
+            return;
+        }
+        let next = self.config.themes.remove(0);
+        let previous = std::mem::replace(&mut self.config.theme, next);
+        self.config.themes.push(previous);
+    }
+
+    /// Shows songs in `change` more columns than are on screen now, or fewer when it's
+    /// negative, for the rest of the session
+    pub fn change_columns(&mut self, change: isize) {
+        let columns = self.config.columns.unwrap_or(self.shown.borrow().columns);
+        self.config.columns = Some(columns.saturating_add_signed(change).max(1));
+    }
+
+    /// Makes auto-scrolling faster, or slower when `factor` is below 1
+    pub fn change_scroll_speed(&mut self, factor: f64) {
+        self.scroll_interval = (self.scroll_interval / factor).clamp(0.25, 60.0);
+    }
+
+    /// Shows a message about something that went wrong, until it's dismissed or times out
+    pub fn notify(&mut self, message: impl Into<String>) {
+        self.notification = Some((message.into(), Instant::now()));
+    }
+
+    /// Hides the notification once it has been shown long enough
+    pub fn expire_notification(&mut self, now: Instant) {
+        if let Some((_, shown)) = self.notification {
+            if now.duration_since(shown).as_secs() >= NOTIFICATION_SECONDS {
+                self.notification = None;
+            }
+        }
+    }
+
+    /// Scrolls a line when auto-scrolling and it's time for the next one, stopping at the
+    /// end of the song
+    pub fn auto_scroll_tick(&mut self, now: Instant) {
+        if !self.auto_scroll {
+            return;
+        }
+        let last = self.last_scroll.get_or_insert(now);
+        if now.duration_since(*last).as_secs_f64() < self.scroll_interval {
+            return;
+        }
+        self.last_scroll = Some(now);
+        let scroll = self.scroll;
+        self.scroll_by(1);
+        if self.scroll == scroll {
+            self.auto_scroll = false;
+        }
+    }
+
+    /// Scrolls the open song by `lines` as it's shown, down when positive, and remembers
+    /// where it was scrolled to for the next time it's opened
+    pub fn scroll_by(&mut self, lines: isize) {
+        let last = match self.displayed_song() {
+            Some(song) => song.content.len().saturating_sub(1),
+            None => return,
+        };
+        self.scroll = self.scroll.saturating_add_signed(lines).min(last);
+        if let Some(name) = &self.saved.session.song {
+            self.saved.scroll.insert(name.clone(), self.scroll);
+        }
+    }
+
+    /// Switches between the whole song, only its lyrics and only its chords. They show
+    /// different lines, so the song is scrolled to the start of the same part.
+    pub fn show_only(&mut self, lyrics: bool, chords: bool) {
+        let current = self.current_section();
+        self.lyrics_only = lyrics;
+        self.chords_only = chords && !lyrics;
+        let line = self
+            .sections()
+            .into_iter()
+            .find(|(_, name)| current.as_ref() == Some(name))
+            .map_or(0, |(line, _)| line);
+        self.scroll_to(line);
+    }
+
+    /// Parts of the open song as it's shown, see `Song::outline`
+    pub fn sections(&self) -> Vec<(usize, String)> {
+        self.displayed_song()
+            .map(|song| song.outline())
+            .unwrap_or_default()
+    }
+
+    /// Name of the part of the song at the top of the song pane
+    pub fn current_section(&self) -> Option<String> {
+        self.sections()
+            .into_iter()
+            .take_while(|(line, _)| *line <= self.scroll)
+            .last()
+            .map(|(_, name)| name)
+    }
+
+    /// Scrolls to the start of the next part of the song, or the previous one
+    pub fn jump_section(&mut self, forward: bool) {
+        let sections = self.sections();
+        let line = match forward {
+            true => sections.iter().find(|(line, _)| *line > self.scroll),
+            false => sections.iter().rev().find(|(line, _)| *line < self.scroll),
+        };
+        if let Some(&(line, _)) = line {
+            self.scroll_to(line);
+        }
+    }
+
+    /// Opens the list of parts of the song with the current part highlighted
+    pub fn open_sections(&mut self) {
+        let sections = self.sections();
+        if sections.is_empty() {
+            return;
+        }
+        self.section_selected = sections
+            .iter()
+            .take_while(|(line, _)| *line <= self.scroll)
+            .count()
+            .saturating_sub(1);
+        self.state = AppState::Sections;
+    }
+
+    /// Scrolls to the `n`th part of the song and closes the list of parts
+    pub fn jump_to_section(&mut self, n: usize) {
+        if let Some(&(line, _)) = self.sections().get(n) {
+            self.scroll_to(line);
+            self.state = AppState::Default;
+        }
+    }
+
+    /// Starts presenting the open song from the part at the top of the song pane
+    pub fn start_presenting(&mut self) {
+        let song = match &self.song {
+            Some(song) => song.lyrics_only(),
+            None => return,
+        };
+        // Lines of the lyrics don't match lines of the song, so start at the same part
+        let current = self.current_section();
+        let outline = song.outline();
+        let start = outline
+            .iter()
+            .find(|(_, name)| Some(name) == current.as_ref())
+            .map_or(0, |(line, _)| *line);
+        self.slide = song
+            .parts()
+            .iter()
+            .position(|part| part.contains(&start))
+            .unwrap_or(0);
+        self.state = AppState::Presenting;
+    }
+
+    /// Lines of the part of the song that is presented, its name and the number of parts
+    pub fn presented(&self) -> Option<(Song, Range<usize>, usize)> {
+        let song = self.song.as_ref()?.lyrics_only();
+        let parts = song.parts();
+        let part = parts
+            .get(self.slide.min(parts.len().saturating_sub(1)))?
+            .clone();
+        let count = parts.len();
+        Some((song, part, count))
+    }
+
+    /// Shows the next part of the song when presenting, or the previous one
+    pub fn next_slide(&mut self, forward: bool) {
+        let count = self.presented().map_or(0, |(_, _, count)| count);
+        self.slide = match forward {
+            true => (self.slide + 1).min(count.saturating_sub(1)),
+            false => self.slide.saturating_sub(1),
+        };
+    }
+
+    /// Page the open song is scrolled to and the number of pages, when it's shown a page
+    /// at a time
+    pub fn page(&self) -> Option<(usize, usize)> {
+        let pages = &self.shown.borrow().pages;
+        if !self.config.paging || pages.is_empty() {
+            return None;
+        }
+        let page = pages
+            .iter()
+            .rposition(|page| page.start <= self.scroll)
+            .unwrap_or(0);
+        Some((page, pages.len()))
+    }
+
+    /// Flips to the next page of the open song, or the previous one
+    pub fn flip_page(&mut self, forward: bool) {
+        let (page, count) = match self.page() {
+            Some(page) => page,
+            None => return,
+        };
+        let page = match forward {
+            true if page + 1 < count => page + 1,
+            true => return,
+            false => page.saturating_sub(1),
+        };
+        let line = self.shown.borrow().pages[page].start;
+        self.scroll_to(line);
+    }
+
+    fn scroll_to(&mut self, line: usize) {
+        self.scroll_by(line as isize - self.scroll as isize);
+    }
+
+    /// Scrolls the open song by as many lines as fit in `rows` terminal rows, down when
+    /// positive. Lines with chords take up more than one row.
+    pub fn scroll_rows(&mut self, rows: isize) {
+        let song = match self.displayed_song() {
+            Some(song) => song,
+            None => return,
+        };
+        let heights = song.content.iter().map(|line| line.metrics().height.max(1));
+        let mut lines = 0;
+        let mut scrolled = 0;
+        if rows > 0 {
+            for height in heights.skip(self.scroll) {
+                if scrolled + height > rows.unsigned_abs() && lines > 0 {
+                    break;
+                }
+                scrolled += height;
+                lines += 1;
+            }
+        } else {
+            for height in heights.take(self.scroll).rev() {
+                if scrolled + height > rows.unsigned_abs() && lines > 0 {
+                    break;
+                }
+                scrolled += height;
+                lines += 1;
+            }
+        }
+        self.scroll_by(lines * rows.signum());
+    }
+
+    /// Path of the file of the open song
+    pub fn song_path(&self) -> Option<PathBuf> {
+        let name = self.saved.session.song.as_ref()?;
+        self.song
+            .as_ref()
+            .and(self.song_file(name))
+            .map(|(file, _)| file.path.clone())
+    }
+
+    /// Opens the current song in the built-in editor
+    pub fn open_editor(&mut self) {
+        let name = match &self.saved.session.song {
+            Some(name) => name,
+            None => return,
+        };
+        if let Some((file, _)) = self.song.as_ref().and(self.song_file(name)) {
+            let editor = Editor::open(&file.path, file.file_contents(), file.converted_from());
+            self.editor = Some(editor);
+            self.state = AppState::Editing;
+        }
+    }
+
+    /// Closes the built-in editor, reloading the song if it was saved. Unsaved changes
+    /// keep it open the first time, closing it again throws them away.
+    pub fn close_editor(&mut self) {
+        if let Some(editor) = &mut self.editor {
+            if editor.modified && !editor.close_refused {
+                editor.close_refused = true;
+                self.notify(String::from(
+                    "Unsaved changes, save them or press Esc again to throw them away",
+                ));
+                return;
+            }
+        }
+        self.editor = None;
+        self.state = AppState::Default;
+        self.reload_song();
+    }
+
+    /// Shows the chord statistics of the open song, or of every song in the open
+    /// playlist when `playlist` is set
+    pub fn open_stats(&mut self, playlist: bool) {
+        let folder = self.file_nav.current();
+        let stats = match (playlist, folder.playlist) {
+            (true, true) => {
+                let songs: Vec<Song> = folder
+                    .files
+                    .iter()
+                    .filter_map(|file| match file {
+                        FileType::Song(name) => self.read_song(name).map(|(song, _)| song),
+                        _ => None,
+                    })
+                    .collect();
+                ChordStats::from_songs(&folder.name, &songs)
+            }
+            _ => match &self.song {
+                Some(song) => ChordStats::from_songs(&song.title, Some(song)),
+                None => return,
+            },
+        };
+        self.stats_playlist = playlist && folder.playlist;
+        self.stats = Some(stats);
+        self.state = AppState::Stats;
+    }
+
+    /// Checks the open song for problems and shows them
+    pub fn open_lints(&mut self) {
+        if let Some(path) = self.song_path() {
+            let contents = encoding::read_to_string(&path).unwrap_or_default().text;
+            self.lints = lint::lint(&contents);
+            self.state = AppState::Linting;
+        }
+    }
+
+    /// Fixes the problems in the open song that can be fixed safely
+    pub fn fix_lints(&mut self) -> io::Result<()> {
+        if let Some(path) = self.song_path() {
+            let contents = encoding::read_to_string(&path)?.text;
+            let fixed = lint::fix(&contents);
+            if fixed != contents {
+                fs::write(&path, &fixed)?;
+                self.reload_song();
             }
+            self.lints = lint::lint(&fixed);
         }
+        Ok(())
+    }
+
+    /// Opens the metadata popup for the current song
+    pub fn open_metadata(&mut self) {
+        let name = match &self.saved.session.song {
+            Some(name) => name,
+            None => return,
+        };
+        if let Some((file, _)) = self.song.as_ref().and(self.song_file(name)) {
+            self.metadata = Some(MetadataForm::open(&file.path, file.file_contents()));
+            self.state = AppState::EditingMetadata;
+        }
+    }
+
+    /// Writes the changes made in the metadata popup to the song file, in the encoding it
+    /// was in, and closes it. The popup stays open if the changes can't be written.
+    pub fn save_metadata(&mut self) -> io::Result<()> {
+        if let Some(form) = &self.metadata {
+            let decoded = encoding::read_to_string(&form.path)?;
+            let updated = form.apply(&decoded.text);
+            if updated != decoded.text {
+                encoding::write(&form.path, &updated, decoded.converted_from)?;
+                self.reload_song();
+            }
+        }
+        self.metadata = None;
+        self.state = AppState::Default;
+        Ok(())
+    }
+
+    /// Reads the open song from disk again, e.g. after it was edited
+    pub fn reload_song(&mut self) {
+        let mut name = match &self.saved.session.song {
+            Some(name) => name.clone(),
+            None => return,
+        };
+        if let Some((path, part)) = self
+            .song
+            .as_ref()
+            .and(self.song_file(&name))
+            .map(|(file, _)| (file.path.clone(), file.part))
+        {
+            self.files
+                .values_mut()
+                .filter(|file| file.path == path)
+                .for_each(LazyFile::reload);
+            // The edit may have changed the title the song is known by
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+            let new_name = self
+                .files
+                .values()
+                .find(|file| file.path == path)
+                .and_then(|file| {
+                    Song::get_names(file.file_contents(), &file_name)
+                        .into_iter()
+                        .nth(part)
+                })
+                .unwrap_or_else(|| file_name.to_string());
+            if new_name != name && self.files.contains_key(&FileType::Song(name.clone())) {
+                self.rename_song(&name, &new_name);
+                name = new_name;
+            }
+        }
+        self.load_song(&name);
+    }
+
+    /// Updates every reference to the song called `old` after its title changed
+    fn rename_song(&mut self, old: &str, new: &str) {
+        let (old, new) = (
+            FileType::Song(old.to_string()),
+            FileType::Song(new.to_string()),
+        );
+        if let Some(file) = self.files.remove(&old) {
+            self.files.insert(new.clone(), file);
+        }
+        for folder in self
+            .file_nav
+            .0
+            .iter_mut()
+            .chain(self.search_nav.0.iter_mut())
+        {
+            for file in folder.files.iter_mut().filter(|file| **file == old) {
+                *file = new.clone();
+            }
+        }
+        if let Some(transposition) = self.saved.transpositions.remove(&old.name()) {
+            self.saved.transpositions.insert(new.name(), transposition);
+        }
+        if let Some(scroll) = self.saved.scroll.remove(&old.name()) {
+            self.saved.scroll.insert(new.name(), scroll);
+        }
+        for favorite in self.saved.favorites.iter_mut() {
+            if *favorite == old.name() {
+                *favorite = new.name();
+            }
+        }
+        for tab in self.saved.session.tabs.iter_mut() {
+            if *tab == old.name() {
+                *tab = new.name();
+            }
+        }
+    }
+
+    /// Transposes the open song and remembers the transposition for the next time it's
+    /// opened
+    pub fn transpose(&mut self, semitones: i32) {
+        let song = match &mut self.song {
+            Some(song) if song.key.is_some() => song,
+            _ => return,
+        };
+        song.change_key(semitones);
+        if let Some(name) = &self.saved.session.song {
+            let transposition = self.saved.transpositions.entry(name.clone()).or_insert(0);
+            *transposition = (*transposition + semitones).rem_euclid(12);
+            if *transposition == 0 {
+                self.saved.transpositions.remove(name);
+            }
+        }
+    }
+
+    /// Shows the open song in `key`, and remembers it for the next time it's opened
+    pub fn transpose_to(&mut self, key: PitchClass) {
+        let name = match &self.saved.session.song {
+            Some(name) => name.clone(),
+            None => return,
+        };
+        // The key the song is in when it's opened without being transposed
+        let original = match &self.song {
+            Some(Song {
+                key: Some(current),
+                offset,
+                ..
+            }) => current.into_u8() as i32 - offset,
+            _ => return,
+        };
+        if let Some((mut song, error)) = self.read_song_in(&name, Some(key)) {
+            let offset = match song.key {
+                Some(key) => (key.into_u8() as i32 - original).rem_euclid(12),
+                None => 0,
+            };
+            song.offset = offset;
+            match offset {
+                0 => self.saved.transpositions.remove(&name),
+                _ => self.saved.transpositions.insert(name, offset),
+            };
+            self.song = Some(song);
+            self.song_error = error;
+        }
+    }
+
+    /// Puts the open song back in the key it was written in
+    pub fn reset_transposition(&mut self) {
+        if let Some(offset) = self.song.as_ref().map(|song| song.offset) {
+            self.transpose(-offset)
+        }
+    }
+
+    /// The highlighted song if it isn't the open song, to preview it before opening it
+    pub fn preview(&self) -> Option<Ref<'_, Song>> {
+        if !self.config.preview || self.config.auto_select_song {
+            return None;
+        }
+        if !matches!(self.state, AppState::Default | AppState::Searching) {
+            return None;
+        }
+        let name = match self.get_nav().selected() {
+            Some(FileType::Song(name)) if self.saved.session.song.as_ref() != Some(name) => name,
+            _ => return None,
+        };
+        let cached = matches!(&*self.previewed.borrow(), Some((previewed, _)) if previewed == name);
+        if !cached {
+            let song = self.read_song(name).map(|(song, _)| song);
+            *self.previewed.borrow_mut() = Some((name.clone(), song));
+        }
+        Ref::filter_map(self.previewed.borrow(), |previewed| {
+            previewed.as_ref()?.1.as_ref()
+        })
+        .ok()
     }
 
     pub fn load_selected_song(&mut self) {
@@ -94,82 +1112,384 @@ impl<'a> App {
         }
     }
 
-    pub fn search(&mut self) {
-        let input = &self.input.to_lowercase();
-        let mut results: Vec<FileType> = self
-            .files
+    /// Fills the file list and search results from the index
+    fn load_files(&mut self) {
+        self.files = self
+            .index
             .iter()
-            .filter_map(|(k, v)| {
-                if k.name().to_lowercase().contains(input) | v.to_lowercase().contains(input) {
-                    Some(k.clone())
-                } else {
-                    None
-                }
+            .flat_map(|(path, files)| {
+                files
+                    .iter()
+                    .enumerate()
+                    .map(move |(part, file)| (file.clone(), LazyFile::new(path, part)))
             })
             .collect();
-        results.sort_by_key(FileType::name);
-        self.get_nav_mut().0 = vec![Folder {
-            name: String::from("Search"),
-            files: results,
-            state: ListState::default(),
-        }];
+        let mut results: Vec<FileType> = self.files.keys().cloned().collect();
+        self.collation.sort(&mut results);
+        self.search_nav = FileNavigator(vec![Folder::search_results(results)]);
+    }
+
+    /// Fuzzy matches the search input against every name in the library, best matches
+    /// first, without reading any files. Songs can be filtered by their fields with
+    /// `name:value`, see `Query`. Input starting with `TEXT_SEARCH_PREFIX` only searches
+    /// the text of the songs.
+    pub fn search(&mut self) {
+        self.search_matches.clear();
+        self.search_snippets.clear();
+        self.search_error = None;
+        if self.regex_search {
+            return self.search_regex();
+        }
+        if let Some(query) = self.input.strip_prefix(TEXT_SEARCH_PREFIX) {
+            let query = collation::fold(query);
+            return self.search_text(&query);
+        }
+        let query = Query::parse(&self.input);
+        let input = &collation::fold(&query.text);
+        let mut results: Vec<(i64, FileType)> = vec![];
+        for (file, lazy) in self.files.iter() {
+            if query.starred() && !self.is_favorite(file) {
+                continue;
+            }
+            if !query.fields.is_empty()
+                && !(matches!(file, FileType::Song(_))
+                    && query.matches(self.index.fields(&lazy.path, lazy.part)))
+            {
+                continue;
+            }
+            if let Some(found) = fuzzy::score(input, &file.name()) {
+                self.search_matches.insert(file.clone(), found.positions);
+                results.push((found.score, file.clone()));
+            }
+        }
+        let collation = &self.collation;
+        results.sort_by(|(a, file_a), (b, file_b)| {
+            b.cmp(a)
+                .then_with(|| collation.compare(&file_a.name(), &file_b.name()))
+        });
+        let results = results.into_iter().map(|(_, file)| file).collect();
+        self.search_nav.0 = vec![Folder::search_results(results)];
+    }
+
+    /// Finds the names matching the search input as a regular expression, ignoring case,
+    /// or the songs with a matching line if it starts with `TEXT_SEARCH_PREFIX`
+    fn search_regex(&mut self) {
+        let (text, pattern) = match self.input.strip_prefix(TEXT_SEARCH_PREFIX) {
+            Some(pattern) => (true, pattern),
+            None => (false, self.input.as_ref()),
+        };
+        let regex = match RegexBuilder::new(pattern).case_insensitive(true).build() {
+            Ok(regex) => regex,
+            Err(e) => {
+                self.search_error = Some(e.to_string());
+                self.search_nav.0 = vec![Folder::search_results(vec![])];
+                return;
+            }
+        };
+        let mut results: Vec<FileType> = vec![];
+        for (file, lazy) in self.files.iter() {
+            if text {
+                let line = lazy.contents().lines().find(|line| regex.is_match(line));
+                if let Some(line) = line {
+                    self.search_snippets
+                        .insert(file.clone(), line.trim().to_string());
+                    results.push(file.clone());
+                }
+            } else if let Some(found) = regex.find(&file.name()) {
+                let name = file.name();
+                let start = name[..found.start()].chars().count();
+                let positions = (start..start + found.as_str().chars().count()).collect();
+                self.search_matches.insert(file.clone(), positions);
+                results.push(file.clone());
+            }
+        }
+        self.collation.sort(&mut results);
+        self.search_nav.0 = vec![Folder::search_results(results)];
+    }
+
+    /// Finds the songs with a line containing `query`, which should be folded with
+    /// `collation::fold`, to find songs by their lyrics or chords
+    fn search_text(&mut self, query: &str) {
+        let mut results: Vec<FileType> = vec![];
+        for (file, lazy) in self.files.iter() {
+            if query.is_empty() {
+                results.push(file.clone());
+            } else if let Some(line) = matching_line(lazy.contents(), query) {
+                self.search_snippets.insert(file.clone(), line);
+                results.push(file.clone());
+            }
+        }
+        self.collation.sort(&mut results);
+        self.search_nav.0 = vec![Folder::search_results(results)];
+    }
+
+    /// Rereads the library after files were changed, keeping the open folders where
+    /// they still exist
+    pub fn refresh(&mut self) {
+        self.index.refresh(&self.config);
+        self.reload();
+    }
+
+    /// Rereads the open song if its file is one of `paths` after files were changed
+    /// outside of gpro. The library itself is rescanned in the background, and swapped
+    /// in with `set_index`.
+    pub fn library_changed(&mut self, paths: &[PathBuf]) {
+        if let Some(path) = self.song_path() {
+            if path.is_file() && paths.contains(&path) {
+                self.reload_song();
+            }
+        }
+        self.indexing = true;
     }
 
-    fn create_filemap(path: &Path) -> HashMap<FileType, String> {
-        App::get_direntries(path)
+    /// Whether no songs were found in any of the library folders
+    pub fn library_is_empty(&self) -> bool {
+        self.index
             .iter()
-            .filter_map(|file| {
-                let path = file.path();
-                if path.is_dir() {
-                    Some((FileType::Folder(path), String::new()))
-                } else {
-                    let extension = path.extension().unwrap_or_default().to_str().unwrap();
-                    if extension == "txt" {
-                        let filestring = fs::read_to_string(path).unwrap_or_default();
-                        Some((
-                            FileType::Song(
-                                Song::get_name(&filestring).unwrap_or_else(|| {
-                                    file.file_name().to_str().unwrap().to_string()
-                                }),
-                            ),
-                            filestring,
-                        ))
-                    } else if extension == "lst" {
-                        let filestring = fs::read_to_string(path).unwrap_or_default();
-                        Some((
-                            FileType::Playlist(
-                                Playlist::get_name(&filestring).unwrap_or_else(|| {
-                                    file.file_name().to_str().unwrap().to_string()
-                                }),
-                            ),
-                            filestring,
-                        ))
-                    } else {
-                        None
-                    }
+            .flat_map(|(_, files)| files)
+            .all(|file| !matches!(file, FileType::Song(_)))
+    }
+
+    /// Switches to the index scanned in the background, opening the song of the last
+    /// session if it wasn't in the index yet
+    pub fn set_index(&mut self, index: Index) {
+        self.index = index;
+        self.indexing = false;
+        self.reload();
+        if self.song.is_none() {
+            if let Some(name) = self.saved.session.song.clone() {
+                self.load_song(&name);
+            }
+        }
+    }
+
+    /// Updates the file list, search results and open folders from the index
+    fn reload(&mut self) {
+        // The previewed song may have changed on disk
+        *self.previewed.get_mut() = None;
+        self.load_files();
+        if !self.input.is_empty() {
+            self.search();
+        }
+        let (index, collation) = (&self.index, &self.collation);
+        let favorites = &self.saved.favorites;
+        let folders = &mut self.file_nav.0;
+        // Close folders that were moved or deleted, along with everything opened from them
+        if let Some(missing) = folders
+            .iter()
+            .position(|folder| folder.path.as_ref().is_some_and(|path| !path.is_dir()))
+        {
+            folders.truncate(missing.max(1));
+        }
+        for folder in folders.iter_mut() {
+            let mut updated = match &folder.path {
+                Some(path) => Folder::from_path(path, index, collation),
+                None => match &folder.view {
+                    Some(view) => Folder::view(view.clone(), index, favorites, collation),
+                    None => continue,
+                },
+            };
+            let selected = folder
+                .state
+                .selected()
+                .map(|i| i.min(updated.files.len().saturating_sub(1)));
+            updated.state.select(selected);
+            *folder = updated;
+        }
+        self.sort_folders();
+    }
+
+    /// Puts the songs of the open folders in the order of the sort mode, keeping the
+    /// selected file selected. Playlists keep their own order.
+    pub fn sort_folders(&mut self) {
+        let mut folders = std::mem::take(&mut self.file_nav.0);
+        for folder in folders
+            .iter_mut()
+            .filter(|folder| folder.path.is_some() || folder.view.is_some())
+        {
+            let selected = folder.selected().cloned();
+            self.sort_files(&mut folder.files);
+            if selected.is_some() {
+                let position = folder
+                    .files
+                    .iter()
+                    .position(|file| Some(file) == selected.as_ref());
+                folder.state.select(position);
+            }
+        }
+        self.file_nav.0 = folders;
+    }
+
+    fn sort_files(&self, files: &mut [FileType]) {
+        self.collation.sort(files);
+        // Everything but songs stays first, sorted by name
+        let not_song = |file: &FileType| !matches!(file, FileType::Song(_));
+        // Taken from the index, so sorting doesn't have to read every song
+        let field = |file: &FileType, name: &str| match file {
+            FileType::Song(_) => {
+                let lazy = self.files.get(file)?;
+                let fields = self.index.fields(&lazy.path, lazy.part);
+                fields
+                    .iter()
+                    .find(|(field, _)| field == name)
+                    .map(|(_, value)| value.clone())
+            }
+            _ => None,
+        };
+        match self.sort {
+            SortMode::Title => (),
+            SortMode::Artist => {
+                let mut artists: Vec<(Option<String>, FileType)> = files
+                    .iter()
+                    .map(|file| (field(file, "artist"), file.clone()))
+                    .collect();
+                // Songs without an artist come last
+                artists.sort_by(|(a, file_a), (b, file_b)| {
+                    not_song(file_b).cmp(&not_song(file_a)).then(match (a, b) {
+                        (Some(a), Some(b)) => self.collation.compare(a, b),
+                        (a, b) => a.is_none().cmp(&b.is_none()),
+                    })
+                });
+                for (file, (_, sorted)) in files.iter_mut().zip(artists) {
+                    *file = sorted;
                 }
-            })
-            .collect()
+            }
+            SortMode::Modified => files.sort_by_cached_key(|file| {
+                let path = match file {
+                    FileType::Folder(path) => Some(path.clone()),
+                    _ => self.files.get(file).map(|file| file.path.clone()),
+                };
+                let modified = path.and_then(|path| fs::metadata(path).ok()?.modified().ok());
+                (!not_song(file), Reverse(modified))
+            }),
+            SortMode::Opened => files.sort_by_cached_key(|file| {
+                let recent = self
+                    .saved
+                    .recent
+                    .iter()
+                    .position(|name| *name == file.name());
+                (!not_song(file), recent.is_none(), recent)
+            }),
+            SortMode::Key => files.sort_by_cached_key(|file| {
+                let key = field(file, "key")
+                    .and_then(|key| parse_key(&key))
+                    .map(|(key, mode)| (key.into_u8(), mode != Mode::Major));
+                (!not_song(file), key.is_none(), key)
+            }),
+        }
     }
 
-    // Gets all DirEntry's that are not a folder
-    fn get_direntries(path: &Path) -> Vec<DirEntry> {
-        let dir = match fs::read_dir(path) {
-            Ok(d) => d,
-            Err(_) => return vec![],
+    /// Opens a list of songs from the whole library, or closes it again when it's open
+    pub fn toggle_view(&mut self, view: View) {
+        let open = self.file_nav.current().view.clone();
+        while self.file_nav.current().view.is_some() && self.file_nav.0.len() > 1 {
+            self.file_nav.path_back();
+        }
+        if open != Some(view.clone()) {
+            self.open_view(view);
+        }
+    }
+
+    fn open_view(&mut self, view: View) {
+        let folder = Folder::view(view, &self.index, &self.saved.favorites, &self.collation);
+        self.file_nav.open_folder(folder);
+        self.sort_folders();
+    }
+
+    /// Stars the selected song, or unstars it if it was starred
+    pub fn toggle_favorite(&mut self) {
+        let name = match self.get_nav().selected() {
+            Some(FileType::Song(name)) => name.clone(),
+            _ => return,
         };
-        dir.flat_map(|dir| {
-            let dir = dir.unwrap();
-            let path = dir.path();
-            if path.is_dir() {
-                let mut dirs = App::get_direntries(&path);
-                dirs.push(dir);
-                dirs
-            } else {
-                vec![dir]
+        let favorites = &mut self.saved.favorites;
+        match favorites.iter().position(|favorite| *favorite == name) {
+            Some(i) => {
+                favorites.remove(i);
             }
-        })
-        .collect()
+            None => favorites.push(name),
+        }
+        let favorites = &self.saved.favorites;
+        for folder in self.file_nav.0.iter_mut() {
+            if folder.view == Some(View::Favorites) {
+                let mut updated =
+                    Folder::view(View::Favorites, &self.index, favorites, &self.collation);
+                let selected = folder
+                    .state
+                    .selected()
+                    .map(|i| i.min(updated.files.len().saturating_sub(1)));
+                updated.state.select(selected);
+                *folder = updated;
+            }
+        }
+    }
+
+    pub fn is_favorite(&self, file: &FileType) -> bool {
+        matches!(file, FileType::Song(_)) && self.saved.favorites.contains(&file.name())
+    }
+
+    /// Asks for the input needed to perform `action` on the selected file
+    pub fn open_prompt(&mut self, action: FileAction) {
+        let path = match self.get_nav().selected() {
+            Some(FileType::Folder(path)) => path.clone(),
+            Some(file) => match self.files.get(file) {
+                Some(file) => file.path.clone(),
+                None => return,
+            },
+            None => return,
+        };
+        let root = self.config.root_of(&path);
+        self.prompt = Some(Prompt::new(action, &path, root));
+        self.state = AppState::Prompting;
+    }
+
+    /// Asks for the title of a new playlist with the songs opened this session, saved in
+    /// the folder that's open or else the library folder
+    pub fn save_queue(&mut self) {
+        if self.queue.is_empty() {
+            self.notify(String::from("No songs have been opened yet"));
+            return;
+        }
+        let folder = self.file_nav.current().path.clone();
+        let path = folder.unwrap_or_else(|| self.config.path.clone());
+        let root = self.config.root_of(&path);
+        let mut prompt = Prompt::new(FileAction::SaveQueue, &path, root);
+        prompt.songs = self.queue.clone();
+        self.prompt = Some(prompt);
+        self.state = AppState::Prompting;
+    }
+
+    /// Performs the pending file operation and closes the prompt. When it fails the
+    /// prompt stays open showing the error.
+    pub fn confirm_prompt(&mut self) {
+        if let Some(prompt) = &mut self.prompt {
+            match prompt.apply(self.config.root_of(&prompt.path)) {
+                Ok(undo) => self.undo.extend(undo),
+                Err(e) => {
+                    prompt.error = Some(e.to_string());
+                    return;
+                }
+            }
+        }
+        self.prompt = None;
+        self.state = AppState::Default;
+        self.refresh();
+    }
+
+    /// Reverts the most recent file operation. If that fails, e.g. because another file
+    /// took its place, it stays on the undo stack.
+    pub fn undo(&mut self) -> io::Result<()> {
+        if let Some(undo) = self.undo.last() {
+            undo.apply()?;
+            self.undo.pop();
+            self.refresh();
+        }
+        Ok(())
+    }
+
+    pub fn cancel_prompt(&mut self) {
+        self.prompt = None;
+        self.state = AppState::Default;
     }
 
     pub fn get_nav(&self) -> &FileNavigator {
@@ -187,29 +1507,146 @@ impl<'a> App {
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Hash)]
+/// Seconds per line that scroll through `song` in its `{duration}`, like `3:45`, or at
+/// two bars of four beats per line at its `{tempo}`
+fn song_scroll_interval(song: &Song) -> Option<f64> {
+    let lines = song.content.len().max(1) as f64;
+    let duration = song.metadata.duration.as_deref().and_then(|duration| {
+        let mut seconds = 0.0;
+        for part in duration.trim().split(':') {
+            seconds = seconds * 60.0 + part.trim().parse::<f64>().ok()?;
+        }
+        Some(seconds)
+    });
+    match duration {
+        Some(duration) if duration > 0.0 => Some(duration / lines),
+        _ => {
+            let tempo: f64 = song.metadata.tempo.as_deref()?.trim().parse().ok()?;
+            (tempo > 0.0).then(|| 8.0 * 60.0 / tempo)
+        }
+    }
+}
+
+/// First line of `text` that contains `query`, which should be folded with
+/// `collation::fold`, trimmed
+fn matching_line(text: &str, query: &str) -> Option<String> {
+    text.lines()
+        .find(|line| collation::fold(line).contains(query))
+        .map(|line| line.trim().to_string())
+}
+
+/// Playlist played through in order, see `App::toggle_setlist`
+pub struct Setlist {
+    pub title: String,
+    pub songs: Vec<String>,
+    /// Song of the setlist that is open
+    pub position: usize,
+}
+
+impl Setlist {
+    /// Song after the open one, if there is one
+    pub fn next(&self) -> Option<&str> {
+        self.songs.get(self.position + 1).map(String::as_str)
+    }
+}
+
+/// Song shown next to the open song in split view
+pub struct SplitPane {
+    pub name: String,
+    pub song: Song,
+    pub scroll: usize,
+}
+
+/// Contents of a library file, only read from disk when they are first needed
+pub struct LazyFile {
+    path: PathBuf,
+    /// Which of the songs in the file this is, see `Song::split`
+    part: usize,
+    /// What was read from the file, with the error if it couldn't be read
+    contents: OnceCell<(Decoded, Option<String>)>,
+}
+
+impl LazyFile {
+    fn new(path: &Path, part: usize) -> Self {
+        LazyFile {
+            path: path.to_path_buf(),
+            part,
+            contents: OnceCell::new(),
+        }
+    }
+
+    fn read(&self) -> &(Decoded, Option<String>) {
+        self.contents
+            .get_or_init(|| match encoding::read_to_string(&self.path) {
+                Ok(decoded) => (decoded, None),
+                Err(e) => (Decoded::default(), Some(e.to_string())),
+            })
+    }
+
+    fn decoded(&self) -> &Decoded {
+        &self.read().0
+    }
+
+    /// Why the file couldn't be read, its contents are empty then
+    pub fn error(&self) -> Option<&str> {
+        self.read().1.as_deref()
+    }
+
+    /// Text of this song, without the other songs in the same file
+    pub fn contents(&self) -> &str {
+        Song::split(self.file_contents())
+            .get(self.part)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Text of the whole file
+    pub fn file_contents(&self) -> &str {
+        &self.decoded().text
+    }
+
+    pub fn converted_from(&self) -> Option<&'static str> {
+        self.decoded().converted_from
+    }
+
+    fn reload(&mut self) {
+        self.contents = OnceCell::new();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum FileType {
     Folder(PathBuf),
     Song(String),
     Playlist(String),
+    /// Tag from `{tag}` directives, listed in the tag view
+    Tag(String),
 }
 
 impl FileType {
-    pub fn from_dir_entry(entry: DirEntry) -> Result<FileType, &'static str> {
+    /// Reads the entries for a file, a song file can hold several songs
+    pub fn from_dir_entry(entry: DirEntry) -> Result<Vec<FileType>, &'static str> {
         let path = entry.path();
         let name = path.file_name().unwrap().to_str().unwrap();
         if path.is_dir() {
-            Ok(FileType::Folder(path))
+            Ok(vec![FileType::Folder(path)])
         } else if name.ends_with(".txt") {
-            Ok(FileType::Song(
-                Song::get_name(&fs::read_to_string(&path).unwrap())
-                    .unwrap_or_else(|| name.to_string()),
-            ))
-        } else if name.ends_with(".lst") {
-            Ok(FileType::Playlist(
-                Playlist::get_name(&fs::read_to_string(&path).unwrap())
+            Ok(Song::get_names(
+                &encoding::read_to_string(&path).unwrap_or_default().text,
+                name,
+            )
+            .into_iter()
+            .map(FileType::Song)
+            .collect())
+        } else if path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| Playlist::EXTENSIONS.contains(&ext))
+        {
+            Ok(vec![FileType::Playlist(
+                Playlist::get_name(&encoding::read_to_string(&path).unwrap_or_default().text)
                     .unwrap_or_else(|| name.to_string()),
-            ))
+            )])
         } else {
             Err("Unable to parse DirEntry to File")
         }
@@ -217,9 +1654,16 @@ impl FileType {
 
     pub fn name(&self) -> String {
         match self {
-            FileType::Folder(path) => path.file_name().unwrap().to_str().unwrap().to_owned(),
+            // Library roots like `.` have no file name of their own
+            FileType::Folder(path) => path
+                .file_name()
+                .unwrap_or(path.as_os_str())
+                .to_str()
+                .unwrap()
+                .to_owned(),
             FileType::Song(name) => name.to_owned(),
             FileType::Playlist(name) => name.to_owned(),
+            FileType::Tag(tag) => tag.to_owned(),
         }
     }
 }
@@ -227,25 +1671,112 @@ impl FileType {
 #[derive(Default)]
 pub struct Folder {
     pub name: String,
+    /// Folder on disk this was read from, playlists and search results have none
+    pub path: Option<PathBuf>,
     pub state: ListState,
     pub files: Vec<FileType>,
+    /// Which songs of the whole library this lists regardless of their folder, if any
+    pub view: Option<View>,
+    /// Whether this lists the songs of a playlist, in order
+    pub playlist: bool,
+}
+
+/// Lists of songs from the whole library
+#[derive(Clone, PartialEq)]
+pub enum View {
+    AllSongs,
+    /// Starred songs
+    Favorites,
+    /// Every tag used in the library
+    Tags,
+    /// Songs with a tag
+    Tagged(String),
 }
 
 impl Folder {
-    fn from_path(path: &Path) -> Folder {
+    fn from_path(path: &Path, index: &Index, collation: &Collation) -> Folder {
         let name = path
             .file_name()
-            .unwrap_or_else(|| path.as_os_str())
+            .unwrap_or(path.as_os_str())
             .to_str()
             .unwrap()
             .to_string();
+        // A folder that can't be read is shown empty, the song pane explains why
         let mut files: Vec<FileType> = fs::read_dir(path)
-            .unwrap()
-            .filter_map(|dir| FileType::from_dir_entry(dir.unwrap()).ok())
+            .into_iter()
+            .flatten()
+            .flatten()
+            .flat_map(|dir| {
+                if index.is_ignored(&dir.path()) {
+                    return vec![];
+                }
+                match index.get(&dir.path()) {
+                    Some(files) => files.to_vec(),
+                    None => FileType::from_dir_entry(dir).unwrap_or_default(),
+                }
+            })
             .collect();
-        files.sort_by_key(FileType::name);
+        collation.sort(&mut files);
         Folder {
             name,
+            path: Some(path.to_path_buf()),
+            files,
+            ..Default::default()
+        }
+    }
+
+    /// Top of the library: the song folder, or a folder for each one when there are more
+    fn library(config: &Config, index: &Index, collation: &Collation) -> Folder {
+        if config.libraries.is_empty() {
+            return Folder::from_path(&config.path, index, collation);
+        }
+        Folder {
+            name: String::from("Library"),
+            files: config.roots().into_iter().map(FileType::Folder).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn view(view: View, index: &Index, favorites: &[String], collation: &Collation) -> Folder {
+        let songs = index
+            .iter()
+            .flat_map(|(_, files)| files.iter())
+            .filter(|file| matches!(file, FileType::Song(_)));
+        let (name, mut files): (String, Vec<FileType>) = match &view {
+            View::AllSongs => (String::from("All songs"), songs.cloned().collect()),
+            View::Favorites => (
+                String::from("Favorites"),
+                songs
+                    .filter(|file| favorites.contains(&file.name()))
+                    .cloned()
+                    .collect(),
+            ),
+            View::Tags => (
+                String::from("Tags"),
+                index
+                    .tags()
+                    .into_keys()
+                    .map(|tag| FileType::Tag(tag.to_string()))
+                    .collect(),
+            ),
+            View::Tagged(tag) => (
+                format!("Tagged {}", tag),
+                index.tags().remove(tag.as_str()).unwrap_or_default(),
+            ),
+        };
+        collation.sort(&mut files);
+        files.dedup();
+        Folder {
+            name,
+            files,
+            view: Some(view),
+            ..Default::default()
+        }
+    }
+
+    fn search_results(files: Vec<FileType>) -> Folder {
+        Folder {
+            name: String::from("Search"),
             files,
             ..Default::default()
         }
@@ -255,6 +1786,7 @@ impl Folder {
         Folder {
             name: playlist.title,
             files: playlist.songs,
+            playlist: true,
             ..Default::default()
         }
     }
@@ -297,16 +1829,12 @@ impl Folder {
 pub struct FileNavigator(Vec<Folder>);
 
 impl FileNavigator {
-    fn from_path(path: &Path) -> FileNavigator {
-        FileNavigator(vec![Folder::from_path(path)])
-    }
-
     fn open_playlist(&mut self, playlist: Playlist) {
         self.0.push(Folder::from_playlist(playlist));
     }
 
-    fn open_path(&mut self, path: &Path) {
-        self.0.push(Folder::from_path(path))
+    fn open_folder(&mut self, folder: Folder) {
+        self.0.push(folder)
     }
 
     pub fn path_back(&mut self) {
@@ -0,0 +1,531 @@
+use crate::parser::{ChordDefinition, Song};
+use lazy_static::lazy_static;
+use regex::Regex;
+use rust_music_theory::note::PitchClass;
+use std::{collections::HashMap, sync::Mutex};
+
+lazy_static! {
+    static ref RE_ROOT: Regex = Regex::new(r"^([A-G][b#]?)([^/]*)").unwrap();
+    /// Fingerings worked out by `find_shape` for a chord in a tuning, since the diagrams
+    /// are drawn again on every frame
+    static ref FOUND_SHAPES: Mutex<FoundShapes> = Mutex::default();
+}
+
+/// Open position guitar chords, from the low E string up
+const OPEN_SHAPES: [(&str, &str); 30] = [
+    ("C", "x 3 2 0 1 0"),
+    ("C7", "x 3 2 3 1 0"),
+    ("Cmaj7", "x 3 2 0 0 0"),
+    ("Cadd9", "x 3 2 0 3 0"),
+    ("D", "x x 0 2 3 2"),
+    ("D7", "x x 0 2 1 2"),
+    ("Dmaj7", "x x 0 2 2 2"),
+    ("Dm", "x x 0 2 3 1"),
+    ("Dm7", "x x 0 2 1 1"),
+    ("Dsus2", "x x 0 2 3 0"),
+    ("Dsus4", "x x 0 2 3 3"),
+    ("E", "0 2 2 1 0 0"),
+    ("E7", "0 2 0 1 0 0"),
+    ("Em", "0 2 2 0 0 0"),
+    ("Em7", "0 2 2 0 3 0"),
+    ("Esus4", "0 2 2 2 0 0"),
+    ("Fmaj7", "x x 3 2 1 0"),
+    ("G", "3 2 0 0 0 3"),
+    ("G7", "3 2 0 0 0 1"),
+    ("Gmaj7", "3 2 0 0 0 2"),
+    ("A", "x 0 2 2 2 0"),
+    ("A7", "x 0 2 0 2 0"),
+    ("Amaj7", "x 0 2 1 2 0"),
+    ("Am", "x 0 2 2 1 0"),
+    ("Am7", "x 0 2 0 1 0"),
+    ("Asus2", "x 0 2 2 0 0"),
+    ("Asus4", "x 0 2 2 3 0"),
+    ("B7", "x 2 1 2 0 2"),
+    ("Bm", "x 2 4 4 3 2"),
+    ("Bb", "x 1 3 3 3 1"),
+];
+
+/// Movable shapes in the first position, with the root they have there. Moved up the
+/// neck their open strings become a barre.
+const GUITAR_SHAPES: [(&str, u8, &str); 13] = [
+    // Root on the low E string
+    ("", 4, "0 2 2 1 0 0"),
+    ("m", 4, "0 2 2 0 0 0"),
+    ("7", 4, "0 2 0 1 0 0"),
+    ("m7", 4, "0 2 0 0 0 0"),
+    ("maj7", 4, "0 x 1 1 0 x"),
+    ("sus4", 4, "0 2 2 2 0 0"),
+    // Root on the A string
+    ("", 9, "x 0 2 2 2 0"),
+    ("m", 9, "x 0 2 2 1 0"),
+    ("7", 9, "x 0 2 0 2 0"),
+    ("m7", 9, "x 0 2 0 1 0"),
+    ("maj7", 9, "x 0 2 1 2 0"),
+    ("sus4", 9, "x 0 2 2 3 0"),
+    ("sus2", 9, "x 0 2 2 0 0"),
+];
+
+/// Open position ukulele chords in standard GCEA tuning, from the G string on
+const UKULELE_OPEN_SHAPES: [(&str, &str); 27] = [
+    ("C", "0 0 0 3"),
+    ("C7", "0 0 0 1"),
+    ("Cmaj7", "0 0 0 2"),
+    ("Cm", "0 3 3 3"),
+    ("Csus4", "0 0 1 3"),
+    ("D", "2 2 2 0"),
+    ("D7", "2 2 2 3"),
+    ("Dm", "2 2 1 0"),
+    ("Dm7", "2 2 1 3"),
+    ("Dsus2", "2 2 0 0"),
+    ("E", "1 4 0 2"),
+    ("E7", "1 2 0 2"),
+    ("Em", "0 4 3 2"),
+    ("Em7", "0 2 0 2"),
+    ("F", "2 0 1 0"),
+    ("F7", "2 3 1 0"),
+    ("G", "0 2 3 2"),
+    ("G7", "0 2 1 2"),
+    ("Gm", "0 2 3 1"),
+    ("Gmaj7", "0 2 2 2"),
+    ("A", "2 1 0 0"),
+    ("A7", "0 1 0 0"),
+    ("Am", "2 0 0 0"),
+    ("Am7", "0 0 0 0"),
+    ("Bb", "3 2 1 1"),
+    ("Bm", "4 2 2 2"),
+    ("B7", "2 3 2 2"),
+];
+
+const UKULELE_SHAPES: [(&str, u8, &str); 12] = [
+    // Root on the A string
+    ("", 9, "2 1 0 0"),
+    ("m", 9, "2 0 0 0"),
+    ("7", 9, "0 1 0 0"),
+    ("m7", 9, "0 0 0 0"),
+    ("maj7", 9, "1 1 0 0"),
+    ("sus4", 9, "2 2 0 0"),
+    // Root on the G string
+    ("", 7, "0 2 3 2"),
+    ("m", 7, "0 2 3 1"),
+    ("7", 7, "0 2 1 2"),
+    ("maj7", 7, "0 2 2 2"),
+    ("sus4", 7, "0 2 3 3"),
+    ("sus2", 7, "0 2 3 0"),
+];
+
+/// Open position mandolin chords in standard GDAE tuning, from the G string on
+const MANDOLIN_OPEN_SHAPES: [(&str, &str); 14] = [
+    ("G", "0 0 2 3"),
+    ("G7", "0 0 2 1"),
+    ("C", "0 2 3 0"),
+    ("D", "2 0 0 2"),
+    ("D7", "2 0 3 2"),
+    ("Dm", "2 0 0 1"),
+    ("A", "2 2 4 0"),
+    ("A7", "2 2 4 3"),
+    ("Am", "2 2 3 0"),
+    ("E", "1 2 2 0"),
+    ("E7", "1 0 2 0"),
+    ("Em", "0 2 2 0"),
+    ("F", "5 3 0 1"),
+    ("Bm", "4 0 2 2"),
+];
+
+const MANDOLIN_SHAPES: [(&str, u8, &str); 8] = [
+    // Root on the G string
+    ("", 7, "0 0 2 3"),
+    ("m", 7, "0 0 1 3"),
+    ("7", 7, "0 0 2 1"),
+    ("m7", 7, "0 0 1 1"),
+    ("maj7", 7, "0 0 2 2"),
+    // Root on the D string
+    ("", 2, "2 0 0 2"),
+    ("m", 2, "2 0 0 1"),
+    ("7", 2, "2 0 3 2"),
+];
+
+/// Named tunings, as the notes of the strings from the lowest up
+const TUNINGS: [(&str, &str); 7] = [
+    ("standard", "E A D G B E"),
+    ("drop-d", "D A D G B E"),
+    ("double-drop-d", "D A D G B D"),
+    ("dadgad", "D A D G A D"),
+    ("open-d", "D A D F# A D"),
+    ("open-g", "D G D G B D"),
+    ("half-step-down", "Eb Ab Db Gb Bb Eb"),
+];
+
+/// Instrument to draw chord diagrams for
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum Instrument {
+    #[default]
+    Guitar,
+    Ukulele,
+    Mandolin,
+    /// Any instrument with frets in a custom tuning, as the pitch classes of the strings
+    /// from the lowest up. Its chord shapes are worked out rather than looked up.
+    Tuned(Vec<u8>),
+}
+
+impl Instrument {
+    /// Instrument for the name in `Config::instrument`, anything unknown is a guitar
+    pub fn from_name(name: Option<&str>) -> Self {
+        match name.map(str::to_lowercase).as_deref() {
+            Some("ukulele" | "uke") => Instrument::Ukulele,
+            Some("mandolin") => Instrument::Mandolin,
+            _ => Instrument::Guitar,
+        }
+    }
+
+    /// Instrument for `Config::instrument` and `Config::tuning`. The tuning is either
+    /// one of `TUNINGS`, like `drop-d`, or the notes of the strings like `D A D G A D`.
+    /// Standard tunings use the built-in shapes.
+    pub fn from_config(name: Option<&str>, tuning: Option<&str>) -> Self {
+        let instrument = Instrument::from_name(name);
+        let tuning = match tuning {
+            Some(tuning) => tuning,
+            None => return instrument,
+        };
+        let notes = TUNINGS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(tuning))
+            .map_or(tuning, |(_, notes)| notes);
+        let strings: Option<Vec<u8>> = notes
+            .split_whitespace()
+            .map(|note| PitchClass::from_str(note).map(|note| note.into_u8()))
+            .collect();
+        match strings {
+            Some(strings) if !strings.is_empty() && strings != instrument.tuning() => {
+                Instrument::Tuned(strings)
+            }
+            _ => instrument,
+        }
+    }
+
+    pub fn strings(&self) -> usize {
+        self.tuning().len()
+    }
+
+    /// Pitch classes of the strings, from the lowest up
+    fn tuning(&self) -> Vec<u8> {
+        match self {
+            Instrument::Guitar => vec![4, 9, 2, 7, 11, 4],
+            Instrument::Ukulele => vec![7, 0, 4, 9],
+            Instrument::Mandolin => vec![7, 2, 9, 4],
+            Instrument::Tuned(strings) => strings.clone(),
+        }
+    }
+
+    fn open_shapes(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Instrument::Guitar => &OPEN_SHAPES,
+            Instrument::Ukulele => &UKULELE_OPEN_SHAPES,
+            Instrument::Mandolin => &MANDOLIN_OPEN_SHAPES,
+            Instrument::Tuned(_) => &[],
+        }
+    }
+
+    fn movable_shapes(&self) -> &'static [(&'static str, u8, &'static str)] {
+        match self {
+            Instrument::Guitar => &GUITAR_SHAPES,
+            Instrument::Ukulele => &UKULELE_SHAPES,
+            Instrument::Mandolin => &MANDOLIN_SHAPES,
+            Instrument::Tuned(_) => &[],
+        }
+    }
+}
+
+/// Fingering for `chord` on `instrument`: an open chord if there is one, or else a
+/// movable shape, whichever is lowest on the neck. Without either, like in a custom
+/// tuning, a fingering is worked out. The bass note of slash chords is ignored.
+pub fn shape(chord: &str, instrument: &Instrument) -> Option<ChordDefinition> {
+    let caps = RE_ROOT.captures(chord)?;
+    let root = PitchClass::from_str(&caps[1])?;
+    let quality = normalize(&caps[2])?;
+    let name = format!("{}{}", &caps[1], quality);
+    let open = instrument.open_shapes().iter();
+    if let Some((_, frets)) = open.clone().find(|(open, _)| *open == name) {
+        return definition(chord, 1, frets);
+    }
+
+    let movable = instrument
+        .movable_shapes()
+        .iter()
+        .filter(|(shape, _, _)| *shape == quality)
+        .map(|(_, shape_root, frets)| (frets, (root.into_u8() + 12 - shape_root) % 12))
+        .min_by_key(|(_, fret)| *fret);
+    let (frets, fret) = match movable {
+        Some(movable) => movable,
+        None => {
+            let mut found = FOUND_SHAPES.lock().unwrap();
+            return found
+                .entry((chord.to_string(), instrument.tuning()))
+                .or_insert_with_key(|(chord, tuning)| {
+                    find_shape(chord, root.into_u8(), quality, tuning)
+                })
+                .clone();
+        }
+    };
+    let mut moved = definition(chord, fret.max(1), frets)?;
+    if fret > 0 {
+        // Open strings of the shape are covered by the barre, the first fret of the box
+        for string in moved.frets.iter_mut().flatten() {
+            *string += 1;
+        }
+    }
+    Some(moved)
+}
+
+/// Diagrams for every chord in `song`, preferring the ones it defines itself if they are
+/// for as many strings as `instrument` has, and the chords there's no fingering for
+pub fn song_diagrams(song: &Song, instrument: &Instrument) -> (Vec<ChordDefinition>, Vec<String>) {
+    let mut diagrams = vec![];
+    let mut missing = vec![];
+    for chord in song.unique_chords() {
+        let defined = song
+            .definitions
+            .iter()
+            .find(|def| def.name == chord && def.frets.len() == instrument.strings());
+        match defined.cloned().or_else(|| shape(chord, instrument)) {
+            Some(diagram) => diagrams.push(diagram),
+            None => missing.push(chord.to_string()),
+        }
+    }
+    (diagrams, missing)
+}
+
+/// Fret per string, `None` for a muted string
+type Frets = Vec<Option<u8>>;
+
+/// Fingerings by chord name and tuning, `None` if there is none
+type FoundShapes = HashMap<(String, Vec<u8>), Option<ChordDefinition>>;
+
+/// Searches for the fingering of a chord lowest on the neck that plays its root on the
+/// lowest string and has all its notes. It mutes as few strings as possible, only ever
+/// on the bass side and no more than a third of them.
+fn find_shape(name: &str, root: u8, quality: &str, tuning: &[u8]) -> Option<ChordDefinition> {
+    let tones: Vec<u8> = intervals(quality)
+        .iter()
+        .map(|interval| (root + interval) % 12)
+        .collect();
+    // A lower position always wins, so the first position with a fingering has the best
+    let frets = (0..10).find_map(|position| {
+        let mut search = ShapeSearch {
+            tuning,
+            tones: &tones,
+            root,
+            position,
+            best: None,
+        };
+        search.place(&mut vec![]);
+        search.best.map(|(_, frets)| frets)
+    })?;
+
+    // Diagrams are four frets high, further up the neck they start at the lowest fret
+    let highest = frets.iter().flatten().copied().max().unwrap_or(0);
+    let lowest = frets
+        .iter()
+        .flatten()
+        .copied()
+        .filter(|fret| *fret > 0)
+        .min();
+    let base_fret = match lowest {
+        Some(lowest) if highest > 4 => lowest,
+        _ => 1,
+    };
+    Some(ChordDefinition {
+        name: name.to_string(),
+        base_fret,
+        frets: frets
+            .into_iter()
+            .map(|fret| match fret {
+                Some(0) | None => fret,
+                Some(fret) => Some(fret + 1 - base_fret),
+            })
+            .collect(),
+    })
+}
+
+/// Search of `find_shape` for the easiest fingering with the hand at one position, four
+/// frets wide
+struct ShapeSearch<'a> {
+    tuning: &'a [u8],
+    tones: &'a [u8],
+    root: u8,
+    position: u8,
+    /// Muted strings and the sum of the frets of the best fingering so far, lower is
+    /// easier to play
+    best: Option<((usize, u8), Frets)>,
+}
+
+impl ShapeSearch<'_> {
+    /// Tries every way to play the strings after `frets`, leaving out the ones that
+    /// can't be played or can't beat the best fingering so far
+    fn place(&mut self, frets: &mut Frets) {
+        let muted = frets.iter().take_while(|fret| fret.is_none()).count();
+        let sum: u8 = frets.iter().flatten().sum();
+        if self
+            .best
+            .as_ref()
+            .is_some_and(|(best, _)| (muted, sum) >= *best)
+        {
+            return;
+        }
+        let notes: Vec<u8> = frets
+            .iter()
+            .zip(self.tuning)
+            .filter_map(|(fret, string)| Some((string + (*fret)?) % 12))
+            .collect();
+        let missing = self
+            .tones
+            .iter()
+            .filter(|tone| !notes.contains(tone))
+            .count();
+        let string = match self.tuning.get(frets.len()) {
+            Some(string) => *string,
+            None => {
+                if missing == 0 && !notes.is_empty() {
+                    self.best = Some(((muted, sum), frets.clone()));
+                }
+                return;
+            }
+        };
+        if missing > self.tuning.len() - frets.len() {
+            return;
+        }
+        // The lowest string that sounds plays the root, the others any chord tone
+        let bass = notes.is_empty();
+        let options: Vec<u8> = std::iter::once(0)
+            .chain(self.position.max(1)..=self.position + 3)
+            .filter(|fret| match (string + fret) % 12 {
+                note if bass => note == self.root,
+                note => self.tones.contains(&note),
+            })
+            .collect();
+        for fret in options {
+            frets.push(Some(fret));
+            self.place(frets);
+            frets.pop();
+        }
+        // Strings are only muted on the bass side, and no more than a third of them
+        if bass && (muted + 1) * 3 <= self.tuning.len() {
+            frets.push(None);
+            self.place(frets);
+            frets.pop();
+        }
+    }
+}
+
+/// Semitones above the root of the notes in a chord quality from `normalize`
+fn intervals(quality: &str) -> &'static [u8] {
+    match quality {
+        "m" => &[0, 3, 7],
+        "7" => &[0, 4, 7, 10],
+        "m7" => &[0, 3, 7, 10],
+        "maj7" => &[0, 4, 7, 11],
+        "sus4" => &[0, 5, 7],
+        "sus2" => &[0, 2, 7],
+        "add9" => &[0, 2, 4, 7],
+        _ => &[0, 4, 7],
+    }
+}
+
+/// Writes the different ways of spelling a chord quality the same way, as far as there
+/// are shapes for it
+fn normalize(quality: &str) -> Option<&'static str> {
+    Some(match quality {
+        "" | "maj" | "M" => "",
+        "m" | "min" | "-" => "m",
+        "7" | "dom7" => "7",
+        "m7" | "min7" | "-7" => "m7",
+        "maj7" | "M7" | "Δ" | "Δ7" => "maj7",
+        "sus4" | "sus" => "sus4",
+        "sus2" => "sus2",
+        "add9" => "add9",
+        _ => return None,
+    })
+}
+
+fn definition(name: &str, base_fret: u8, frets: &str) -> Option<ChordDefinition> {
+    ChordDefinition::parse(&format!("{} base-fret {} frets {}", name, base_fret, frets))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_and_barre_shapes() {
+        let frets = |chord: &str| {
+            let shape = shape(chord, &Instrument::Guitar).unwrap();
+            (shape.base_fret, shape.frets)
+        };
+        assert_eq!(
+            frets("Am"),
+            (1, vec![None, Some(0), Some(2), Some(2), Some(1), Some(0)])
+        );
+        assert_eq!(
+            frets("F#m"),
+            (
+                2,
+                vec![Some(1), Some(3), Some(3), Some(1), Some(1), Some(1)]
+            )
+        );
+        assert_eq!(
+            frets("C#7/G#"),
+            (4, vec![None, Some(1), Some(3), Some(1), Some(3), Some(1)])
+        );
+        assert_eq!(shape("C#7/G#", &Instrument::Guitar).unwrap().name, "C#7/G#");
+        assert!(shape("Cm9", &Instrument::Guitar).is_none());
+    }
+
+    #[test]
+    fn definitions_come_first() {
+        let song = Song::from(String::from(
+            "{define: G base-fret 1 frets 3 x 0 0 3 3}\n[G]la [Em]la [Cm9]\n",
+        ));
+        let (diagrams, missing) = song_diagrams(&song, &Instrument::Guitar);
+        assert_eq!(diagrams[0].frets[1], None);
+        assert_eq!(diagrams[1].name, "Em");
+        assert_eq!(missing, vec!["Cm9"]);
+    }
+
+    #[test]
+    fn other_instruments() {
+        let ukulele = Instrument::from_name(Some("Ukulele"));
+        let shape = shape("Bm7", &ukulele).unwrap();
+        assert_eq!(shape.base_fret, 2);
+        assert_eq!(shape.frets, vec![Some(1), Some(1), Some(1), Some(1)]);
+
+        let mandolin = Instrument::from_name(Some("mandolin"));
+        let song = Song::from(String::from(
+            "{define: G base-fret 1 frets 3 x 0 0 3 3}\n[G]la [Bb]\n",
+        ));
+        let (diagrams, _) = song_diagrams(&song, &mandolin);
+        assert_eq!(diagrams[0].frets, vec![Some(0), Some(0), Some(2), Some(3)]);
+        assert_eq!(diagrams[1].base_fret, 3);
+    }
+
+    #[test]
+    fn shapes_in_custom_tunings() {
+        let dadgad = Instrument::from_config(None, Some("DADGAD"));
+        assert_eq!(dadgad, Instrument::Tuned(vec![2, 9, 2, 7, 9, 2]));
+        let d = shape("D", &dadgad).unwrap();
+        assert_eq!(
+            d.frets,
+            vec![Some(0), Some(0), Some(0), Some(2), Some(0), Some(4)]
+        );
+
+        let drop_d = Instrument::from_config(Some("guitar"), Some("D A D G B E"));
+        let a = shape("A", &drop_d).unwrap();
+        assert_eq!(
+            a.frets,
+            vec![None, Some(0), Some(2), Some(2), Some(2), Some(0)]
+        );
+        let f = shape("F", &drop_d).unwrap();
+        assert_eq!(f.frets[0], Some(3));
+
+        let standard = Instrument::from_config(Some("guitar"), Some("standard"));
+        assert_eq!(standard, Instrument::Guitar);
+    }
+}
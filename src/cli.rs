@@ -0,0 +1,116 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use std::path::PathBuf;
+
+/// Displays ChordPro songs and playlists in the terminal
+#[derive(Parser)]
+#[command(name = "gpro", version, about)]
+pub struct Cli {
+    /// Set config file
+    #[arg(short, long, value_name = "PATH", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Write the default config
+    #[arg(long, value_name = "PATH")]
+    pub default_config: Option<PathBuf>,
+
+    /// Load everything but exit before starting the interface
+    #[arg(short, long, hide = true)]
+    pub debug: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Browse and view songs, this is the default
+    View {
+        /// Song folder to open instead of the one from the config
+        path: Option<PathBuf>,
+    },
+    /// Print a song to stdout
+    Cat {
+        file: PathBuf,
+
+        /// Width of the output, defaults to the terminal width
+        #[arg(long)]
+        width: Option<u16>,
+
+        /// Height of the output, defaults to the terminal height
+        #[arg(long)]
+        height: Option<u16>,
+
+        /// When to use colors
+        #[arg(long, value_enum, default_value_t = ColorMode::Auto)]
+        color: ColorMode,
+    },
+    /// Rewrite chords-over-lyrics charts as inline ChordPro
+    Convert {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Keep the original file next to the converted one, with `.orig` appended
+        #[arg(long)]
+        keep_original: bool,
+    },
+    /// Format song files according to the style rules in the config
+    Fmt {
+        /// Songs or folders of songs to format
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Only list the files that would change, exiting with an error if there are any
+        #[arg(long)]
+        check: bool,
+    },
+    /// Check songs for common mistakes
+    Lint {
+        /// Songs or folders of songs to check, defaults to the whole library
+        paths: Vec<PathBuf>,
+
+        /// Fix the problems that can be fixed safely
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Find and replace text in every song and playlist in the library
+    Replace {
+        pattern: String,
+        replacement: String,
+
+        /// Treat the pattern as a regex, the replacement can refer to groups like `$1`
+        #[arg(long)]
+        regex: bool,
+
+        /// Apply every change without asking
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Only show the changes
+        #[arg(long, conflicts_with = "yes")]
+        dry_run: bool,
+    },
+    /// Write the songs of a playlist to a single ChordPro file, in the keys it asks for
+    Export {
+        playlist: PathBuf,
+
+        /// File to write to instead of stdout, a printable songbook if it ends in `.pdf`
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+
+        /// Columns of song text on each page of a songbook
+        #[arg(long, default_value_t = 2)]
+        columns: usize,
+    },
+    /// Rebuild the library index cache
+    Index,
+    /// Print shell completions
+    Completions { shell: Shell },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
@@ -0,0 +1,84 @@
+use crate::app::FileType;
+use icu_collator::{Collator, CollatorOptions, Strength};
+use icu_locid::Locale;
+use icu_normalizer::DecomposingNormalizer;
+use std::{cmp::Ordering, sync::OnceLock};
+
+/// Splits characters into their base character and diacritics for `fold_char`, created
+/// once since searching folds every character of every name
+static NFD: OnceLock<DecomposingNormalizer> = OnceLock::new();
+
+/// Sorts names the way people expect for a language instead of by bytes, so accented
+/// titles end up next to their unaccented counterparts and case is ignored
+pub struct Collation(Collator);
+
+impl Collation {
+    /// Creates a collation for a BCP 47 locale like `nl` or `de-AT`, falling back to the
+    /// language independent root collation when the locale isn't recognized
+    pub fn new(locale: &str) -> Self {
+        let locale = Locale::try_from_bytes(locale.as_bytes()).unwrap_or_default();
+        let mut options = CollatorOptions::new();
+        options.strength = Some(Strength::Secondary);
+        let collator = Collator::try_new(&(&locale).into(), options)
+            .or_else(|_| Collator::try_new(&Default::default(), options))
+            .expect("Root collation data is compiled in");
+        Collation(collator)
+    }
+
+    pub fn compare(&self, left: &str, right: &str) -> Ordering {
+        self.0.compare(left, right)
+    }
+
+    pub fn sort(&self, files: &mut [FileType]) {
+        files.sort_by(|a, b| self.compare(&a.name(), &b.name()));
+    }
+}
+
+/// `c` in lowercase without diacritics, so `Ï` becomes `i`. Characters are only
+/// replaced by a single other character, so positions in folded text match the original.
+pub fn fold_char(c: char) -> char {
+    let c = c.to_lowercase().next().unwrap_or(c);
+    if c.is_ascii() {
+        return c;
+    }
+    let nfd = NFD.get_or_init(DecomposingNormalizer::new_nfd);
+    let decomposed = nfd.normalize(c.encode_utf8(&mut [0; 4]));
+    let mut chars = decomposed.chars();
+    match chars.next() {
+        // Only diacritics are dropped, other characters can decompose too, like Hangul
+        Some(base) if chars.all(|mark| ('\u{300}'..='\u{36f}').contains(&mark)) => base,
+        _ => c,
+    }
+}
+
+/// `text` in lowercase without diacritics, for searching regardless of accents, see
+/// `fold_char`
+pub fn fold(text: &str) -> String {
+    text.chars().map(fold_char).collect()
+}
+
+impl Default for Collation {
+    fn default() -> Self {
+        Collation::new("und")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorts_accents_and_case_naturally() {
+        let collation = Collation::new("nl");
+        let mut names = vec!["Zee", "één", "Abba", "Echo", "Eén", "eend"];
+        names.sort_by(|a, b| collation.compare(a, b));
+        assert_eq!(names, vec!["Abba", "Echo", "Eén", "één", "eend", "Zee"]);
+    }
+
+    #[test]
+    fn folds_diacritics() {
+        assert_eq!(fold("Zeïlen"), "zeilen");
+        assert_eq!(fold("Opwékking Ç"), "opwekking c");
+        assert_eq!(fold("한국어"), "한국어");
+    }
+}
@@ -1,33 +1,134 @@
+use lazy_static::lazy_static;
 use serde::{
-    de::{Deserializer, Visitor},
+    de::{Deserializer, MapAccess, Visitor},
     ser::Serializer,
     Deserialize, Serialize,
 };
-use std::path::PathBuf;
+use std::{convert::TryFrom, env, path::PathBuf};
 use termion::event::Key;
 use tui::style::{Color, Modifier, Style};
 
 #[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
+    /// File the config was read from
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
     pub path: PathBuf,
+    /// More song folders shown next to `path`, like a folder shared with the band
+    pub libraries: Vec<PathBuf>,
+    /// How many levels of folders below `path` are scanned for songs, all of them when
+    /// unset
+    pub scan_depth: Option<usize>,
+    /// Glob patterns of files and folders to leave out of the library, like `*.bak`,
+    /// on top of the ones in the `.gproignore` file of a library folder
+    pub ignore: Vec<String>,
+    /// Locale used to sort song names, e.g. `nl` or `en-US`
+    pub locale: String,
+    /// Order of the song list: `title`, `artist`, `modified`, `opened` or `key`
+    pub sort: SortMode,
     pub theme: Theme,
+    /// More themes to switch to while gpro is running, like a light one for rehearsing
+    /// in a bright room
+    pub themes: Vec<Theme>,
     pub keybinds: Keybinds,
     pub icons: Icons,
+    pub format: FormatStyle,
+    /// Instrument to show songs for, like `guitar`. Directives meant for another
+    /// instrument, like `{capo-bass_guitar: 2}`, are skipped. Chord diagrams are drawn
+    /// for `ukulele` and `mandolin` too, and for guitar otherwise.
+    pub instrument: Option<String>,
+    /// Tuning to draw chord diagrams for, like `drop-d`, `dadgad` or the notes of the
+    /// strings from the lowest up, like `D A D G A D`
+    pub tuning: Option<String>,
+    /// Show chords as Nashville numbers, relative to the key of the song
+    pub nashville: bool,
+    /// Show the chord as written in the song file after every transposed chord, like
+    /// `A (G)`
+    pub dual_chords: bool,
+    /// List every chord the song uses below it
+    pub chord_index: bool,
+    /// Show the key, capo, transposition and position of the open song on the bottom line
+    pub status_bar: bool,
+    /// Show the Roman numeral of every chord relative to the key of the song below it
+    pub roman_numerals: bool,
+    /// Show only the lyrics of songs, without chords
+    pub lyrics_only: bool,
+    /// Show songs as a compact chart of only their chords and section labels
+    pub chords_only: bool,
+    /// Whether transposed chords are written with sharps or flats, `auto` picks
+    /// whatever fits the key
+    pub accidentals: Accidentals,
+    /// Suggest a capo position in the song header that lets the song be played with
+    /// open chords
+    pub capo_suggestion: bool,
+    /// Seconds between lines when auto-scrolling a song without a `{duration}` or
+    /// `{tempo}`
+    pub scroll_interval: f64,
     pub auto_select_song: bool,
-    pub extra_column_size: usize,
+    /// Show a dimmed preview of the highlighted song until it's opened
+    pub preview: bool,
+    /// Show the title of the next song in the status bar when playing a setlist
+    pub setlist_preview: bool,
+    /// Width of the song list, it can be resized while gpro is running
+    pub sidebar_width: u16,
+    /// Number of columns songs are shown in. Without it as many columns are used as fit
+    /// next to each other without wrapping the longest line.
+    pub columns: Option<usize>,
+    /// Extra width columns used to get, replaced by `columns`. It's only read to warn
+    /// about configs that still set it.
+    #[serde(skip_serializing)]
+    pub extra_column_size: Option<usize>,
+    /// Empty cells between columns
     pub column_padding: usize,
+    /// Drawn down the middle of the padding between columns, like `│`
+    pub column_rule: Option<String>,
+    /// Show how far a song that doesn't fit on screen is scrolled on its right border
+    pub scrollbar: bool,
+    /// Show songs that don't fit on screen one page at a time instead of in columns
+    pub paging: bool,
+    /// Line numbers (`numbers`) or rehearsal letters (`marks`) next to the song
+    pub gutter: Gutter,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
+            source: None,
             path: PathBuf::from("."),
+            libraries: vec![],
+            scan_depth: None,
+            ignore: vec![],
+            locale: String::from("en"),
+            sort: SortMode::Title,
             theme: Theme::default(),
+            themes: vec![],
             keybinds: Keybinds::default(),
             icons: Icons::default(),
+            format: FormatStyle::default(),
+            instrument: None,
+            tuning: None,
+            nashville: false,
+            dual_chords: false,
+            chord_index: true,
+            status_bar: true,
+            roman_numerals: false,
+            lyrics_only: false,
+            chords_only: false,
+            accidentals: Accidentals::Auto,
+            capo_suggestion: false,
+            scroll_interval: 3.0,
             auto_select_song: false,
-            extra_column_size: 15,
+            preview: true,
+            setlist_preview: true,
+            sidebar_width: 20,
+            columns: None,
+            extra_column_size: None,
             column_padding: 2,
+            column_rule: None,
+            scrollbar: true,
+            paging: false,
+            gutter: Gutter::None,
         }
     }
 }
@@ -35,15 +136,20 @@ impl Default for Config {
 impl Config {
     pub fn load(file: &std::path::Path) -> Result<Config, Box<dyn std::error::Error>> {
         let contents = std::fs::read_to_string(file)?;
-        Ok(serde_yaml::from_str(&contents)?)
+        let mut config: Config = serde_yaml::from_str(&contents)?;
+        if config.extra_column_size.is_some() {
+            eprintln!(
+                "{}: extra_column_size is no longer used, set columns instead",
+                file.display()
+            );
+        }
+        config.source = Some(file.to_path_buf());
+        Ok(config)
     }
 
     pub fn write_default(file: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
         if file.exists() {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "File already exists",
-            )));
+            return Err(Box::new(std::io::Error::other("File already exists")));
         }
 
         let conf = Config::default();
@@ -51,42 +157,198 @@ impl Config {
         std::fs::write(file, &contents)?;
         Ok(())
     }
+
+    /// Every song folder, `path` first
+    pub fn roots(&self) -> Vec<PathBuf> {
+        std::iter::once(&self.path)
+            .chain(&self.libraries)
+            .cloned()
+            .collect()
+    }
+
+    /// Song folder that `file` is in, `path` if it isn't in any of them
+    pub fn root_of(&self, file: &std::path::Path) -> &std::path::Path {
+        self.libraries
+            .iter()
+            .find(|root| file.starts_with(root))
+            .unwrap_or(&self.path)
+    }
+}
+
+/// Order of the songs in the song list. Folders and playlists always come first,
+/// sorted by name.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortMode {
+    #[default]
+    Title,
+    Artist,
+    /// Most recently modified first
+    Modified,
+    /// Most recently opened first
+    Opened,
+    Key,
+}
+
+/// What is shown next to the lines of a song, for pointing out where to start playing
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Gutter {
+    #[default]
+    None,
+    /// Number of every line with something on it
+    Numbers,
+    /// A letter at the start of every part of the song
+    Marks,
+}
+
+impl Gutter {
+    pub fn next(self) -> Self {
+        match self {
+            Gutter::None => Gutter::Numbers,
+            Gutter::Numbers => Gutter::Marks,
+            Gutter::Marks => Gutter::None,
+        }
+    }
+}
+
+impl SortMode {
+    pub fn next(self) -> Self {
+        match self {
+            SortMode::Title => SortMode::Artist,
+            SortMode::Artist => SortMode::Modified,
+            SortMode::Modified => SortMode::Opened,
+            SortMode::Opened => SortMode::Key,
+            SortMode::Key => SortMode::Title,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortMode::Title => "title",
+            SortMode::Artist => "artist",
+            SortMode::Modified => "last modified",
+            SortMode::Opened => "last opened",
+            SortMode::Key => "key",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Accidentals {
+    #[default]
+    Auto,
+    Sharps,
+    Flats,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Theme {
+    /// Shown in the status bar when switching themes
+    pub name: String,
     pub title: ConfStyle,
     pub comment: ConfStyle,
+    /// Comments from `{comment_italic}`
+    pub comment_italic: ConfStyle,
+    /// Comments from `{comment_box}`
+    pub comment_box: ConfStyle,
+    /// Section headers, like the `Verse 2` in `{start_of_verse: Verse 2}`
+    pub section: ConfStyle,
+    /// Lines of a chorus, under the styles of its chords and lyrics. A background color
+    /// sets sections apart.
+    pub chorus: ConfStyle,
+    pub verse: ConfStyle,
+    pub bridge: ConfStyle,
+    /// Lines of tabs, grids and other `{start_of_...}` sections
+    pub other_section: ConfStyle,
     pub chord: ConfStyle,
+    /// Bar lines and repeat signs in grid sections
+    pub bar: ConfStyle,
+    /// Annotations written like chords, like `[*Riff 1]`
+    pub annotation: ConfStyle,
+    /// Text in brackets that isn't a chord, like `[Gsus44]`
+    pub warning: ConfStyle,
+    /// Roman numerals below the chords
+    pub numeral: ConfStyle,
     pub lyrics: ConfStyle,
     pub selected: ConfStyle,
+    /// Characters of a name that matched the search
+    pub search_match: ConfStyle,
     pub folder: ConfStyle,
     pub song: ConfStyle,
     pub playlist: ConfStyle,
+    pub status_bar: ConfStyle,
+    /// Messages about something that went wrong, shown over the status bar
+    pub notification: ConfStyle,
+    /// The `column_rule` between columns
+    pub column_rule: ConfStyle,
+    /// Line numbers or rehearsal letters next to the song
+    pub gutter: ConfStyle,
+    pub scrollbar: ConfStyle,
 }
 
 impl Default for Theme {
     fn default() -> Self {
         Theme {
+            name: "default".to_string(),
             title: ConfStyle::default()
                 .fg(Color::Blue)
                 .add_modifier(Modifier::BOLD),
             comment: ConfStyle::default()
                 .fg(Color::Red)
                 .add_modifier(Modifier::BOLD),
+            comment_italic: ConfStyle::default()
+                .fg(Color::Red)
+                .add_modifier(Modifier::ITALIC),
+            comment_box: ConfStyle::default()
+                .fg(Color::Red)
+                .add_modifier(Modifier::REVERSED),
+            section: ConfStyle::default()
+                .fg(Color::Magenta)
+                .add_modifier(Modifier::BOLD),
+            chorus: ConfStyle::default(),
+            verse: ConfStyle::default(),
+            bridge: ConfStyle::default(),
+            other_section: ConfStyle::default(),
             chord: ConfStyle::default().fg(Color::Blue),
+            bar: ConfStyle::default().fg(Color::Yellow),
+            annotation: ConfStyle::default()
+                .fg(Color::Blue)
+                .add_modifier(Modifier::ITALIC),
+            warning: ConfStyle::default()
+                .fg(Color::LightRed)
+                .add_modifier(Modifier::UNDERLINED),
+            numeral: ConfStyle::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::ITALIC),
             lyrics: ConfStyle::default(),
             selected: ConfStyle::default()
                 .fg(Color::Green)
                 .add_modifier(Modifier::BOLD),
+            search_match: ConfStyle::default()
+                .fg(Color::Green)
+                .add_modifier(Modifier::BOLD),
             folder: ConfStyle::default().fg(Color::Yellow),
             song: ConfStyle::default(),
             playlist: ConfStyle::default().fg(Color::Cyan),
+            status_bar: ConfStyle::default().add_modifier(Modifier::REVERSED),
+            notification: ConfStyle::default()
+                .fg(Color::White)
+                .bg(Color::Red)
+                .add_modifier(Modifier::BOLD),
+            column_rule: ConfStyle::default().fg(Color::DarkGray),
+            gutter: ConfStyle::default()
+                .fg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+            scrollbar: ConfStyle::default(),
         }
     }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Keybinds {
     pub up: SerDeKey,
     pub down: SerDeKey,
@@ -94,13 +356,96 @@ pub struct Keybinds {
     pub back: SerDeKey,
     pub jump_up: SerDeKey,
     pub jump_down: SerDeKey,
-    pub col_size_inc: SerDeKey,
-    pub col_size_dec: SerDeKey,
+    pub scroll_down: SerDeKey,
+    pub scroll_up: SerDeKey,
+    pub half_page_down: SerDeKey,
+    pub half_page_up: SerDeKey,
+    pub page_down: SerDeKey,
+    pub page_up: SerDeKey,
+    pub auto_scroll: SerDeKey,
+    pub scroll_faster: SerDeKey,
+    pub scroll_slower: SerDeKey,
+    #[serde(alias = "col_size_inc")]
+    pub more_columns: SerDeKey,
+    #[serde(alias = "col_size_dec")]
+    pub fewer_columns: SerDeKey,
+    pub auto_columns: SerDeKey,
+    pub paging: SerDeKey,
+    pub gutter: SerDeKey,
     pub search: SerDeKey,
+    pub regex_search: SerDeKey,
     pub transpose: SerDeKey,
+    pub transpose_up: SerDeKey,
+    pub transpose_down: SerDeKey,
+    pub transpose_reset: SerDeKey,
+    pub edit: SerDeKey,
+    pub quick_edit: SerDeKey,
+    pub metadata: SerDeKey,
+    pub rename: SerDeKey,
+    pub move_file: SerDeKey,
+    pub duplicate: SerDeKey,
+    pub delete: SerDeKey,
+    pub undo: SerDeKey,
+    pub lint: SerDeKey,
+    pub stats: SerDeKey,
+    pub notes: SerDeKey,
+    pub source_comments: SerDeKey,
+    pub all_songs: SerDeKey,
+    pub favorites: SerDeKey,
+    pub star: SerDeKey,
+    pub tags: SerDeKey,
+    pub sort: SerDeKey,
+    pub nashville: SerDeKey,
+    pub capo_suggestion: SerDeKey,
+    pub diagnostics: SerDeKey,
+    pub dual_chords: SerDeKey,
+    pub chord_diagrams: SerDeKey,
+    pub roman_numerals: SerDeKey,
+    pub lyrics_only: SerDeKey,
+    pub chords_only: SerDeKey,
+    pub help: SerDeKey,
+    pub next_section: SerDeKey,
+    pub previous_section: SerDeKey,
+    pub sections: SerDeKey,
+    pub present: SerDeKey,
+    pub new_tab: SerDeKey,
+    pub next_tab: SerDeKey,
+    pub previous_tab: SerDeKey,
+    pub close_tab: SerDeKey,
+    pub split: SerDeKey,
+    pub split_focus: SerDeKey,
+    pub sidebar: SerDeKey,
+    pub sidebar_wider: SerDeKey,
+    pub sidebar_narrower: SerDeKey,
+    pub next_theme: SerDeKey,
+    pub setlist: SerDeKey,
+    pub next_song: SerDeKey,
+    pub previous_song: SerDeKey,
+    pub save_queue: SerDeKey,
+    pub save: SerDeKey,
     pub quit: SerDeKey,
 }
 
+impl Keybinds {
+    /// Every bound key with the action it's bound to, like `("Ctrl+f", "Page down")`, in
+    /// the order of the fields
+    pub fn list(&self) -> Vec<(String, String)> {
+        let mapping = match serde_yaml::to_value(self) {
+            Ok(serde_yaml::Value::Mapping(mapping)) => mapping,
+            _ => return vec![],
+        };
+        mapping
+            .iter()
+            .filter_map(|(action, key)| {
+                let action = action.as_str()?.replace('_', " ");
+                let mut chars = action.chars();
+                let action = chars.next()?.to_uppercase().chain(chars).collect();
+                Some((key_name(key.as_str()?).to_string(), action))
+            })
+            .collect()
+    }
+}
+
 impl Default for Keybinds {
     fn default() -> Self {
         Keybinds {
@@ -110,20 +455,85 @@ impl Default for Keybinds {
             back: SerDeKey(Key::Left),
             jump_up: SerDeKey(Key::PageUp),
             jump_down: SerDeKey(Key::PageDown),
-            col_size_inc: SerDeKey(Key::End),
-            col_size_dec: SerDeKey(Key::Home),
+            scroll_down: SerDeKey(Key::Char('j')),
+            scroll_up: SerDeKey(Key::Char('k')),
+            half_page_down: SerDeKey(Key::Ctrl('d')),
+            half_page_up: SerDeKey(Key::Ctrl('u')),
+            page_down: SerDeKey(Key::Ctrl('f')),
+            page_up: SerDeKey(Key::Ctrl('b')),
+            auto_scroll: SerDeKey(Key::Char('a')),
+            scroll_faster: SerDeKey(Key::Char(']')),
+            scroll_slower: SerDeKey(Key::Char('[')),
+            more_columns: SerDeKey(Key::End),
+            fewer_columns: SerDeKey(Key::Home),
+            auto_columns: SerDeKey(Key::Char('0')),
+            paging: SerDeKey(Key::Char('p')),
+            gutter: SerDeKey(Key::Char('G')),
             search: SerDeKey(Key::Char('/')),
+            regex_search: SerDeKey(Key::Ctrl('r')),
             transpose: SerDeKey(Key::Char('t')),
+            transpose_up: SerDeKey(Key::Char('+')),
+            transpose_down: SerDeKey(Key::Char('-')),
+            transpose_reset: SerDeKey(Key::Char('=')),
+            edit: SerDeKey(Key::Char('e')),
+            quick_edit: SerDeKey(Key::Char('E')),
+            metadata: SerDeKey(Key::Char('m')),
+            rename: SerDeKey(Key::Char('r')),
+            move_file: SerDeKey(Key::Char('M')),
+            duplicate: SerDeKey(Key::Char('c')),
+            delete: SerDeKey(Key::Char('d')),
+            undo: SerDeKey(Key::Char('u')),
+            lint: SerDeKey(Key::Char('l')),
+            stats: SerDeKey(Key::Char('s')),
+            notes: SerDeKey(Key::Char('n')),
+            source_comments: SerDeKey(Key::Char('#')),
+            all_songs: SerDeKey(Key::Char('A')),
+            favorites: SerDeKey(Key::Char('F')),
+            star: SerDeKey(Key::Char('*')),
+            tags: SerDeKey(Key::Char('T')),
+            sort: SerDeKey(Key::Char('S')),
+            nashville: SerDeKey(Key::Char('N')),
+            capo_suggestion: SerDeKey(Key::Char('C')),
+            diagnostics: SerDeKey(Key::Char('D')),
+            dual_chords: SerDeKey(Key::Char('o')),
+            chord_diagrams: SerDeKey(Key::Char('g')),
+            roman_numerals: SerDeKey(Key::Char('R')),
+            lyrics_only: SerDeKey(Key::Char('L')),
+            chords_only: SerDeKey(Key::Char('K')),
+            help: SerDeKey(Key::Char('?')),
+            next_section: SerDeKey(Key::Char('}')),
+            previous_section: SerDeKey(Key::Char('{')),
+            sections: SerDeKey(Key::Char('J')),
+            present: SerDeKey(Key::Char('P')),
+            new_tab: SerDeKey(Key::Ctrl('t')),
+            next_tab: SerDeKey(Key::Char('\t')),
+            previous_tab: SerDeKey(Key::BackTab),
+            close_tab: SerDeKey(Key::Ctrl('w')),
+            split: SerDeKey(Key::Char('|')),
+            split_focus: SerDeKey(Key::Char('f')),
+            sidebar: SerDeKey(Key::Char('b')),
+            sidebar_wider: SerDeKey(Key::Char('>')),
+            sidebar_narrower: SerDeKey(Key::Char('<')),
+            next_theme: SerDeKey(Key::Char('v')),
+            setlist: SerDeKey(Key::Char('O')),
+            next_song: SerDeKey(Key::Char(')')),
+            previous_song: SerDeKey(Key::Char('(')),
+            save_queue: SerDeKey(Key::Char('W')),
+            save: SerDeKey(Key::Ctrl('s')),
             quit: SerDeKey(Key::Ctrl('c')),
         }
     }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Icons {
     pub folder: String,
     pub playlist: String,
     pub song: String,
+    /// Shown after the names of starred songs
+    pub favorite: String,
+    pub tag: String,
 }
 
 impl Default for Icons {
@@ -132,27 +542,52 @@ impl Default for Icons {
             folder: " ".to_string(),
             playlist: "蘿".to_string(),
             song: " ".to_string(),
+            favorite: " ★".to_string(),
+            tag: "# ".to_string(),
         }
     }
 }
 
-/// Style replacement which uses SerDeModifier in order to be readable when serialized
+/// Rules applied by `gpro fmt`
 #[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FormatStyle {
+    /// Replace abbreviated directives with their full name, like `{t}` with `{title}`
+    pub expand_directives: bool,
+    /// Attach chords followed by a space to the next word, so `[G] la` becomes `[G]la`
+    pub attach_chords: bool,
+    /// Maximum amount of consecutive empty lines
+    pub max_blank_lines: usize,
+}
+
+impl Default for FormatStyle {
+    fn default() -> Self {
+        FormatStyle {
+            expand_directives: true,
+            attach_chords: true,
+            max_blank_lines: 1,
+        }
+    }
+}
+
+/// Style replacement which uses SerDeColor and SerDeModifier in order to be readable when
+/// serialized
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ConfStyle {
-    pub fg: Option<Color>,
-    pub bg: Option<Color>,
+    pub fg: Option<SerDeColor>,
+    pub bg: Option<SerDeColor>,
     pub modifiers: Vec<SerDeModifier>,
 }
 
 impl ConfStyle {
     pub fn fg(mut self, fg: Color) -> Self {
-        self.fg = Some(fg);
+        self.fg = Some(SerDeColor(fg));
         self
     }
 
-    #[allow(dead_code)]
     pub fn bg(mut self, bg: Color) -> Self {
-        self.bg = Some(bg);
+        self.bg = Some(SerDeColor(bg));
         self
     }
 
@@ -164,8 +599,8 @@ impl ConfStyle {
     pub fn to_style(&self) -> Style {
         self.modifiers.iter().fold(
             Style {
-                fg: self.fg,
-                bg: self.bg,
+                fg: self.fg.as_ref().map(SerDeColor::to_color),
+                bg: self.bg.as_ref().map(SerDeColor::to_color),
                 add_modifier: Modifier::empty(),
                 sub_modifier: Modifier::empty(),
             },
@@ -174,16 +609,6 @@ impl ConfStyle {
     }
 }
 
-impl Default for ConfStyle {
-    fn default() -> Self {
-        ConfStyle {
-            fg: None,
-            bg: None,
-            modifiers: vec![],
-        }
-    }
-}
-
 /// Termion key wrapper that has serialize and deserialize
 #[derive(Clone)]
 pub struct SerDeKey(Key);
@@ -194,6 +619,26 @@ impl SerDeKey {
     }
 }
 
+impl SerDeKey {
+    /// How the key is shown in help texts
+    pub fn name(&self) -> String {
+        match serde_yaml::to_value(self) {
+            Ok(serde_yaml::Value::String(key)) => key_name(&key).to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+/// Readable name of a serialized key that is otherwise whitespace
+fn key_name(key: &str) -> &str {
+    match key {
+        "\n" => "Enter",
+        "\t" => "Tab",
+        " " => "Space",
+        key => key,
+    }
+}
+
 impl Serialize for SerDeKey {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -313,7 +758,7 @@ impl Serialize for SerDeModifier {
             Modifier::CROSSED_OUT => "Strikethrough",
             _ => unreachable!(),
         };
-        serializer.serialize_str(&string)
+        serializer.serialize_str(string)
     }
 }
 
@@ -339,19 +784,264 @@ impl<'de> Visitor<'de> for ModifierVisitor {
     where
         E: serde::de::Error,
     {
-        let modifier = match s {
-            "Bold" => Modifier::BOLD,
-            "Dim" => Modifier::DIM,
-            "Italic" => Modifier::ITALIC,
-            "Underlined" => Modifier::UNDERLINED,
-            "Slow blink" => Modifier::SLOW_BLINK,
-            "Rapid blink" => Modifier::RAPID_BLINK,
-            "Reversed" => Modifier::REVERSED,
-            "Hidden" => Modifier::HIDDEN,
-            "Strikethrough" => Modifier::CROSSED_OUT,
+        let name: String = s
+            .chars()
+            .filter(|c| !matches!(c, ' ' | '_' | '-'))
+            .collect::<String>()
+            .to_lowercase();
+        let modifier = match name.as_str() {
+            "bold" => Modifier::BOLD,
+            "dim" => Modifier::DIM,
+            "italic" => Modifier::ITALIC,
+            "underlined" | "underline" => Modifier::UNDERLINED,
+            "slowblink" => Modifier::SLOW_BLINK,
+            "rapidblink" => Modifier::RAPID_BLINK,
+            "reversed" | "reverse" => Modifier::REVERSED,
+            "hidden" => Modifier::HIDDEN,
+            "strikethrough" | "crossedout" => Modifier::CROSSED_OUT,
             _ => return Err(E::invalid_value(serde::de::Unexpected::Str(s), &self)),
         };
 
         Ok(SerDeModifier(modifier))
     }
 }
+
+lazy_static! {
+    /// Whether the terminal says it can show any RGB color
+    static ref TRUECOLOR: bool = env::var("COLORTERM")
+        .is_ok_and(|colorterm| colorterm == "truecolor" || colorterm == "24bit");
+}
+
+const COLOR_NAMES: [(&str, Color); 17] = [
+    ("Reset", Color::Reset),
+    ("Black", Color::Black),
+    ("Red", Color::Red),
+    ("Green", Color::Green),
+    ("Yellow", Color::Yellow),
+    ("Blue", Color::Blue),
+    ("Magenta", Color::Magenta),
+    ("Cyan", Color::Cyan),
+    ("Gray", Color::Gray),
+    ("DarkGray", Color::DarkGray),
+    ("LightRed", Color::LightRed),
+    ("LightGreen", Color::LightGreen),
+    ("LightYellow", Color::LightYellow),
+    ("LightBlue", Color::LightBlue),
+    ("LightMagenta", Color::LightMagenta),
+    ("LightCyan", Color::LightCyan),
+    ("White", Color::White),
+];
+
+/// Tui Color wrapper that reads named colors, 256-color indices like `208` and hex
+/// values like `#ff8700`
+#[derive(Clone, Debug, PartialEq)]
+pub struct SerDeColor(Color);
+
+impl SerDeColor {
+    /// The color to draw, with RGB colors replaced by the closest of the 256 colors when
+    /// the terminal doesn't support them
+    pub fn to_color(&self) -> Color {
+        match self.0 {
+            Color::Rgb(r, g, b) if !*TRUECOLOR => Color::Indexed(closest_indexed(r, g, b)),
+            color => color,
+        }
+    }
+}
+
+/// Closest color to an RGB value in the 6×6×6 cube and grayscale ramp of the 256 colors
+fn closest_indexed(r: u8, g: u8, b: u8) -> u8 {
+    let levels = [0, 95, 135, 175, 215, 255];
+    let level = |v: u8| match v {
+        0..=47 => 0,
+        48..=114 => 1,
+        v => (v - 35) / 40,
+    };
+    let distance = |(r2, g2, b2): (u8, u8, u8)| {
+        [(r, r2), (g, g2), (b, b2)]
+            .iter()
+            .map(|&(a, b)| (a as i32 - b as i32).pow(2))
+            .sum::<i32>()
+    };
+    let (ri, gi, bi) = (level(r), level(g), level(b));
+    let cube = (
+        levels[ri as usize],
+        levels[gi as usize],
+        levels[bi as usize],
+    );
+    let gray_index = ((r as u32 + g as u32 + b as u32) / 3).saturating_sub(3) / 10;
+    let gray_index = gray_index.min(23) as u8;
+    let gray = 8 + 10 * gray_index;
+    if distance((gray, gray, gray)) < distance(cube) {
+        232 + gray_index
+    } else {
+        16 + 36 * ri + 6 * gi + bi
+    }
+}
+
+impl Serialize for SerDeColor {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self.0 {
+            Color::Indexed(i) => serializer.serialize_u8(i),
+            Color::Rgb(r, g, b) => {
+                serializer.serialize_str(&format!("#{:02x}{:02x}{:02x}", r, g, b))
+            }
+            color => {
+                let (name, _) = COLOR_NAMES
+                    .iter()
+                    .find(|(_, named)| *named == color)
+                    .expect("All other colors have a name");
+                serializer.serialize_str(name)
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SerDeColor {
+    fn deserialize<D>(deserializer: D) -> Result<SerDeColor, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ColorVisitor)
+    }
+}
+
+struct ColorVisitor;
+
+impl<'de> Visitor<'de> for ColorVisitor {
+    type Value = SerDeColor;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            formatter,
+            "a color name, a number from 0 to 255 or a hex color like #ff8700"
+        )
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        let invalid = || E::invalid_value(serde::de::Unexpected::Str(s), &self);
+        if let Some(hex) = s.strip_prefix('#') {
+            let channel = |i: usize| {
+                hex.get(i..i + 2)
+                    .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+            };
+            return match (hex.len(), channel(0), channel(2), channel(4)) {
+                (6, Some(r), Some(g), Some(b)) => Ok(SerDeColor(Color::Rgb(r, g, b))),
+                _ => Err(invalid()),
+            };
+        }
+        if let Ok(i) = s.parse() {
+            return Ok(SerDeColor(Color::Indexed(i)));
+        }
+        let name: String = s
+            .chars()
+            .filter(|c| !matches!(c, ' ' | '_' | '-'))
+            .collect();
+        COLOR_NAMES
+            .iter()
+            .find(|(named, _)| named.eq_ignore_ascii_case(&name))
+            .map(|(_, color)| SerDeColor(*color))
+            .ok_or_else(invalid)
+    }
+
+    fn visit_u64<E>(self, i: u64) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        match u8::try_from(i) {
+            Ok(i) => Ok(SerDeColor(Color::Indexed(i))),
+            Err(_) => Err(E::invalid_value(serde::de::Unexpected::Unsigned(i), &self)),
+        }
+    }
+
+    /// Colors as written by earlier versions, like `Indexed: 208` or `Rgb: [255, 135, 0]`
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let color = match map.next_key::<String>()?.as_deref() {
+            Some("Indexed") => Color::Indexed(map.next_value()?),
+            Some("Rgb") => {
+                let (r, g, b) = map.next_value()?;
+                Color::Rgb(r, g, b)
+            }
+            _ => {
+                return Err(serde::de::Error::invalid_type(
+                    serde::de::Unexpected::Map,
+                    &self,
+                ))
+            }
+        };
+        Ok(SerDeColor(color))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_column_keybinds_still_load() {
+        let config: Config =
+            serde_yaml::from_str("keybinds:\n  col_size_inc: Ctrl+l\n  col_size_dec: Ctrl+h\n")
+                .unwrap();
+        assert_eq!(config.keybinds.more_columns.to_key(), Key::Ctrl('l'));
+        assert_eq!(config.keybinds.fewer_columns.to_key(), Key::Ctrl('h'));
+    }
+
+    fn color(yaml: &str) -> Option<Color> {
+        serde_yaml::from_str::<SerDeColor>(yaml)
+            .ok()
+            .map(|color| color.0)
+    }
+
+    #[test]
+    fn colors_are_parsed() {
+        assert_eq!(color("LightBlue"), Some(Color::LightBlue));
+        assert_eq!(color("light blue"), Some(Color::LightBlue));
+        assert_eq!(color("208"), Some(Color::Indexed(208)));
+        assert_eq!(color("'#FF8700'"), Some(Color::Rgb(255, 135, 0)));
+        assert_eq!(color("Indexed: 3"), Some(Color::Indexed(3)));
+        assert_eq!(color("Rgb: [1, 2, 3]"), Some(Color::Rgb(1, 2, 3)));
+        assert_eq!(color("'#ff87'"), None);
+        assert_eq!(color("256"), None);
+        assert_eq!(color("Purple"), None);
+    }
+
+    #[test]
+    fn colors_round_trip() {
+        for yaml in ["Blue", "208", "'#ff8700'"] {
+            let color: SerDeColor = serde_yaml::from_str(yaml).unwrap();
+            let written = serde_yaml::to_string(&color).unwrap();
+            assert_eq!(serde_yaml::from_str::<SerDeColor>(&written).unwrap(), color);
+        }
+    }
+
+    #[test]
+    fn styles_can_have_only_modifiers() {
+        let style: ConfStyle = serde_yaml::from_str("modifiers: [bold, Underline, Slow blink]")
+            .expect("Modifiers without colors are valid");
+        let style = style.to_style();
+        assert_eq!(style.fg, None);
+        assert_eq!(
+            style.add_modifier,
+            Modifier::BOLD | Modifier::UNDERLINED | Modifier::SLOW_BLINK
+        );
+        let style: ConfStyle = serde_yaml::from_str("fg: Blue").expect("Colors alone are valid");
+        assert_eq!(style.to_style().add_modifier, Modifier::empty());
+        assert!(serde_yaml::from_str::<ConfStyle>("modifiers: [Shiny]").is_err());
+    }
+
+    #[test]
+    fn rgb_falls_back_to_closest_indexed_color() {
+        assert_eq!(closest_indexed(255, 135, 0), 208);
+        assert_eq!(closest_indexed(0, 0, 0), 16);
+        assert_eq!(closest_indexed(128, 128, 128), 244);
+        assert_eq!(closest_indexed(255, 255, 255), 231);
+    }
+}
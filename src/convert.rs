@@ -0,0 +1,131 @@
+use crate::parser::{self, Song};
+use lazy_static::lazy_static;
+use regex::Regex;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+lazy_static! {
+    static ref RE_BAR: Regex = Regex::new(r"^(?:\||\|\||/|-|%|\(?x[0-9]+\)?|N\.?C\.?)$").unwrap();
+    static ref RE_TOKENS: Regex = Regex::new(r"\S+").unwrap();
+}
+
+/// Whether `token` is a chord name like `G`, `F#m7` or `D/F#`, or one in parentheses
+fn is_chord(token: &str) -> bool {
+    let chord = match token.strip_prefix('(') {
+        Some(chord) => chord.strip_suffix(')').unwrap_or(chord),
+        None => token,
+    };
+    !chord.is_empty() && parser::is_chord(chord)
+}
+
+/// Whether `line` only holds chords, like the chord lines in a chords-over-lyrics chart.
+/// Bar lines and repeat marks are allowed next to the chords.
+pub fn is_chord_line(line: &str) -> bool {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    tokens.iter().any(|token| is_chord(token))
+        && tokens
+            .iter()
+            .all(|token| is_chord(token) || RE_BAR.is_match(token))
+}
+
+/// Whether `songstring` looks like a chords-over-lyrics chart rather than ChordPro
+pub fn is_chords_over_lyrics(songstring: &str) -> bool {
+    !songstring.contains('[') && songstring.lines().any(is_chord_line)
+}
+
+/// Converts a chords-over-lyrics chart to inline ChordPro, placing each chord in the
+/// lyrics below it at the column it was written at
+pub fn to_chordpro(songstring: &str) -> String {
+    let newline = if songstring.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut lines: Vec<String> = vec![];
+    let mut input = songstring.lines().peekable();
+    while let Some(line) = input.next() {
+        if !is_chord_line(line) || Song::directive(line).is_some() {
+            lines.push(line.to_string());
+            continue;
+        }
+        let lyrics = match input.peek() {
+            Some(next)
+                if !next.trim().is_empty()
+                    && !is_chord_line(next)
+                    && Song::directive(next).is_none() =>
+            {
+                input.next().unwrap()
+            }
+            _ => "",
+        };
+        lines.push(merge(line, lyrics));
+    }
+    let mut result = lines.join(newline);
+    if songstring.ends_with('\n') {
+        result.push_str(newline);
+    }
+    result
+}
+
+/// Inserts the chords of `chords` into `lyrics` at the same columns. Columns are
+/// measured in display width and chords never split a character from its accents.
+fn merge(chords: &str, lyrics: &str) -> String {
+    if lyrics.is_empty() {
+        return RE_TOKENS
+            .find_iter(chords)
+            .map(|chord| format!("[{}]", chord.as_str()))
+            .collect::<Vec<_>>()
+            .join(" ");
+    }
+    let mut chords = RE_TOKENS
+        .find_iter(chords)
+        .map(|chord| (chords[..chord.start()].width(), chord.as_str()))
+        .peekable();
+    let mut result = String::new();
+    let mut column = 0;
+    for grapheme in lyrics.trim_end().graphemes(true) {
+        while let Some((_, chord)) = chords.next_if(|(at, _)| *at <= column) {
+            result.push_str(&format!("[{}]", chord));
+        }
+        result.push_str(grapheme);
+        column += grapheme.width();
+    }
+    for (at, chord) in chords {
+        result.push_str(&" ".repeat(at.saturating_sub(column)));
+        column = column.max(at);
+        result.push_str(&format!("[{}]", chord));
+    }
+    result.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_chord_lines() {
+        assert!(is_chord_line("G   D/F#  Em7   Cadd9 | x2"));
+        assert!(is_chord_line("  Bbmaj7 (A)"));
+        assert!(!is_chord_line("Amazing grace"));
+        assert!(!is_chord_line("| x2"));
+        assert!(!is_chord_line(""));
+    }
+
+    #[test]
+    fn converts_chords_over_lyrics() {
+        let chart =
+            "{title: Song}\r\nG       C\r\nAmazing grace\r\n\r\nD    G\r\nIntro\r\nEm  C\r\n";
+        assert_eq!(
+            to_chordpro(chart),
+            "{title: Song}\r\n[G]Amazing [C]grace\r\n\r\n[D]Intro[G]\r\n[Em] [C]\r\n"
+        );
+    }
+
+    #[test]
+    fn chords_are_placed_by_column() {
+        assert_eq!(merge("A     E", "la"), "[A]la    [E]");
+        assert_eq!(merge("   G", "café au"), "caf[G]é au");
+        assert_eq!(merge("    G", "cafe\u{301} au"), "cafe\u{301}[G] au");
+        assert_eq!(merge("  G C", "歌詞"), "歌[G]詞[C]");
+    }
+}
@@ -0,0 +1,299 @@
+use crate::{encoding, parser::Song};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Minimal line based text editor for making quick fixes to song files from inside gpro
+pub struct Editor {
+    pub path: PathBuf,
+    pub lines: Vec<String>,
+    /// Cursor position as line and character index
+    pub cursor: (usize, usize),
+    /// First line shown on screen
+    pub scroll: usize,
+    pub modified: bool,
+    /// Encoding the file was converted from when it was read, it's saved in it again
+    encoding: Option<&'static str>,
+    /// Whether closing the editor was already refused because of unsaved changes
+    pub close_refused: bool,
+    backed_up: bool,
+}
+
+impl Editor {
+    pub fn open(path: &Path, contents: &str, encoding: Option<&'static str>) -> Self {
+        let mut lines: Vec<String> = contents.lines().map(String::from).collect();
+        if lines.is_empty() {
+            lines.push(String::new());
+        }
+        Editor {
+            path: path.to_path_buf(),
+            lines,
+            cursor: (0, 0),
+            scroll: 0,
+            modified: false,
+            encoding,
+            close_refused: false,
+            backed_up: false,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.lines.join("\n") + "\n"
+    }
+
+    /// Writes the text back to the file. The first save copies the original file to
+    /// `<file>~`, so a botched edit can always be recovered.
+    pub fn save(&mut self) -> io::Result<()> {
+        if !self.backed_up {
+            let mut backup = self.path.clone().into_os_string();
+            backup.push("~");
+            fs::copy(&self.path, backup)?;
+            self.backed_up = true;
+        }
+        encoding::write(&self.path, &self.text(), self.encoding)?;
+        self.modified = false;
+        self.close_refused = false;
+        Ok(())
+    }
+
+    /// Byte offset of the cursor in the current line
+    fn byte_index(&self) -> usize {
+        let line = &self.lines[self.cursor.0];
+        line.char_indices()
+            .nth(self.cursor.1)
+            .map(|(i, _)| i)
+            .unwrap_or_else(|| line.len())
+    }
+
+    fn line_len(&self, line: usize) -> usize {
+        self.lines[line].chars().count()
+    }
+
+    pub fn insert(&mut self, c: char) {
+        let i = self.byte_index();
+        self.lines[self.cursor.0].insert(i, c);
+        self.cursor.1 += 1;
+        self.modified = true;
+    }
+
+    /// Inserts a chord bracket pair and places the cursor between the brackets
+    pub fn insert_chord(&mut self) {
+        self.insert('[');
+        let i = self.byte_index();
+        self.lines[self.cursor.0].insert(i, ']');
+    }
+
+    /// Closes a chord, skipping over the bracket if it was already inserted
+    pub fn close_chord(&mut self) {
+        let i = self.byte_index();
+        if self.lines[self.cursor.0][i..].starts_with(']') {
+            self.cursor.1 += 1;
+        } else {
+            self.insert(']');
+        }
+    }
+
+    pub fn newline(&mut self) {
+        let i = self.byte_index();
+        let rest = self.lines[self.cursor.0].split_off(i);
+        self.lines.insert(self.cursor.0 + 1, rest);
+        self.cursor = (self.cursor.0 + 1, 0);
+        self.modified = true;
+    }
+
+    pub fn backspace(&mut self) {
+        if self.cursor.1 > 0 {
+            self.left();
+            self.delete();
+        } else if self.cursor.0 > 0 {
+            let line = self.lines.remove(self.cursor.0);
+            self.cursor.0 -= 1;
+            self.cursor.1 = self.line_len(self.cursor.0);
+            self.lines[self.cursor.0].push_str(&line);
+            self.modified = true;
+        }
+    }
+
+    pub fn delete(&mut self) {
+        let i = self.byte_index();
+        if i < self.lines[self.cursor.0].len() {
+            self.lines[self.cursor.0].remove(i);
+            self.modified = true;
+        } else if self.cursor.0 + 1 < self.lines.len() {
+            let next = self.lines.remove(self.cursor.0 + 1);
+            self.lines[self.cursor.0].push_str(&next);
+            self.modified = true;
+        }
+    }
+
+    pub fn left(&mut self) {
+        if self.cursor.1 > 0 {
+            self.cursor.1 -= 1;
+        } else if self.cursor.0 > 0 {
+            self.cursor.0 -= 1;
+            self.cursor.1 = self.line_len(self.cursor.0);
+        }
+    }
+
+    pub fn right(&mut self) {
+        if self.cursor.1 < self.line_len(self.cursor.0) {
+            self.cursor.1 += 1;
+        } else if self.cursor.0 + 1 < self.lines.len() {
+            self.cursor = (self.cursor.0 + 1, 0);
+        }
+    }
+
+    pub fn up(&mut self) {
+        if self.cursor.0 > 0 {
+            self.cursor.0 -= 1;
+            self.cursor.1 = self.cursor.1.min(self.line_len(self.cursor.0));
+        }
+    }
+
+    pub fn down(&mut self) {
+        if self.cursor.0 + 1 < self.lines.len() {
+            self.cursor.0 += 1;
+            self.cursor.1 = self.cursor.1.min(self.line_len(self.cursor.0));
+        }
+    }
+
+    pub fn home(&mut self) {
+        self.cursor.1 = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor.1 = self.line_len(self.cursor.0);
+    }
+
+    /// Adjusts the scroll position so the cursor is visible in a view `height` lines high
+    pub fn scroll_to_cursor(&mut self, height: usize) {
+        if self.cursor.0 < self.scroll {
+            self.scroll = self.cursor.0;
+        } else if height > 0 && self.cursor.0 >= self.scroll + height {
+            self.scroll = self.cursor.0 + 1 - height;
+        }
+    }
+}
+
+/// Metadata fields shown in the metadata popup, with the directive names they can be
+/// written as. New directives use the first name.
+const METADATA_FIELDS: [(&str, &[&str]); 5] = [
+    ("Title", &["title", "t"]),
+    ("Subtitle", &["subtitle", "st"]),
+    ("Key", &["key"]),
+    ("Capo", &["capo", "capo-bass_guitar"]),
+    ("Tempo", &["tempo"]),
+];
+
+/// Form for quickly changing the metadata directives of a song
+pub struct MetadataForm {
+    pub path: PathBuf,
+    /// Labels and current values of the fields
+    pub fields: Vec<(&'static str, String)>,
+    pub selected: usize,
+    original: Vec<String>,
+}
+
+impl MetadataForm {
+    pub fn open(path: &Path, contents: &str) -> Self {
+        let original: Vec<String> = METADATA_FIELDS
+            .iter()
+            .map(|(_, names)| Song::get_directive(contents, names).unwrap_or_default())
+            .collect();
+        MetadataForm {
+            path: path.to_path_buf(),
+            fields: METADATA_FIELDS
+                .iter()
+                .zip(original.iter())
+                .map(|((label, _), value)| (*label, value.clone()))
+                .collect(),
+            selected: 0,
+            original,
+        }
+    }
+
+    pub fn next(&mut self) {
+        self.selected = (self.selected + 1) % self.fields.len();
+    }
+
+    pub fn previous(&mut self) {
+        self.selected = (self.selected + self.fields.len() - 1) % self.fields.len();
+    }
+
+    pub fn input(&mut self) -> &mut String {
+        &mut self.fields[self.selected].1
+    }
+
+    /// Applies the changed fields to `contents`, leaving unchanged directives untouched
+    pub fn apply(&self, contents: &str) -> String {
+        METADATA_FIELDS
+            .iter()
+            .zip(self.fields.iter().zip(self.original.iter()))
+            .filter(|(_, ((_, value), original))| value.trim() != original.as_str())
+            .fold(
+                contents.to_string(),
+                |contents, ((_, names), ((_, value), _))| {
+                    Song::set_directive(&contents, names, value)
+                },
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor(text: &str) -> Editor {
+        Editor::open(Path::new("song.txt"), text, None)
+    }
+
+    #[test]
+    fn edits_multibyte_lines() {
+        let mut editor = editor("één\ntwee");
+        editor.end();
+        editor.insert('!');
+        editor.newline();
+        editor.insert('x');
+        editor.down();
+        editor.home();
+        editor.backspace();
+        assert_eq!(editor.text(), "één!\nxtwee\n");
+        assert_eq!(editor.cursor, (1, 1));
+        assert!(editor.modified);
+    }
+
+    #[test]
+    fn chord_brackets_are_paired() {
+        let mut editor = editor("la");
+        editor.insert_chord();
+        editor.insert('G');
+        editor.close_chord();
+        editor.insert('x');
+        assert_eq!(editor.text(), "[G]xla\n");
+    }
+
+    #[test]
+    fn scrolls_to_cursor() {
+        let mut editor = editor("1\n2\n3\n4\n5");
+        (0..4).for_each(|_| editor.down());
+        editor.scroll_to_cursor(2);
+        assert_eq!(editor.scroll, 3);
+        (0..4).for_each(|_| editor.up());
+        editor.scroll_to_cursor(2);
+        assert_eq!(editor.scroll, 0);
+    }
+
+    #[test]
+    fn metadata_form_only_writes_changes() {
+        let song = "{t:Old }\n{Capo-Bass_Guitar: 1}\n[G]la\n";
+        let mut form = MetadataForm::open(Path::new("song.txt"), song);
+        assert_eq!(form.fields[0].1, "Old");
+        form.next();
+        form.input().push_str("Live");
+        form.next();
+        form.next();
+        form.input().clear();
+        assert_eq!(form.apply(song), "{t:Old }\n{subtitle: Live}\n[G]la\n");
+    }
+}
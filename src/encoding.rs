@@ -0,0 +1,93 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8};
+use std::{fs, io, path::Path};
+
+/// Contents of a text file converted to UTF-8
+#[derive(Debug, Default, Clone)]
+pub struct Decoded {
+    pub text: String,
+    /// Name of the encoding the file was converted from, `None` if it already was UTF-8
+    pub converted_from: Option<&'static str>,
+}
+
+/// Reads a file that may not be UTF-8, like songs exported by old Windows tools in
+/// Latin-1 or Windows-1252, guessing its encoding and converting it to UTF-8
+pub fn read_to_string(path: &Path) -> io::Result<Decoded> {
+    Ok(decode(&fs::read(path)?))
+}
+
+pub fn decode(bytes: &[u8]) -> Decoded {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return Decoded {
+            text: text.to_string(),
+            converted_from: None,
+        };
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    let encoding = detector.guess(None, true);
+    let (text, _, _) = encoding.decode(bytes);
+    Decoded {
+        text: text.into_owned(),
+        converted_from: match encoding == UTF_8 {
+            true => None,
+            false => Some(encoding.name()),
+        },
+    }
+}
+
+/// Converts `text` back to `encoding`, the name of the encoding it was converted from
+/// by `decode`, or UTF-8 if it's `None`. Text that can't be written in the encoding is
+/// an error rather than being replaced.
+pub fn encode(text: &str, encoding: Option<&str>) -> io::Result<Vec<u8>> {
+    let encoding = match encoding.and_then(|name| Encoding::for_label(name.as_bytes())) {
+        Some(encoding) => encoding,
+        None => return Ok(text.as_bytes().to_vec()),
+    };
+    let (bytes, _, unmappable) = encoding.encode(text);
+    if unmappable {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("the text can't be written in {}", encoding.name()),
+        ));
+    }
+    Ok(bytes.into_owned())
+}
+
+/// Writes `text` to `path` in `encoding`, see `encode`
+pub fn write(path: &Path, text: &str, encoding: Option<&str>) -> io::Result<()> {
+    fs::write(path, encode(text, encoding)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utf8_is_kept() {
+        let decoded = decode("{title: Één}".as_bytes());
+        assert_eq!(decoded.text, "{title: Één}");
+        assert_eq!(decoded.converted_from, None);
+    }
+
+    #[test]
+    fn windows_1252_is_converted() {
+        let decoded = decode(b"{title: Caf\xe9 cr\xe8me}\nZe\xefl en \x93quotes\x94\n");
+        assert_eq!(decoded.text, "{title: Café crème}\nZeïl en “quotes”\n");
+        assert_eq!(decoded.converted_from, Some("windows-1252"));
+    }
+
+    #[test]
+    fn text_is_written_back_in_its_encoding() {
+        let bytes = b"{title: Caf\xe9}\n";
+        let decoded = decode(bytes);
+        assert_eq!(
+            encode(&decoded.text, decoded.converted_from).unwrap(),
+            bytes
+        );
+        assert!(encode("\u{142}", decoded.converted_from).is_err());
+        assert_eq!(encode("\u{142}", None).unwrap(), "\u{142}".as_bytes());
+    }
+}
@@ -0,0 +1,369 @@
+use crate::{
+    encoding,
+    parser::{Playlist, Song},
+};
+use std::{
+    env, fs, io,
+    path::{Component, Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const TITLE: &[&str] = &["title", "t"];
+
+/// Housekeeping operations on files and folders in the library
+#[derive(Clone, Copy, PartialEq)]
+pub enum FileAction {
+    Rename,
+    Move,
+    Duplicate,
+    Delete,
+    /// Writes the songs opened this session to a new playlist in the folder
+    SaveQueue,
+}
+
+impl FileAction {
+    pub fn label(&self) -> &'static str {
+        match self {
+            FileAction::Rename => "Rename",
+            FileAction::Move => "Move to",
+            FileAction::Duplicate => "Duplicate as",
+            FileAction::Delete => "Delete",
+            FileAction::SaveQueue => "Save queue as",
+        }
+    }
+}
+
+/// Record of a file that was moved from `from` to `to`, so the move can be reverted.
+/// Deleted files are moved to the trash, so deletions are undone the same way.
+pub struct Undo {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+impl Undo {
+    /// Moves the file back to where it was
+    pub fn apply(&self) -> io::Result<()> {
+        if self.from.exists() {
+            return Err(already_exists(&self.from));
+        }
+        move_path(&self.to, &self.from)
+    }
+}
+
+/// Where deleted files are kept, following the XDG base directory spec
+pub fn trash_dir() -> PathBuf {
+    match env::var("XDG_DATA_HOME") {
+        Ok(data) => PathBuf::from(data),
+        Err(_) => match env::var("HOME") {
+            Ok(home) => PathBuf::from(home).join(".local").join("share"),
+            Err(_) => env::temp_dir(),
+        },
+    }
+    .join("gpro")
+    .join("trash")
+}
+
+/// Pending file operation, waiting for the user to enter a name or confirm it
+pub struct Prompt {
+    pub action: FileAction,
+    pub path: PathBuf,
+    pub input: String,
+    /// Songs of the playlist written by `FileAction::SaveQueue`
+    pub songs: Vec<String>,
+    /// Why the last attempt to apply the operation failed
+    pub error: Option<String>,
+}
+
+impl Prompt {
+    /// Prepares `action` on `path`, filling in the input with a sensible default. Move
+    /// destinations are folders relative to the library `root`.
+    pub fn new(action: FileAction, path: &Path, root: &Path) -> Self {
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let input = match action {
+            FileAction::Rename => file_name,
+            FileAction::Move => path
+                .parent()
+                .and_then(|parent| parent.strip_prefix(root).ok())
+                .map(|parent| parent.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            FileAction::Duplicate => unused_name(path)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            FileAction::Delete => String::new(),
+            FileAction::SaveQueue => String::from("Session"),
+        };
+        Prompt {
+            action,
+            path: path.to_path_buf(),
+            input,
+            songs: vec![],
+            error: None,
+        }
+    }
+
+    /// Performs the operation. Names are relative to the folder of the file, move
+    /// destinations to the library `root`, and a saved queue is titled by the input.
+    /// Names can't lead to another folder and move destinations can't leave `root` or
+    /// lie inside the moved folder.
+    /// Returns how to undo the operation, if it can be undone.
+    pub fn apply(&self, root: &Path) -> io::Result<Option<Undo>> {
+        let input = Path::new(self.input.trim());
+        let components: Vec<Component> = input.components().collect();
+        match self.action {
+            FileAction::Rename | FileAction::Duplicate | FileAction::SaveQueue
+                if !matches!(components.as_slice(), [] | [Component::Normal(_)]) =>
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Names can't contain a path separator",
+                ));
+            }
+            FileAction::Move
+                if !components
+                    .iter()
+                    .all(|part| matches!(part, Component::Normal(_) | Component::CurDir)) =>
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} is outside of the library", input.display()),
+                ));
+            }
+            _ => (),
+        }
+        let parent = self.path.parent().unwrap_or(root);
+        let target = match self.action {
+            FileAction::Rename | FileAction::Duplicate => parent.join(self.input.trim()),
+            FileAction::Move => root
+                .join(self.input.trim())
+                .join(self.path.file_name().unwrap_or_default()),
+            FileAction::Delete => trashed_name(&self.path),
+            FileAction::SaveQueue => {
+                self.path
+                    .join(format!("{}.{}", self.input.trim(), Playlist::EXTENSIONS[1]))
+            }
+        };
+        if self.input.trim().is_empty()
+            && matches!(
+                self.action,
+                FileAction::Rename | FileAction::Duplicate | FileAction::SaveQueue
+            )
+        {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Empty name"));
+        }
+        if self.action == FileAction::Move && target.starts_with(&self.path) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "A folder can't be moved into itself",
+            ));
+        }
+        if target.exists() {
+            return Err(already_exists(&target));
+        }
+        match self.action {
+            FileAction::Duplicate => {
+                copy_recursive(&self.path, &target)?;
+                rename_copied_song(&target)?;
+                Ok(None)
+            }
+            FileAction::SaveQueue => {
+                let playlist = format!("{}\n{}\n", self.input.trim(), self.songs.join("\n"));
+                fs::write(&target, playlist)?;
+                Ok(None)
+            }
+            _ => {
+                move_path(&self.path, &target)?;
+                Ok(Some(Undo {
+                    from: self.path.clone(),
+                    to: target,
+                }))
+            }
+        }
+    }
+}
+
+fn already_exists(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("{} already exists", path.display()),
+    )
+}
+
+/// Unique path in the trash for `path`, prefixed with the time it was deleted
+fn trashed_name(path: &Path) -> PathBuf {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0);
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let trashed = trash_dir().join(format!("{}-{}", time, name));
+    match trashed.exists() {
+        true => unused_name(&trashed),
+        false => trashed,
+    }
+}
+
+/// Moves a file or folder, falling back to copying when `to` is on another file system
+fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(dir) = to.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    match fs::rename(from, to) {
+        Err(error) if error.kind() == io::ErrorKind::CrossesDevices => (),
+        result => return result,
+    }
+    copy_recursive(from, to)?;
+    if from.is_dir() {
+        fs::remove_dir_all(from)
+    } else {
+        fs::remove_file(from)
+    }
+}
+
+/// Song files at `path`, which is either a song or a folder that's searched recursively
+pub fn song_files(path: &Path) -> io::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut entries: Vec<PathBuf> = fs::read_dir(path)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    entries.sort();
+    let mut songs = vec![];
+    for entry in entries {
+        if entry.is_dir() {
+            songs.append(&mut song_files(&entry)?);
+        } else if entry.extension().and_then(|ext| ext.to_str()) == Some("txt") {
+            songs.push(entry);
+        }
+    }
+    Ok(songs)
+}
+
+/// First of `name (copy).ext`, `name (copy 2).ext`, ... that doesn't exist yet
+fn unused_name(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|i| {
+            let copy = match i {
+                1 => String::from("copy"),
+                i => format!("copy {}", i),
+            };
+            path.with_file_name(format!("{} ({}){}", stem, copy, extension))
+        })
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+/// Songs are listed by title, so a copy needs a title of its own to show up next to
+/// the original
+fn rename_copied_song(path: &Path) -> io::Result<()> {
+    if path.extension().and_then(|ext| ext.to_str()) != Some("txt") {
+        return Ok(());
+    }
+    let contents = encoding::read_to_string(path)?.text;
+    match Song::get_directive(&contents, TITLE) {
+        Some(title) => fs::write(
+            path,
+            Song::set_directive(&contents, TITLE, &format!("{} (copy)", title)),
+        ),
+        None => Ok(()),
+    }
+}
+
+fn copy_recursive(from: &Path, to: &Path) -> io::Result<()> {
+    if from.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        fs::copy(from, to).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompts_start_with_defaults() {
+        let root = Path::new("/nonexistent/library");
+        let song = root.join("hymns").join("song.txt");
+        let input = |action| Prompt::new(action, &song, root).input;
+        assert_eq!(input(FileAction::Rename), "song.txt");
+        assert_eq!(input(FileAction::Move), "hymns");
+        assert_eq!(input(FileAction::Duplicate), "song (copy).txt");
+        assert_eq!(input(FileAction::Delete), "");
+        assert_eq!(input(FileAction::SaveQueue), "Session");
+    }
+
+    #[test]
+    fn queue_is_saved_as_playlist() {
+        let root = env::temp_dir().join(format!("gpro-queue-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let mut prompt = Prompt::new(FileAction::SaveQueue, &root, &root);
+        prompt.input = String::from("Tonight ");
+        prompt.songs = vec![String::from("Song A"), String::from("Song B [D]")];
+        assert!(prompt.apply(&root).unwrap().is_none());
+        let playlist = fs::read_to_string(root.join("Tonight.playlist")).unwrap();
+        assert_eq!(playlist, "Tonight\nSong A\nSong B [D]\n");
+        assert!(prompt.apply(&root).is_err());
+        prompt.input = String::from("../Tonight");
+        assert!(prompt.apply(&root).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn files_stay_in_the_library() {
+        let root = Path::new("/nonexistent/library");
+        let song = root.join("hymns").join("song.txt");
+        for (action, input) in [
+            (FileAction::Rename, "../song.txt"),
+            (FileAction::Rename, "other/song.txt"),
+            (FileAction::Duplicate, ".."),
+            (FileAction::Move, "../elsewhere"),
+            (FileAction::Move, "/tmp"),
+        ] {
+            let mut prompt = Prompt::new(action, &song, root);
+            prompt.input = String::from(input);
+            let error = prompt.apply(root).err().map(|error| error.kind());
+            assert_eq!(error, Some(io::ErrorKind::InvalidInput), "{}", input);
+        }
+        let folder = root.join("hymns");
+        for input in ["hymns", "hymns/old", "./hymns"] {
+            let mut prompt = Prompt::new(FileAction::Move, &folder, root);
+            prompt.input = String::from(input);
+            let error = prompt.apply(root).err().map(|error| error.kind());
+            assert_eq!(error, Some(io::ErrorKind::InvalidInput), "{}", input);
+        }
+    }
+
+    #[test]
+    fn renames_can_be_undone() {
+        let root = env::temp_dir().join(format!("gpro-files-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let song = root.join("song.txt");
+        fs::write(&song, "{title: Song}\n").unwrap();
+        let mut prompt = Prompt::new(FileAction::Move, &song, &root);
+        prompt.input = String::from("archive");
+        let undo = prompt.apply(&root).unwrap().unwrap();
+        assert!(root.join("archive").join("song.txt").exists());
+        assert!(!song.exists());
+        undo.apply().unwrap();
+        assert!(song.exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+use crate::{conf::FormatStyle, encoding, files, parser::Song};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+lazy_static! {
+    static ref RE_CHORD: Regex = Regex::new(r"\[([^\[\]\n]*)\]").unwrap();
+    static ref RE_LOOSE_CHORD: Regex = Regex::new(r"(\[[^\[\]\n]*\]) +([^\s\[])").unwrap();
+}
+
+/// Abbreviated directives and their full names
+const ABBREVIATIONS: [(&str, &str); 14] = [
+    ("t", "title"),
+    ("st", "subtitle"),
+    ("c", "comment"),
+    ("ci", "comment_italic"),
+    ("cb", "comment_box"),
+    ("soc", "start_of_chorus"),
+    ("eoc", "end_of_chorus"),
+    ("sov", "start_of_verse"),
+    ("eov", "end_of_verse"),
+    ("sot", "start_of_tab"),
+    ("eot", "end_of_tab"),
+    ("ns", "new_song"),
+    ("colb", "column_break"),
+    ("np", "new_page"),
+];
+
+/// Formats a song file according to `style`. Only layout is changed, the song
+/// renders the same before and after. Tab and grid sections line up by their spaces, so
+/// their lines are kept exactly as written.
+pub fn format_song(songstring: &str, style: &FormatStyle) -> String {
+    let newline = if songstring.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut lines: Vec<String> = vec![];
+    let mut blank_lines = 0;
+    let mut verbatim = false;
+    for line in songstring.lines() {
+        let directive = Song::directive(line).map(|(name, _)| name.to_lowercase());
+        match directive.as_deref() {
+            Some("sot" | "start_of_tab" | "sog" | "start_of_grid") => verbatim = true,
+            Some("eot" | "end_of_tab" | "eog" | "end_of_grid") => verbatim = false,
+            _ if verbatim => {
+                lines.push(line.to_string());
+                blank_lines = 0;
+                continue;
+            }
+            _ => (),
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank_lines += 1;
+            if blank_lines > style.max_blank_lines || lines.is_empty() {
+                continue;
+            }
+        } else {
+            blank_lines = 0;
+        }
+        lines.push(match Song::directive(line) {
+            Some((name, value)) => format_directive(name, value, style),
+            None => format_chords(line, style),
+        });
+    }
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    let mut result = lines.join(newline);
+    result.push_str(newline);
+    result
+}
+
+fn format_directive(name: &str, value: Option<&str>, style: &FormatStyle) -> String {
+    let mut name = name.to_lowercase();
+    if style.expand_directives {
+        if let Some((_, full)) = ABBREVIATIONS.iter().find(|(short, _)| *short == name) {
+            name = full.to_string();
+        }
+    }
+    match value {
+        Some(value) => format!("{{{}: {}}}", name, value),
+        None => format!("{{{}}}", name),
+    }
+}
+
+fn format_chords(line: &str, style: &FormatStyle) -> String {
+    let line = RE_CHORD.replace_all(line, |cap: &regex::Captures| {
+        format!("[{}]", cap.get(1).unwrap().as_str().trim())
+    });
+    match style.attach_chords {
+        true => RE_LOOSE_CHORD.replace_all(&line, "$1$2").to_string(),
+        false => line.to_string(),
+    }
+}
+
+/// Formats the song at `path`, or every song in it when it's a folder. Returns the
+/// files that changed, which are only written when `check` is false.
+pub fn format_path(path: &Path, style: &FormatStyle, check: bool) -> io::Result<Vec<PathBuf>> {
+    let mut changed = vec![];
+    for file in files::song_files(path)? {
+        let contents = encoding::read_to_string(&file)?;
+        let formatted = format_song(&contents.text, style);
+        // Files in other encodings are rewritten as UTF-8
+        if formatted != contents.text || contents.converted_from.is_some() {
+            if !check {
+                fs::write(&file, formatted)?;
+            }
+            changed.push(file);
+        }
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_song() {
+        let song = "\n{T:Song }  \n{c:Intro}\n\n\n\n[ G ] Hello [C] [D]world\t\n\n";
+        assert_eq!(
+            format_song(song, &FormatStyle::default()),
+            "{title: Song}\n{comment: Intro}\n\n[G]Hello [C] [D]world\n"
+        );
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let song = "{title: Song}\r\n[G]Hello\r\n";
+        assert_eq!(format_song(song, &FormatStyle::default()), song);
+    }
+
+    #[test]
+    fn tabs_and_grids_are_kept_as_written() {
+        let song = "{title: Song}\n{start_of_tab}\ne|--0--[x]--|  \n\n\n\nB|--1--  |\n{end_of_tab}\n{start_of_grid}\n|  G . | [C]  |\t\n{end_of_grid}\n";
+        assert_eq!(format_song(song, &FormatStyle::default()), song);
+    }
+}
@@ -0,0 +1,104 @@
+use crate::collation::fold_char;
+
+/// Points for every matched character
+const MATCH: i64 = 16;
+/// Extra points for a character matched right after the previous one
+const CONSECUTIVE: i64 = 8;
+/// Extra points for matching the first character of a word
+const WORD_START: i64 = 10;
+/// Points lost for every character skipped between two matches
+const GAP: i64 = 2;
+/// Most points lost for the characters skipped before the first match
+const MAX_LEADING_GAP: i64 = 6;
+
+/// Result of matching a search query against a name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    /// Higher is a better match
+    pub score: i64,
+    /// Indices of the matched characters in the name
+    pub positions: Vec<usize>,
+}
+
+/// Matches `pattern` against `text` as a subsequence, ignoring case, like fzf does. Of
+/// all the ways the characters of `pattern` can be found in `text` in order, the one
+/// with the best score is returned: matches that are consecutive or start words count
+/// for more, gaps between them for less. An empty pattern matches everything.
+pub fn score(pattern: &str, text: &str) -> Option<Match> {
+    let pattern: Vec<char> = pattern.chars().map(fold_char).collect();
+    let original: Vec<char> = text.chars().collect();
+    let text: Vec<char> = original.iter().copied().map(fold_char).collect();
+    if pattern.is_empty() {
+        return Some(Match {
+            score: 0,
+            positions: vec![],
+        });
+    }
+
+    let bonus = |j: usize| match j {
+        0 => WORD_START,
+        _ if !original[j - 1].is_alphanumeric() => WORD_START,
+        // Camel case humps start words too
+        _ if original[j].is_uppercase() && original[j - 1].is_lowercase() => WORD_START,
+        _ => 0,
+    };
+    // Best score with the i-th pattern character matched at the j-th text character,
+    // and where the previous pattern character was matched
+    let mut scores: Vec<Vec<Option<(i64, usize)>>> = vec![vec![None; text.len()]; pattern.len()];
+    for (i, p) in pattern.iter().enumerate() {
+        for j in 0..text.len() {
+            if text[j] != *p {
+                continue;
+            }
+            scores[i][j] = match i {
+                0 => Some((MATCH + bonus(j) - (j as i64 * GAP).min(MAX_LEADING_GAP), j)),
+                _ => (0..j)
+                    .filter_map(|k| {
+                        let (previous, _) = scores[i - 1][k]?;
+                        let step = match j - k {
+                            1 => CONSECUTIVE,
+                            gap => -((gap - 1) as i64 * GAP),
+                        };
+                        Some((previous + step + MATCH + bonus(j), k))
+                    })
+                    .max_by_key(|(score, _)| *score),
+            };
+        }
+    }
+
+    let last = pattern.len() - 1;
+    let (mut j, (score, _)) = scores[last]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, score)| Some((j, (*score)?)))
+        .max_by_key(|(_, (score, _))| *score)?;
+    let mut positions = vec![j];
+    for i in (1..=last).rev() {
+        j = scores[i][j]?.1;
+        positions.push(j);
+    }
+    positions.reverse();
+    Some(Match { score, positions })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_subsequences() {
+        let found = score("amg", "Amazing Grace").unwrap();
+        assert_eq!(found.positions, vec![0, 1, 8]);
+        assert!(score("gra", "Amazing Grace").is_some());
+        assert!(score("xyz", "Amazing Grace").is_none());
+        assert_eq!(score("zeilen", "Zeïlen").unwrap().positions.len(), 6);
+        assert_eq!(score("", "Anything").unwrap().score, 0);
+    }
+
+    #[test]
+    fn ranks_word_starts_and_runs_higher() {
+        let score = |pattern, text| score(pattern, text).unwrap().score;
+        assert!(score("gr", "Amazing Grace") > score("gr", "Hungry"));
+        assert!(score("how", "How Great") > score("how", "Shout to the Lord Now"));
+    }
+}
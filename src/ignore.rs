@@ -0,0 +1,113 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Name of the file in a library folder listing what to leave out of the library
+pub const IGNORE_FILE: &str = ".gproignore";
+
+/// Glob patterns of files and folders that are left out of the library, like backup
+/// files or PDFs kept next to the songs. Patterns without a `/` match names anywhere in
+/// the library, other patterns match paths from the library folder. A trailing `/` only
+/// matches folders.
+#[derive(Debug, Default, Clone)]
+pub struct Ignore {
+    /// Library folders and the patterns that apply to them
+    roots: Vec<(PathBuf, Vec<String>)>,
+}
+
+impl Ignore {
+    /// Combines `patterns` with the `.gproignore` file of every folder of `roots`. Empty
+    /// lines and lines starting with `#` in the file are skipped.
+    pub fn new(roots: &[PathBuf], patterns: &[String]) -> Self {
+        let roots = roots
+            .iter()
+            .map(|root| {
+                let file = fs::read_to_string(root.join(IGNORE_FILE)).unwrap_or_default();
+                let patterns = patterns
+                    .iter()
+                    .map(|pattern| pattern.trim().to_string())
+                    .chain(file.lines().map(|line| line.trim().to_string()))
+                    .filter(|pattern| !pattern.is_empty() && !pattern.starts_with('#'))
+                    .collect();
+                (root.clone(), patterns)
+            })
+            .collect();
+        Ignore { roots }
+    }
+
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let (root, patterns) = match self.roots.iter().find(|(root, _)| path.starts_with(root)) {
+            Some(root) => root,
+            None => return false,
+        };
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        let name = relative.rsplit('/').next().unwrap_or_default();
+        patterns.iter().any(|pattern| {
+            let (pattern, dir_only) = match pattern.strip_suffix('/') {
+                Some(pattern) => (pattern, true),
+                None => (pattern.as_str(), false),
+            };
+            if dir_only && !path.is_dir() {
+                return false;
+            }
+            match pattern.strip_prefix('/') {
+                Some(pattern) => glob_match(pattern, &relative),
+                None if pattern.contains('/') => glob_match(pattern, &relative),
+                None => glob_match(pattern, name),
+            }
+        })
+    }
+}
+
+/// Whether `text` matches the glob `pattern`. `*` matches anything but `/`, `**`
+/// matches anything and `?` matches a single character.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    matches(&pattern, &text)
+}
+
+fn matches(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', rest @ ..] => {
+            // `**/` also matches no folders at all
+            let rest = rest.strip_prefix(&['/']).unwrap_or(rest);
+            (0..=text.len()).any(|i| matches(rest, &text[i..]))
+        }
+        ['*', rest @ ..] => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != '/')
+            .any(|i| matches(rest, &text[i..])),
+        ['?', rest @ ..] => !text.is_empty() && text[0] != '/' && matches(rest, &text[1..]),
+        [c, rest @ ..] => text.first() == Some(c) && matches(rest, &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs_match() {
+        assert!(glob_match("*.bak", "song.txt.bak"));
+        assert!(!glob_match("*.bak", "drafts/song.bak"));
+        assert!(glob_match("drafts/*", "drafts/song.txt"));
+        assert!(glob_match("**/old.txt", "old.txt"));
+        assert!(glob_match("**/old.txt", "a/b/old.txt"));
+        assert!(glob_match("song?.txt", "song2.txt"));
+        assert!(!glob_match("song?.txt", "song.txt"));
+    }
+
+    #[test]
+    fn patterns_match_names_or_paths() {
+        let roots = vec![PathBuf::from("/nonexistent/library")];
+        let root = &roots[0];
+        let ignore = Ignore::new(&roots, &["*~".to_string(), "archive/*.txt".to_string()]);
+        assert!(ignore.is_ignored(&root.join("hymns").join("song.txt~")));
+        assert!(ignore.is_ignored(&root.join("archive").join("song.txt")));
+        assert!(!ignore.is_ignored(&root.join("hymns").join("archive").join("song.txt")));
+        assert!(!ignore.is_ignored(&root.join("song.txt")));
+    }
+}
@@ -0,0 +1,359 @@
+use crate::{
+    app::FileType,
+    conf::Config,
+    encoding,
+    ignore::Ignore,
+    parser::{Playlist, Song},
+    query,
+};
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// First line of the index cache, changed whenever its format changes so an old cache
+/// is thrown away instead of misread
+const CACHE_HEADER: &str = "gpro index 3";
+
+/// Cached names of a file in the library, valid as long as the file isn't modified. A
+/// song file can hold several songs.
+#[derive(Clone)]
+pub struct IndexEntry {
+    pub files: Vec<FileType>,
+    /// Fields of each of `files` that can be searched for, including their tags, see
+    /// `query::song_fields`
+    pub fields: Vec<Vec<(String, String)>>,
+    pub modified: u64,
+}
+
+/// Index of every song, playlist and folder in the library, keyed by path. It is stored
+/// on disk between runs so only new or modified files have to be read at startup.
+#[derive(Default)]
+pub struct Index {
+    entries: HashMap<PathBuf, IndexEntry>,
+    /// Files and folders left out of the library
+    ignore: Ignore,
+}
+
+impl Index {
+    /// Location of the index cache, following the XDG base directory spec
+    pub fn default_path() -> Option<PathBuf> {
+        match env::var("XDG_CACHE_HOME") {
+            Ok(cache) => Some(PathBuf::from(cache)),
+            Err(_) => env::var("HOME")
+                .ok()
+                .map(|home| PathBuf::from(home).join(".cache")),
+        }
+        .map(|cache| cache.join("gpro").join("index"))
+    }
+
+    /// Loads the cached index as it was left by the last run, which may be out of date
+    pub fn cached(config: &Config) -> Index {
+        let mut index = match Index::default_path() {
+            Some(path) => Index::load(&path),
+            None => Index::default(),
+        };
+        index.ignore = Ignore::new(&config.roots(), &config.ignore);
+        index
+    }
+
+    /// Loads the cached index, brings it up to date with the library folders of `config`
+    /// and writes it back to the cache
+    pub fn update(config: &Config) -> Index {
+        let mut index = Index::cached(config);
+        index.refresh(config);
+        index
+    }
+
+    /// Brings the index up to date with the library folders of `config` and writes it to
+    /// the cache
+    pub fn refresh(&mut self, config: &Config) {
+        self.ignore = Ignore::new(&config.roots(), &config.ignore);
+        self.scan(&config.roots(), config.scan_depth);
+        if let Some(path) = Index::default_path() {
+            // The index is only a cache, failing to write it just means a slower next start
+            let _ = self.save(&path);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Loads the index from `file`. Lines that can't be parsed are skipped, so a missing,
+    /// corrupt or outdated cache only costs a rescan.
+    pub fn load(file: &Path) -> Index {
+        let contents = fs::read_to_string(file).unwrap_or_default();
+        let mut lines = contents.lines();
+        if lines.next() != Some(CACHE_HEADER) {
+            return Index::default();
+        }
+        let mut entries: HashMap<PathBuf, IndexEntry> = HashMap::new();
+        let lines = lines.filter_map(|line| {
+            let mut fields = unescape_fields(line).into_iter();
+            let modified = fields.next()?.parse().ok()?;
+            let kind = fields.next()?;
+            let path = PathBuf::from(fields.next()?);
+            let name = fields.next()?;
+            let song_fields: Vec<(String, String)> = fields
+                .map(|field| {
+                    let (name, value) = field.split_once(':')?;
+                    Some((name.to_string(), value.to_string()))
+                })
+                .collect::<Option<_>>()?;
+            let file = match kind.as_str() {
+                "d" => FileType::Folder(path.clone()),
+                "s" => FileType::Song(name),
+                "p" => FileType::Playlist(name),
+                _ => return None,
+            };
+            Some((path, file, song_fields, modified))
+        });
+        // Every song of a file has its own line, in order
+        for (path, file, song_fields, modified) in lines {
+            let entry = entries.entry(path).or_insert(IndexEntry {
+                files: vec![],
+                fields: vec![],
+                modified,
+            });
+            entry.files.push(file);
+            entry.fields.push(song_fields);
+        }
+        Index {
+            entries,
+            ..Default::default()
+        }
+    }
+
+    pub fn save(&self, file: &Path) -> io::Result<()> {
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let entries: String = self
+            .entries
+            .iter()
+            .flat_map(|(path, entry)| {
+                entry
+                    .files
+                    .iter()
+                    .zip(&entry.fields)
+                    .filter_map(move |(file, song_fields)| {
+                        let (kind, name) = match file {
+                            FileType::Folder(_) => ("d", String::new()),
+                            FileType::Song(name) => ("s", name.clone()),
+                            FileType::Playlist(name) => ("p", name.clone()),
+                            FileType::Tag(_) => return None,
+                        };
+                        let path = path.to_str()?;
+                        let mut fields = vec![
+                            entry.modified.to_string(),
+                            kind.to_string(),
+                            escape(path),
+                            escape(&name),
+                        ];
+                        fields.extend(
+                            song_fields
+                                .iter()
+                                .map(|(name, value)| escape(&format!("{}:{}", name, value))),
+                        );
+                        Some(fields.join("\t") + "\n")
+                    })
+            })
+            .collect();
+        fs::write(file, format!("{}\n{}", CACHE_HEADER, entries))
+    }
+
+    /// Walks every folder of `roots` recursively, reading only the files that are new or
+    /// changed since they were last indexed, and forgets about files that no longer
+    /// exist. Folders more than `depth` levels below their root are left out if it's
+    /// given.
+    pub fn scan(&mut self, roots: &[PathBuf], depth: Option<usize>) {
+        let mut entries = HashMap::new();
+        for root in roots {
+            self.scan_dir(root, depth, &mut entries);
+        }
+        self.entries = entries;
+    }
+
+    fn scan_dir(
+        &self,
+        dir: &Path,
+        depth: Option<usize>,
+        entries: &mut HashMap<PathBuf, IndexEntry>,
+    ) {
+        let dir = match fs::read_dir(dir) {
+            Ok(d) => d,
+            Err(_) => return,
+        };
+        for entry in dir.flatten() {
+            let path = entry.path();
+            if self.ignore.is_ignored(&path) {
+                continue;
+            }
+            let modified = entry
+                .metadata()
+                .and_then(|meta| meta.modified())
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|time| time.as_secs())
+                .unwrap_or(0);
+            if path.is_dir() && depth != Some(0) {
+                self.scan_dir(&path, depth.map(|depth| depth - 1), entries);
+            }
+            let entry = match self.entries.get(&path) {
+                Some(cached) if cached.modified == modified => Some(cached.clone()),
+                _ => Index::read_entry(&path, modified),
+            };
+            if let Some(entry) = entry {
+                entries.insert(path, entry);
+            }
+        }
+    }
+
+    fn read_entry(path: &Path, modified: u64) -> Option<IndexEntry> {
+        let file_name = path.file_name()?.to_str()?.to_string();
+        let files = match path.extension().and_then(|ext| ext.to_str()) {
+            _ if path.is_dir() => vec![FileType::Folder(path.to_path_buf())],
+            Some("txt") => {
+                let filestring = encoding::read_to_string(path).unwrap_or_default().text;
+                let fields = Song::split(&filestring)
+                    .into_iter()
+                    .map(|song| query::song_fields(&Song::from(song.to_string())))
+                    .collect();
+                return Some(IndexEntry {
+                    files: Song::get_names(&filestring, &file_name)
+                        .into_iter()
+                        .map(FileType::Song)
+                        .collect(),
+                    fields,
+                    modified,
+                });
+            }
+            Some(ext) if Playlist::EXTENSIONS.contains(&ext) => {
+                let filestring = encoding::read_to_string(path).unwrap_or_default().text;
+                vec![FileType::Playlist(
+                    Playlist::get_name(&filestring).unwrap_or(file_name),
+                )]
+            }
+            _ => return None,
+        };
+        Some(IndexEntry {
+            fields: vec![vec![]; files.len()],
+            files,
+            modified,
+        })
+    }
+
+    /// Every tag in the library with the songs that have it
+    pub fn tags(&self) -> HashMap<&str, Vec<FileType>> {
+        let mut tags: HashMap<&str, Vec<FileType>> = HashMap::new();
+        for entry in self.entries.values() {
+            for (file, fields) in entry.files.iter().zip(&entry.fields) {
+                let file_tags = fields.iter().filter(|(name, _)| name == "tag");
+                for (_, tag) in file_tags {
+                    tags.entry(tag).or_default().push(file.clone());
+                }
+            }
+        }
+        tags
+    }
+
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        self.ignore.is_ignored(path)
+    }
+
+    /// Searchable fields of song `part` of the file at `path`, see `query::song_fields`
+    pub fn fields(&self, path: &Path, part: usize) -> &[(String, String)] {
+        self.entries
+            .get(path)
+            .and_then(|entry| entry.fields.get(part))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&[FileType]> {
+        self.entries.get(path).map(|entry| entry.files.as_slice())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&PathBuf, &[FileType])> {
+        self.entries
+            .iter()
+            .map(|(path, entry)| (path, entry.files.as_slice()))
+    }
+}
+
+/// Escapes backslashes, tabs and line breaks in a field of the index cache, so names
+/// and tags can contain any character
+fn escape(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Splits a line of the index cache at its tabs and undoes `escape` on every field
+fn unescape_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        let field = fields.last_mut().unwrap();
+        match c {
+            '\t' => fields.push(String::new()),
+            '\\' => match chars.next() {
+                Some('t') => field.push('\t'),
+                Some('n') => field.push('\n'),
+                Some('r') => field.push('\r'),
+                Some(c) => field.push(c),
+                None => (),
+            },
+            c => field.push(c),
+        }
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_keeps_names_with_special_characters() {
+        let path = env::temp_dir().join(format!("gpro-index-{}", std::process::id()));
+        let mut index = Index::default();
+        index.entries.insert(
+            PathBuf::from("/songs/a\tb.txt"),
+            IndexEntry {
+                files: vec![FileType::Song(String::from(
+                    "Tab\there, back\\slash\nand more",
+                ))],
+                fields: vec![vec![
+                    (String::from("tag"), String::from("a,b")),
+                    (String::from("artist"), String::from("x:y")),
+                ]],
+                modified: 7,
+            },
+        );
+        index.save(&path).unwrap();
+        let loaded = Index::load(&path);
+        fs::remove_file(&path).unwrap();
+        let entry = &loaded.entries[Path::new("/songs/a\tb.txt")];
+        assert_eq!(entry.files[0].name(), "Tab\there, back\\slash\nand more");
+        assert_eq!(
+            entry.fields,
+            index.entries[Path::new("/songs/a\tb.txt")].fields
+        );
+        assert_eq!(entry.modified, 7);
+    }
+}
@@ -0,0 +1,436 @@
+use std::ops::Range;
+
+/// Size of a single song line as it will be rendered, independent of its content
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LineMetrics {
+    pub width: usize,
+    pub height: usize,
+    /// Forces this line to start a new column
+    pub column_break: bool,
+    /// Keeps the line in the same column as the next one, like the chord line above its
+    /// lyrics in a chords-over-lyrics chart
+    pub keep_with_next: bool,
+}
+
+impl LineMetrics {
+    pub fn new(width: usize, height: usize) -> Self {
+        LineMetrics {
+            width,
+            height,
+            column_break: false,
+            keep_with_next: false,
+        }
+    }
+}
+
+/// Range of lines that end up in a single column, together with its measured size
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnPlan {
+    pub lines: Range<usize>,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// What of a song fit on screen the last time it was drawn
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Shown {
+    /// Number of columns that fit next to each other
+    pub columns: usize,
+    /// Lines on each page, when the song is shown a page at a time
+    pub pages: Vec<Range<usize>>,
+}
+
+/// Result of laying out a song: which lines go in which column
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LayoutPlan {
+    pub columns: Vec<ColumnPlan>,
+}
+
+impl LayoutPlan {
+    /// Total width of all columns, including `padding` after each of them
+    pub fn width(&self, padding: usize) -> usize {
+        self.columns
+            .iter()
+            .map(|column| column.width + padding)
+            .sum()
+    }
+
+    /// Drops the columns that fall outside a viewport `width` cells wide. `margin` extra
+    /// columns are kept past the edge so a partially visible column still gets drawn.
+    pub fn visible(mut self, width: usize, padding: usize, margin: usize) -> Self {
+        let mut used = 0;
+        let fitting = self
+            .columns
+            .iter()
+            .take_while(|column| {
+                used += column.width + padding;
+                used <= width
+            })
+            .count();
+        self.columns.truncate(fitting + margin);
+        self
+    }
+}
+
+/// Number of columns to show the (unwrapped) lines in: as many columns as fit next to
+/// each other in `width` without wrapping the longest line, but no more than it takes to
+/// fit every line in columns `height` high
+pub fn column_count(lines: &[LineMetrics], width: usize, height: usize, padding: usize) -> usize {
+    let longest = lines.iter().map(|line| line.width).max().unwrap_or(0);
+    let by_width = (width + padding) / (longest + padding).max(1);
+    let total: usize = lines.iter().map(|line| line.height).sum();
+    let by_height = total.div_ceil(height.max(1));
+    by_width.min(by_height).max(1)
+}
+
+/// Maximum line width before a line gets wrapped, so `columns` columns fit in `width`
+pub fn wrap_width(width: usize, columns: usize, padding: usize) -> usize {
+    (width / columns.max(1)).saturating_sub(padding).max(1)
+}
+
+/// Splits the (already wrapped) lines into as few columns as possible that are at most
+/// `height` high, then balances the lines over those columns so the last one isn't
+/// nearly empty. The balanced layout is only used when it still fits in `width`.
+pub fn plan_columns(
+    lines: &[LineMetrics],
+    height: usize,
+    width: usize,
+    padding: usize,
+) -> LayoutPlan {
+    let greedy = pack(lines, height);
+    let count = greedy.columns.len();
+    if count <= 1 {
+        return greedy;
+    }
+
+    // Binary search the lowest column height that doesn't need more columns
+    let mut low = lines.iter().map(|line| line.height).max().unwrap_or(0);
+    let mut high = height;
+    if low >= high {
+        return greedy;
+    }
+    while low < high {
+        let mid = (low + high) / 2;
+        if pack(lines, mid).columns.len() <= count {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+
+    let balanced = pack(lines, low);
+    if balanced.width(padding) <= width || balanced.width(padding) <= greedy.width(padding) {
+        balanced
+    } else {
+        greedy
+    }
+}
+
+/// Splits the lines into pages of at most `height` rows, starting a new page at column
+/// breaks too. Unlike columns, pages aren't balanced, so each one is filled up.
+pub fn pages(lines: &[LineMetrics], height: usize) -> Vec<Range<usize>> {
+    pack(lines, height)
+        .columns
+        .into_iter()
+        .map(|column| column.lines)
+        .collect()
+}
+
+/// Fills up each column until the next line doesn't fit in `height` or asks for a
+/// column break. Lines that are higher than `height` get a column of their own. A line
+/// that is kept with the next one moves to the next column along with it, unless it's
+/// the only line of its column.
+fn pack(lines: &[LineMetrics], height: usize) -> LayoutPlan {
+    let mut columns = vec![];
+    let mut start = 0;
+    let mut column_height = 0;
+
+    for (i, line) in lines.iter().enumerate() {
+        if i > start && (line.column_break || column_height + line.height > height) {
+            let mut end = i;
+            if !line.column_break && end - 1 > start && lines[end - 1].keep_with_next {
+                end -= 1;
+            }
+            columns.push(column(lines, start..end));
+            start = end;
+            column_height = lines[start..i].iter().map(|line| line.height).sum();
+        }
+        column_height += line.height;
+    }
+    if start < lines.len() {
+        columns.push(column(lines, start..lines.len()));
+    }
+
+    LayoutPlan { columns }
+}
+
+fn column(lines: &[LineMetrics], range: Range<usize>) -> ColumnPlan {
+    let lines_in_column = &lines[range.clone()];
+    ColumnPlan {
+        width: lines_in_column
+            .iter()
+            .map(|line| line.width)
+            .max()
+            .unwrap_or(0),
+        height: lines_in_column.iter().map(|line| line.height).sum(),
+        lines: range,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        conf::Theme,
+        parser::{Song, SongLine},
+    };
+
+    fn metrics(song: &str, max_width: usize) -> Vec<LineMetrics> {
+        let song = Song::from(song.to_string());
+        song.content
+            .iter()
+            .flat_map(|line| line.wrap(max_width))
+            .map(|line| line.metrics())
+            .collect()
+    }
+
+    /// Rows of a formatted line as plain text
+    fn rows(line: &SongLine) -> Vec<String> {
+        line.format(&Theme::default())
+            .iter()
+            .map(|spans| spans.0.iter().map(|span| span.content.as_ref()).collect())
+            .collect()
+    }
+
+    fn snapshot(plan: &LayoutPlan) -> String {
+        plan.columns
+            .iter()
+            .map(|column| {
+                format!(
+                    "{}..{} w{} h{}",
+                    column.lines.start, column.lines.end, column.width, column.height
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    const SHORT_SONG: &str = "{title: Short}
+[G]Amazing [D]grace, how [Em]sweet the [C]sound
+That [G]saved a [D]wretch like [G]me
+";
+
+    const LONG_SONG: &str = "{title: Long}
+[C]One line [F]with chords
+Plain text line
+{soc}
+[Am]Chorus [G]line one
+[F]Chorus line two that is quite a bit longer than the other lines [C]here
+{eoc}
+{c: Repeat}
+[C]Last [G]line
+";
+
+    #[test]
+    fn empty_song_has_no_columns() {
+        assert_eq!(plan_columns(&[], 10, 100, 2), LayoutPlan::default());
+    }
+
+    #[test]
+    fn column_count_fits_width_and_height() {
+        let lines = [LineMetrics::new(10, 2); 10];
+        // Four columns fit next to each other, but three are enough
+        assert_eq!(column_count(&lines, 50, 8, 2), 3);
+        assert_eq!(column_count(&lines, 30, 8, 2), 2);
+        assert_eq!(column_count(&lines, 5, 8, 2), 1);
+        assert_eq!(column_count(&lines, 100, 20, 2), 1);
+        assert_eq!(column_count(&[], 100, 20, 2), 1);
+    }
+
+    #[test]
+    fn wrap_width_divides_the_width() {
+        assert_eq!(wrap_width(100, 3, 2), 31);
+        assert_eq!(wrap_width(2, 3, 2), 1);
+    }
+
+    #[test]
+    fn oversized_line_gets_own_column() {
+        let lines = [
+            LineMetrics::new(4, 1),
+            LineMetrics::new(6, 5),
+            LineMetrics::new(2, 1),
+        ];
+        assert_eq!(
+            snapshot(&plan_columns(&lines, 3, 100, 2)),
+            "0..1 w4 h1\n1..2 w6 h5\n2..3 w2 h1"
+        );
+    }
+
+    #[test]
+    fn only_visible_columns_are_kept() {
+        let lines = [LineMetrics::new(10, 2); 6];
+        let plan = plan_columns(&lines, 4, 100, 2).visible(25, 2, 1);
+        assert_eq!(snapshot(&plan), "0..2 w10 h4\n2..4 w10 h4\n4..6 w10 h4");
+        let plan = plan_columns(&lines, 2, 100, 2).visible(25, 2, 0);
+        assert_eq!(snapshot(&plan), "0..1 w10 h2\n1..2 w10 h2");
+    }
+
+    #[test]
+    fn pages_are_filled_up() {
+        let mut lines = [LineMetrics::new(10, 1); 7];
+        assert_eq!(pages(&lines, 6), vec![0..6, 6..7]);
+        lines[2].column_break = true;
+        assert_eq!(pages(&lines, 6), vec![0..2, 2..7]);
+        assert!(pages(&[], 6).is_empty());
+    }
+
+    #[test]
+    fn columns_are_balanced() {
+        let lines = [LineMetrics::new(10, 1); 7];
+        assert_eq!(
+            snapshot(&plan_columns(&lines, 6, 100, 2)),
+            "0..4 w10 h4\n4..7 w10 h3"
+        );
+    }
+
+    #[test]
+    fn column_breaks_are_respected() {
+        let mut lines = [LineMetrics::new(10, 1); 6];
+        lines[1].column_break = true;
+        assert_eq!(
+            snapshot(&plan_columns(&lines, 10, 100, 2)),
+            "0..1 w10 h1\n1..6 w10 h5"
+        );
+        assert_eq!(
+            snapshot(&plan_columns(&lines, 3, 100, 2)),
+            "0..1 w10 h1\n1..4 w10 h3\n4..6 w10 h2"
+        );
+    }
+
+    #[test]
+    fn lines_are_kept_with_the_next_one() {
+        let mut lines = [LineMetrics::new(10, 1); 6];
+        lines[2].keep_with_next = true;
+        assert_eq!(
+            snapshot(&plan_columns(&lines, 3, 100, 2)),
+            "0..2 w10 h2\n2..4 w10 h2\n4..6 w10 h2"
+        );
+        // Nothing to move when the line is alone in its column
+        let mut lines = [LineMetrics::new(10, 1); 2];
+        lines[0].keep_with_next = true;
+        assert_eq!(
+            snapshot(&plan_columns(&lines, 1, 100, 2)),
+            "0..1 w10 h1\n1..2 w10 h1"
+        );
+    }
+
+    #[test]
+    fn chord_lines_stay_above_their_lyrics() {
+        let lines = metrics(
+            "Line one\nLine two\nG       D\nAmazing grace\nC       G\nHow sweet\n",
+            50,
+        );
+        for column in plan_columns(&lines, 3, 100, 2).columns {
+            assert!(!lines[column.lines.end - 1].keep_with_next);
+        }
+    }
+
+    #[test]
+    fn column_break_directives() {
+        let lines = metrics("[G]One\n{colb}\nTwo\n{np}\nThree\n", 18);
+        assert_eq!(
+            snapshot(&plan_columns(&lines, 20, 100, 2)),
+            "0..1 w3 h2\n1..2 w3 h1\n2..3 w5 h1"
+        );
+    }
+
+    #[test]
+    fn wrapping_keeps_chords_with_their_text() {
+        let song = Song::from("[G]Amazing [D]grace, how [Em]sweet the [C]sound\n".to_string());
+        let wrapped = song.content[0].wrap(20);
+        assert!(wrapped.len() > 1);
+        assert!(wrapped.iter().all(|line| line.metrics().height == 2));
+        assert!(wrapped.iter().all(|line| line.width() <= 20));
+    }
+
+    #[test]
+    fn wrapping_edge_cases() {
+        let song =
+            Song::from("Averylongwordthatishyphenated short\n{colb}\nOne two three\n".to_string());
+        let wrapped: Vec<String> = song.content[0]
+            .wrap(10)
+            .iter()
+            .map(|line| rows(line).pop().unwrap())
+            .collect();
+        assert_eq!(
+            wrapped,
+            vec!["Averylong-", "wordthati-", "shyphenat-", "ed short"]
+        );
+        let wrapped: Vec<LineMetrics> = song.content[1]
+            .wrap(4)
+            .iter()
+            .map(SongLine::metrics)
+            .collect();
+        assert!(wrapped[0].column_break);
+        assert!(wrapped[1..].iter().all(|line| !line.column_break));
+    }
+
+    #[test]
+    fn long_words_are_hyphenated() {
+        let song = Song::from("Averyveryverylongword [G]short\n".to_string());
+        let wrapped = song.content[0].wrap(10);
+        let text: Vec<String> = wrapped
+            .iter()
+            .map(|line| rows(line).pop().unwrap())
+            .collect();
+        assert_eq!(text, vec!["Averyvery-", "verylongw-", "ord short"]);
+        assert!(wrapped.iter().all(|line| line.width() <= 10));
+
+        let song = Song::from("[G]Supercali[D]fragilistic\n".to_string());
+        let wrapped: Vec<Vec<String>> = song.content[0].wrap(10).iter().map(rows).collect();
+        assert_eq!(
+            wrapped,
+            vec![
+                vec!["G ", "Supercali-"],
+                vec!["D ", "fragilist-"],
+                vec!["ic"],
+            ]
+        );
+    }
+
+    #[test]
+    fn wide_characters_are_wrapped_between_them() {
+        let song = Song::from("[C]我爱你中国[G]我的母亲 mother\n".to_string());
+        let wrapped = song.content[0].wrap(9);
+        assert!(wrapped.len() > 1);
+        assert!(wrapped.iter().all(|line| line.width() <= 9));
+        let lyrics: String = wrapped
+            .iter()
+            .map(|line| {
+                let formatted = line.format(&Theme::default());
+                let text = formatted.last().unwrap();
+                text.0
+                    .iter()
+                    .map(|span| span.content.as_ref())
+                    .collect::<String>()
+            })
+            .collect();
+        assert_eq!(lyrics, "我爱你中国我的母亲 mother");
+        assert_eq!(wrapped[0].format(&Theme::default())[0].0[0].content, "C ");
+    }
+
+    #[test]
+    fn short_song_fits_single_column() {
+        let lines = metrics(SHORT_SONG, 50);
+        assert_eq!(snapshot(&plan_columns(&lines, 20, 100, 2)), "0..2 w34 h4");
+    }
+
+    #[test]
+    fn long_song_breaks_into_columns() {
+        let lines = metrics(LONG_SONG, 17);
+        assert_eq!(
+            snapshot(&plan_columns(&lines, 4, 100, 2)),
+            "0..3 w15 h4\n3..5 w17 h4\n5..9 w14 h4\n9..11 w12 h3\n11..12 w9 h2"
+        );
+    }
+}
@@ -0,0 +1,24 @@
+pub mod app;
+pub mod chords;
+pub mod cli;
+pub mod collation;
+pub mod conf;
+pub mod convert;
+pub mod editor;
+pub mod encoding;
+pub mod files;
+pub mod format;
+pub mod fuzzy;
+pub mod ignore;
+pub mod index;
+pub mod layout;
+pub mod lint;
+pub mod parser;
+pub mod pdf;
+pub mod query;
+pub mod render;
+pub mod replace;
+pub mod state;
+pub mod stats;
+pub mod ui;
+pub mod util;
@@ -0,0 +1,256 @@
+use crate::parser::{self, Song};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{collections::HashMap, fmt};
+
+lazy_static! {
+    static ref RE_CHORD: Regex = Regex::new(r"\[([^\[\]\n]*)\]").unwrap();
+}
+
+/// Sections that have to be closed, as the start and end directives with their
+/// abbreviations
+const SECTIONS: [(&str, &str, &str, &str); 5] = [
+    ("start_of_chorus", "soc", "end_of_chorus", "eoc"),
+    ("start_of_verse", "sov", "end_of_verse", "eov"),
+    ("start_of_bridge", "sob", "end_of_bridge", "eob"),
+    ("start_of_tab", "sot", "end_of_tab", "eot"),
+    ("start_of_grid", "sog", "end_of_grid", "eog"),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LintKind {
+    MissingTitle,
+    MissingKey,
+    UnclosedSection,
+    UnopenedSection,
+    SuspiciousChord,
+    InconsistentLabel,
+    TrailingWhitespace,
+}
+
+impl LintKind {
+    /// Whether `fix` can solve this problem without changing the meaning of the song
+    pub fn fixable(&self) -> bool {
+        matches!(
+            self,
+            LintKind::UnclosedSection | LintKind::InconsistentLabel | LintKind::TrailingWhitespace
+        )
+    }
+}
+
+/// Problem found in a song file
+#[derive(Debug, PartialEq)]
+pub struct Lint {
+    /// Line the problem is on, starting at 1, or 0 when it's about the whole song
+    pub line: usize,
+    pub kind: LintKind,
+    pub message: String,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.line, self.message)?;
+        if self.kind.fixable() {
+            write!(f, " (fixable)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Which of `SECTIONS` the directive `name` starts or ends
+fn section(name: &str) -> Option<(usize, bool)> {
+    let name = name.to_lowercase();
+    SECTIONS
+        .iter()
+        .enumerate()
+        .find_map(|(i, (start, soc, end, eoc))| {
+            if name == *start || name == *soc {
+                Some((i, true))
+            } else if name == *end || name == *eoc {
+                Some((i, false))
+            } else {
+                None
+            }
+        })
+}
+
+/// Label of a section comment, like `Chorus` in `{c: Chorus}`
+fn label(line: &str) -> Option<&str> {
+    match Song::directive(line)? {
+        (name, Some(value)) if matches!(name.to_lowercase().as_str(), "c" | "comment") => {
+            Some(value)
+        }
+        _ => None,
+    }
+}
+
+/// Name a label is compared by, so `Chorus`, `CHORUS:` and `chorus` are the same
+fn label_key(label: &str) -> String {
+    label.trim_end_matches(':').trim().to_lowercase()
+}
+
+/// Checks a song for common mistakes
+pub fn lint(songstring: &str) -> Vec<Lint> {
+    let mut lints = vec![];
+    if Song::get_directive(songstring, &["title", "t"]).is_none() {
+        lints.push(Lint {
+            line: 0,
+            kind: LintKind::MissingTitle,
+            message: String::from("Song has no title"),
+        });
+    }
+    if Song::get_directive(songstring, &["key"]).is_none() {
+        lints.push(Lint {
+            line: 0,
+            kind: LintKind::MissingKey,
+            message: String::from("Song has no key, so it can't be transposed"),
+        });
+    }
+
+    let mut open: Vec<Option<usize>> = vec![None; SECTIONS.len()];
+    let mut labels: HashMap<String, &str> = HashMap::new();
+    for (i, line) in songstring.lines().enumerate() {
+        let number = i + 1;
+        if line.trim_end() != line {
+            lints.push(Lint {
+                line: number,
+                kind: LintKind::TrailingWhitespace,
+                message: String::from("Trailing whitespace"),
+            });
+        }
+        if let Some((section, start)) = Song::directive(line).and_then(|(name, _)| section(name)) {
+            let name = SECTIONS[section].0.trim_start_matches("start_of_");
+            match (start, open[section]) {
+                (true, Some(opened)) => lints.push(Lint {
+                    line: opened,
+                    kind: LintKind::UnclosedSection,
+                    message: format!("The {} is never closed", name),
+                }),
+                (false, None) => lints.push(Lint {
+                    line: number,
+                    kind: LintKind::UnopenedSection,
+                    message: format!("End of a {} that was never started", name),
+                }),
+                _ => (),
+            }
+            open[section] = start.then_some(number);
+        }
+        if let Some(label) = label(line) {
+            let first = labels.entry(label_key(label)).or_insert(label);
+            if *first != label {
+                lints.push(Lint {
+                    line: number,
+                    kind: LintKind::InconsistentLabel,
+                    message: format!("Label '{}' is written as '{}' before", label, first),
+                });
+            }
+        } else if Song::directive(line).is_none() {
+            for cap in RE_CHORD.captures_iter(line) {
+                let chord = cap.get(1).unwrap().as_str();
+                if !chord.starts_with('*') && !parser::is_chord(chord) {
+                    lints.push(Lint {
+                        line: number,
+                        kind: LintKind::SuspiciousChord,
+                        message: format!("'{}' doesn't look like a chord", chord),
+                    });
+                }
+            }
+        }
+    }
+    for (section, opened) in open.iter().enumerate() {
+        if let Some(opened) = opened {
+            lints.push(Lint {
+                line: *opened,
+                kind: LintKind::UnclosedSection,
+                message: format!(
+                    "The {} is never closed",
+                    SECTIONS[section].0.trim_start_matches("start_of_")
+                ),
+            });
+        }
+    }
+    lints.sort_by_key(|lint| lint.line);
+    lints
+}
+
+/// Fixes the problems `lint` marks as fixable. Unclosed sections are closed right
+/// before the next section of the same kind starts, or at the end of the song.
+pub fn fix(songstring: &str) -> String {
+    let newline = if songstring.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut lines: Vec<String> = vec![];
+    let mut open = vec![false; SECTIONS.len()];
+    let mut labels: HashMap<String, String> = HashMap::new();
+    for line in songstring.lines() {
+        let mut line = line.trim_end().to_string();
+        if let Some((section, start)) = Song::directive(&line).and_then(|(name, _)| section(name)) {
+            if start && open[section] {
+                lines.push(format!("{{{}}}", SECTIONS[section].2));
+            }
+            open[section] = start;
+        }
+        if let Some(label) = label(&line) {
+            let first = labels
+                .entry(label_key(label))
+                .or_insert_with(|| label.to_string());
+            if first != label {
+                line = Song::set_directive(&line, &["comment", "c"], first);
+            }
+        }
+        lines.push(line);
+    }
+    for (section, open) in open.iter().enumerate() {
+        if *open {
+            lines.push(format!("{{{}}}", SECTIONS[section].2));
+        }
+    }
+    let mut result = lines.join(newline);
+    if songstring.ends_with('\n') {
+        result.push_str(newline);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(song: &str) -> Vec<(usize, LintKind)> {
+        lint(song)
+            .iter()
+            .map(|lint| (lint.line, lint.kind))
+            .collect()
+    }
+
+    #[test]
+    fn finds_problems() {
+        let song = "{c: Chorus}\n{soc}\n[G]la [Hello]  \n{soc}\n{eov}\n{c: chorus:}\n";
+        assert_eq!(
+            kinds(song),
+            vec![
+                (0, LintKind::MissingTitle),
+                (0, LintKind::MissingKey),
+                (2, LintKind::UnclosedSection),
+                (3, LintKind::TrailingWhitespace),
+                (3, LintKind::SuspiciousChord),
+                (4, LintKind::UnclosedSection),
+                (5, LintKind::UnopenedSection),
+                (6, LintKind::InconsistentLabel),
+            ]
+        );
+    }
+
+    #[test]
+    fn fixes_fixable_problems() {
+        let song = "{title: a}\n{key: G}\n{c: Chorus}\n{soc}\n[G]la \n{soc}\n{c: chorus:}\n";
+        let fixed = fix(song);
+        assert_eq!(
+            fixed,
+            "{title: a}\n{key: G}\n{c: Chorus}\n{soc}\n[G]la\n{end_of_chorus}\n{soc}\n{c: Chorus}\n{end_of_chorus}\n"
+        );
+        assert!(lint(&fixed).is_empty());
+    }
+}
@@ -1,63 +1,51 @@
-mod app;
-mod conf;
-mod parser;
-mod ui;
-mod util;
-
-use crate::{
-    app::{App, AppState},
+use clap::{CommandFactory, Parser};
+use gpro::{
+    app::{App, AppState, View},
+    cli::{Cli, ColorMode, Command},
     conf::Config,
-    util::{Event, Events},
+    convert,
+    editor::Editor,
+    encoding,
+    files::{self, FileAction},
+    format,
+    index::Index,
+    lint,
+    parser::{parse_key, Song},
+    pdf, render,
+    replace::Replacer,
+    state::SavedState,
+    ui,
+    util::{self, Event, Events},
+};
+use std::{
+    env,
+    error::Error,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process, thread,
+    time::{Duration, Instant},
 };
-use getopts::Options;
-use std::{env, error::Error, io, path::PathBuf, time::Duration};
 use termion::{event::Key, raw::IntoRawMode};
 use tui::{
     backend::TermionBackend,
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
     Terminal,
 };
 
-fn print_usage(program: &str, opts: Options) {
-    let brief = format!("Usage: {} FILE [options]", program);
-    print!("{}", opts.usage(&brief));
-}
-
 fn main() -> Result<(), Box<dyn Error>> {
-    // parse commandline arguments
-    let args: Vec<String> = std::env::args().collect();
-    let program = &args[0];
+    let cli = Cli::parse();
 
-    let mut opts = Options::new();
-    opts.optopt("c", "config", "set config file", "PATH");
-    opts.optopt("", "default-config", "write the default config", "PATH");
-    opts.optflag("h", "help", "print this help menu");
-    opts.optflag("d", "debug", "");
-
-    let matches = match opts.parse(&args[1..]) {
-        Ok(m) => m,
-        Err(f) => {
-            panic!("{}", f)
-        }
-    };
-
-    if matches.opt_present("h") {
-        print_usage(program, opts);
-        return Ok(());
-    }
-
-    if let Some(arg) = matches.opt_str("default-config") {
-        let path = PathBuf::from(&arg);
+    if let Some(path) = cli.default_config {
         Config::write_default(&path)?;
         println!("Default config has been written to {}", path.display());
         return Ok(());
     }
 
-    let config = match matches.opt_str("c") {
-        Some(arg) => {
-            let path = PathBuf::from(&arg);
+    let mut config = match cli.config {
+        Some(path) => {
             if !path.exists() {
-                panic!("Path '{}' doesn't exist", arg)
+                panic!("Path '{}' doesn't exist", path.display())
             }
             Config::load(&path)?
         }
@@ -73,28 +61,254 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
     };
 
-    let mut app = App::new(config.clone());
+    match cli.command.unwrap_or(Command::View { path: None }) {
+        Command::View { path } => {
+            if let Some(path) = path {
+                config.path = path;
+            }
+            run(config, cli.debug)
+        }
+        Command::Cat {
+            file,
+            width,
+            height,
+            color,
+        } => {
+            let decoded = encoding::read_to_string(&file)?;
+            let (mut song, error) = Song::parse(decoded.text, None, config.instrument.as_deref());
+            if let Some(error) = error {
+                eprintln!("{}: {}", file.display(), error);
+            }
+            song.converted_from = decoded.converted_from;
+            let (term_width, term_height) = termion::terminal_size().unwrap_or((80, 24));
+            let buffer = render::render_song(
+                &song,
+                &config,
+                width.unwrap_or(term_width),
+                height.unwrap_or(term_height),
+            );
+            let ansi = match color {
+                ColorMode::Auto => termion::is_tty(&io::stdout()),
+                ColorMode::Always => true,
+                ColorMode::Never => false,
+            };
+            print!("{}", render::buffer_to_string(&buffer, ansi));
+            Ok(())
+        }
+        Command::Convert {
+            files,
+            keep_original,
+        } => {
+            for file in &files {
+                let contents = encoding::read_to_string(file)?.text;
+                if !convert::is_chords_over_lyrics(&contents) {
+                    println!(
+                        "Skipped {}, it isn't a chords-over-lyrics chart",
+                        file.display()
+                    );
+                    continue;
+                }
+                if keep_original {
+                    let mut original = file.clone().into_os_string();
+                    original.push(".orig");
+                    fs::copy(file, original)?;
+                }
+                fs::write(file, convert::to_chordpro(&contents))?;
+                println!("Converted {}", file.display());
+            }
+            Ok(())
+        }
+        Command::Fmt { paths, check } => {
+            let mut changed = vec![];
+            for path in &paths {
+                changed.append(&mut format::format_path(path, &config.format, check)?);
+            }
+            for path in &changed {
+                match check {
+                    true => println!("Would format {}", path.display()),
+                    false => println!("Formatted {}", path.display()),
+                }
+            }
+            if check && !changed.is_empty() {
+                process::exit(1);
+            }
+            Ok(())
+        }
+        Command::Lint { mut paths, fix } => {
+            if paths.is_empty() {
+                paths = config.roots();
+            }
+            let mut problems = 0;
+            for path in &paths {
+                for file in files::song_files(path)? {
+                    let mut contents = encoding::read_to_string(&file)?.text;
+                    if fix {
+                        let fixed = lint::fix(&contents);
+                        if fixed != contents {
+                            fs::write(&file, &fixed)?;
+                            contents = fixed;
+                        }
+                    }
+                    for problem in lint::lint(&contents) {
+                        println!("{}:{}", file.display(), problem);
+                        problems += 1;
+                    }
+                }
+            }
+            if problems > 0 {
+                process::exit(1);
+            }
+            Ok(())
+        }
+        Command::Replace {
+            pattern,
+            replacement,
+            regex,
+            yes,
+            dry_run,
+        } => {
+            let replacer = Replacer::new(&pattern, &replacement, regex)?;
+            let index = Index::update(&config);
+            let mut apply_all = yes;
+            for change in replacer.find(&index) {
+                println!("{}", change.path.display());
+                for (line, before, after) in change.changed_lines() {
+                    println!("{:>5} - {}", line, before);
+                    println!("{:>5} + {}", line, after);
+                }
+                if dry_run {
+                    continue;
+                }
+                if !apply_all {
+                    match ask("Apply? [y]es, [n]o, [a]ll, [q]uit")?.as_str() {
+                        "y" => (),
+                        "a" => apply_all = true,
+                        "q" => break,
+                        _ => continue,
+                    }
+                }
+                change.apply()?;
+            }
+            Ok(())
+        }
+        Command::Export {
+            playlist,
+            output,
+            columns,
+        } => {
+            let index = Index::update(&config);
+            let saved = SavedState::default_path()
+                .and_then(|path| SavedState::load(&path).ok())
+                .unwrap_or_default();
+            let app = App::new(config, index, saved);
+            let (songs, missing) = app.read_playlist(&playlist)?;
+            for entry in &missing {
+                eprintln!("{}: '{}' isn't in the library", playlist.display(), entry);
+            }
+            match output {
+                Some(path) if path.extension().is_some_and(|ext| ext == "pdf") => {
+                    let theme = &app.config.theme;
+                    let book =
+                        pdf::songbook(&songs.title, &app.playlist_songs(&songs), columns, theme)?;
+                    if !book.unprintable.is_empty() {
+                        let unprintable: String = book.unprintable.iter().collect();
+                        eprintln!(
+                            "{}: the PDF fonts don't have {}, they're printed as '?'",
+                            path.display(),
+                            unprintable
+                        );
+                    }
+                    fs::write(&path, book.pdf)?;
+                    println!("Exported {} to {}", playlist.display(), path.display());
+                }
+                Some(path) => {
+                    fs::write(&path, app.playlist_chordpro(&songs))?;
+                    println!("Exported {} to {}", playlist.display(), path.display());
+                }
+                None => print!("{}", app.playlist_chordpro(&songs)),
+            }
+            Ok(())
+        }
+        Command::Index => {
+            let index = Index::update(&config);
+            let roots: Vec<String> = config
+                .roots()
+                .iter()
+                .map(|root| root.display().to_string())
+                .collect();
+            println!("Indexed {} files in {}", index.len(), roots.join(", "));
+            Ok(())
+        }
+        Command::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "gpro", &mut io::stdout());
+            Ok(())
+        }
+    }
+}
+
+/// Prints `question` and reads a single line answer from stdin
+fn ask(question: &str) -> io::Result<String> {
+    print!("{} ", question);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().to_lowercase())
+}
+
+/// Runs the interactive interface until the user quits
+fn run(config: Config, debug: bool) -> Result<(), Box<dyn Error>> {
+    // The library is scanned in the background, until then the last index is shown
+    let index = match debug {
+        true => Index::update(&config),
+        false => Index::cached(&config),
+    };
+
+    let state_path = SavedState::default_path();
+    let saved = match &state_path {
+        Some(path) => SavedState::load(path).unwrap_or_default(),
+        None => SavedState::default(),
+    };
+
+    let mut app = App::new(config.clone(), index, saved);
 
-    if matches.opt_present("d") {
+    if debug {
         return Ok(());
     }
 
-    let stdout = io::stdout().into_raw_mode()?;
-    let backend = TermionBackend::new(stdout);
+    let raw_mode = io::stdout().into_raw_mode()?;
+    let backend = TermionBackend::new(io::stdout());
 
     let mut term = Terminal::new(backend)?;
     let events = Events::with_config(util::Config {
         exit_key: config.keybinds.quit.to_key(),
         tick_rate: Duration::from_millis(250),
     });
+    app.indexing = true;
+    index_in_background(&events, &config);
+    // Without a watcher the library is only reread after changes made in gpro
+    let _watcher = events.watch(&config.roots()).ok();
+    // Files changed since the last tick, handled together since saving a file can take
+    // several events
+    let mut changed: Vec<PathBuf> = vec![];
 
     term.clear().unwrap();
     loop {
         term.draw(|f| {
+            // The status bar takes the bottom margin
+            let size = f.size();
+            if (app.config.status_bar || app.notification.is_some()) && size.height > 2 {
+                let status_bar = Rect::new(size.x, size.bottom() - 1, size.width, 1);
+                ui::draw_status_bar(f, &app, status_bar);
+            }
+            // The song list is always shown when it's needed for input
+            let sidebar = match app.hide_sidebar && app.state == AppState::Default {
+                true => 0,
+                false => app.sidebar_width,
+            };
             let layout = Layout::default()
                 .direction(Direction::Horizontal)
                 .margin(1)
-                .constraints([Constraint::Length(20), Constraint::Min(80)].as_ref())
+                .constraints([Constraint::Length(sidebar), Constraint::Min(80)].as_ref())
                 .split(f.size());
 
             let left_bar = Layout::default()
@@ -103,6 +317,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .split(layout[0]);
 
             match app.state {
+                AppState::Default if app.hide_sidebar => (),
                 AppState::Default => ui::draw_song_list(f, &mut app, layout[0]),
                 AppState::Searching => {
                     ui::draw_song_list(f, &mut app, left_bar[0]);
@@ -112,6 +327,49 @@ fn main() -> Result<(), Box<dyn Error>> {
                     ui::draw_song_list(f, &mut app, left_bar[0]);
                     ui::draw_transposition(f, &mut app, left_bar[1]);
                 }
+                AppState::Editing => {
+                    ui::draw_song_list(f, &mut app, layout[0]);
+                    ui::draw_editor(f, &mut app, layout[1]);
+                    return;
+                }
+                AppState::Prompting => {
+                    ui::draw_song_list(f, &mut app, left_bar[0]);
+                    ui::draw_prompt(f, &app, left_bar[1]);
+                }
+                AppState::Stats => {
+                    ui::draw_song_list(f, &mut app, layout[0]);
+                    ui::draw_song(f, &app, layout[1]);
+                    ui::draw_stats(f, &app, layout[1]);
+                    return;
+                }
+                AppState::Linting => {
+                    ui::draw_song_list(f, &mut app, layout[0]);
+                    ui::draw_song(f, &app, layout[1]);
+                    ui::draw_lints(f, &app, layout[1]);
+                    return;
+                }
+                AppState::EditingMetadata => {
+                    ui::draw_song_list(f, &mut app, layout[0]);
+                    ui::draw_song(f, &app, layout[1]);
+                    ui::draw_metadata(f, &app, layout[1]);
+                    return;
+                }
+                AppState::Help => {
+                    ui::draw_song_list(f, &mut app, layout[0]);
+                    ui::draw_song(f, &app, layout[1]);
+                    ui::draw_help(f, &app, f.size());
+                    return;
+                }
+                AppState::Presenting => {
+                    ui::draw_presentation(f, &app, f.size());
+                    return;
+                }
+                AppState::Sections => {
+                    ui::draw_song_list(f, &mut app, layout[0]);
+                    ui::draw_song(f, &app, layout[1]);
+                    ui::draw_sections(f, &app, layout[1]);
+                    return;
+                }
             }
             ui::draw_song(f, &app, layout[1]);
         })?;
@@ -121,19 +379,148 @@ fn main() -> Result<(), Box<dyn Error>> {
                 if key == app.config.keybinds.quit.to_key() {
                     break;
                 }
+                // Esc in the editor is also how unsaved changes are confirmed to be thrown
+                // away, so it doesn't only dismiss the notification asking for that
+                if key == Key::Esc && app.notification.is_some() {
+                    app.notification = None;
+                    if app.state != AppState::Editing {
+                        continue;
+                    }
+                }
                 match app.state {
                     AppState::Default => {
                         if key == app.config.keybinds.search.to_key() {
                             app.state = AppState::Searching
                         } else if key == app.config.keybinds.transpose.to_key() {
                             app.state = AppState::Transposing;
+                        } else if key == app.config.keybinds.rename.to_key() {
+                            app.open_prompt(FileAction::Rename);
+                        } else if key == app.config.keybinds.move_file.to_key() {
+                            app.open_prompt(FileAction::Move);
+                        } else if key == app.config.keybinds.duplicate.to_key() {
+                            app.open_prompt(FileAction::Duplicate);
+                        } else if key == app.config.keybinds.delete.to_key() {
+                            app.open_prompt(FileAction::Delete);
+                        } else if key == app.config.keybinds.undo.to_key() {
+                            // A failed undo stays on the stack, so it can be retried once
+                            // whatever is in the way has been moved
+                            if let Err(e) = app.undo() {
+                                app.notify(format!("Couldn't undo: {}", e));
+                            }
+                        } else if key == app.config.keybinds.notes.to_key() {
+                            app.show_notes = !app.show_notes;
+                        } else if key == app.config.keybinds.source_comments.to_key() {
+                            app.show_source_comments = !app.show_source_comments;
+                        } else if key == app.config.keybinds.diagnostics.to_key() {
+                            app.show_diagnostics = !app.show_diagnostics;
+                        } else if key == app.config.keybinds.chord_diagrams.to_key() {
+                            app.show_chord_diagrams = !app.show_chord_diagrams;
+                        } else if key == app.config.keybinds.all_songs.to_key() {
+                            app.toggle_view(View::AllSongs);
+                        } else if key == app.config.keybinds.favorites.to_key() {
+                            app.toggle_view(View::Favorites);
+                        } else if key == app.config.keybinds.tags.to_key() {
+                            app.toggle_view(View::Tags);
+                        } else if key == app.config.keybinds.star.to_key() {
+                            app.toggle_favorite();
+                        } else if key == app.config.keybinds.sort.to_key() {
+                            app.sort = app.sort.next();
+                            app.sort_folders();
+                        } else if key == app.config.keybinds.nashville.to_key() {
+                            app.nashville = !app.nashville;
+                        } else if key == app.config.keybinds.dual_chords.to_key() {
+                            app.dual_chords = !app.dual_chords;
+                        } else if key == app.config.keybinds.roman_numerals.to_key() {
+                            app.roman_numerals = !app.roman_numerals;
+                        } else if key == app.config.keybinds.lyrics_only.to_key() {
+                            app.show_only(!app.lyrics_only, false);
+                        } else if key == app.config.keybinds.sidebar.to_key() {
+                            app.hide_sidebar = !app.hide_sidebar;
+                        } else if key == app.config.keybinds.sidebar_wider.to_key() {
+                            app.resize_sidebar(2);
+                        } else if key == app.config.keybinds.sidebar_narrower.to_key() {
+                            app.resize_sidebar(-2);
+                        } else if key == app.config.keybinds.auto_columns.to_key() {
+                            // Not with the other column keys, it would fire while typing a
+                            // search
+                            app.config.columns = None;
+                        } else if key == app.config.keybinds.next_theme.to_key() {
+                            app.next_theme();
+                        } else if key == app.config.keybinds.setlist.to_key() {
+                            app.toggle_setlist();
+                        } else if key == app.config.keybinds.next_song.to_key() {
+                            app.setlist_step(true);
+                        } else if key == app.config.keybinds.previous_song.to_key() {
+                            app.setlist_step(false);
+                        } else if key == app.config.keybinds.save_queue.to_key() {
+                            app.save_queue();
+                        } else if key == app.config.keybinds.next_section.to_key() {
+                            app.jump_section(true);
+                        } else if key == app.config.keybinds.previous_section.to_key() {
+                            app.jump_section(false);
+                        } else if key == app.config.keybinds.sections.to_key() {
+                            app.open_sections();
+                        } else if key == app.config.keybinds.new_tab.to_key() {
+                            app.open_in_new_tab();
+                        } else if key == app.config.keybinds.next_tab.to_key() {
+                            app.cycle_tabs(true);
+                        } else if key == app.config.keybinds.previous_tab.to_key() {
+                            app.cycle_tabs(false);
+                        } else if key == app.config.keybinds.close_tab.to_key() {
+                            app.close_tab();
+                        } else if key == app.config.keybinds.split.to_key() {
+                            app.toggle_split();
+                        } else if key == app.config.keybinds.split_focus.to_key() {
+                            app.switch_split_focus();
+                        } else if key == app.config.keybinds.present.to_key() {
+                            app.start_presenting();
+                        } else if key == app.config.keybinds.help.to_key() {
+                            app.state = AppState::Help;
+                        } else if key == app.config.keybinds.chords_only.to_key() {
+                            app.show_only(false, !app.chords_only);
+                        } else if key == app.config.keybinds.capo_suggestion.to_key() {
+                            app.config.capo_suggestion = !app.config.capo_suggestion;
+                        } else if key == app.config.keybinds.stats.to_key() {
+                            app.open_stats(false);
+                        } else if key == app.config.keybinds.lint.to_key() {
+                            app.open_lints();
+                        } else if key == app.config.keybinds.metadata.to_key() {
+                            app.open_metadata();
+                        } else if key == app.config.keybinds.quick_edit.to_key() {
+                            app.open_editor();
+                        } else if key == app.config.keybinds.edit.to_key() {
+                            // Fall back to the built-in editor when no editor is configured
+                            let editor = env::var("VISUAL").or_else(|_| env::var("EDITOR"));
+                            if editor.is_err() {
+                                app.open_editor();
+                            } else if let Some(path) = app.song_path() {
+                                events.pause();
+                                raw_mode.suspend_raw_mode()?;
+                                term.show_cursor()?;
+                                let result = edit_file(&path);
+                                raw_mode.activate_raw_mode()?;
+                                term.hide_cursor()?;
+                                term.clear()?;
+                                events.resume();
+                                match result {
+                                    Ok(()) => app.reload_song(),
+                                    Err(e) => {
+                                        app.notify(format!("Couldn't open the editor: {}", e))
+                                    }
+                                }
+                            }
                         }
                         keybinds_songlist(&key, &mut app);
                         keybinds_song(&key, &mut app);
+                        keybinds_transpose(&key, &mut app);
+                        keybinds_scroll(&key, &mut app);
                     }
                     AppState::Searching => {
                         if key == Key::Esc {
                             app.state = AppState::Default
+                        } else if key == app.config.keybinds.regex_search.to_key() {
+                            app.regex_search = !app.regex_search;
+                            app.search();
                         }
                         match key {
                             Key::Char(c) => match c {
@@ -154,28 +541,200 @@ fn main() -> Result<(), Box<dyn Error>> {
                     }
                     AppState::Transposing => {
                         if key == Key::Esc {
+                            app.key_input.clear();
                             app.state = AppState::Default
                         } else if key == app.config.keybinds.search.to_key() {
                             app.state = AppState::Searching
                         } else if key == app.config.keybinds.next.to_key() {
-                            if let Some(song) = &mut app.song {
-                                song.change_key(1)
-                            }
+                            app.transpose(1)
                         } else if key == app.config.keybinds.back.to_key() {
-                            if let Some(song) = &mut app.song {
-                                song.change_key(-1)
+                            app.transpose(-1)
+                        } else {
+                            match key {
+                                Key::Char('\n') => {
+                                    if let Some((key, _)) = parse_key(&app.key_input) {
+                                        app.transpose_to(key);
+                                        app.key_input.clear();
+                                    }
+                                }
+                                Key::Char(c) if c.is_ascii_alphabetic() || c == '#' => {
+                                    app.key_input.push(c)
+                                }
+                                Key::Backspace => {
+                                    app.key_input.pop();
+                                }
+                                _ => (),
                             }
                         }
                         keybinds_song(&key, &mut app);
+                        keybinds_transpose(&key, &mut app);
+                    }
+                    AppState::Editing => {
+                        if key == app.config.keybinds.save.to_key() {
+                            if let Some(editor) = &mut app.editor {
+                                if let Err(e) = editor.save() {
+                                    app.notify(format!("Couldn't save: {}", e));
+                                }
+                            }
+                        } else if key == Key::Esc {
+                            app.close_editor();
+                        } else if let Some(editor) = &mut app.editor {
+                            keybinds_editor(&key, editor);
+                        }
+                    }
+                    AppState::Prompting => {
+                        let delete = matches!(
+                            &app.prompt,
+                            Some(prompt) if prompt.action == FileAction::Delete
+                        );
+                        match key {
+                            Key::Esc => app.cancel_prompt(),
+                            Key::Char('\n') if !delete => app.confirm_prompt(),
+                            Key::Char('y') if delete => app.confirm_prompt(),
+                            Key::Char('n') if delete => app.cancel_prompt(),
+                            Key::Char(c) if !delete => {
+                                if let Some(prompt) = &mut app.prompt {
+                                    prompt.input.push(c)
+                                }
+                            }
+                            Key::Backspace => {
+                                if let Some(prompt) = &mut app.prompt {
+                                    prompt.input.pop();
+                                }
+                            }
+                            _ => (),
+                        }
+                    }
+                    AppState::Stats => match key {
+                        Key::Esc => app.state = AppState::Default,
+                        Key::Char('\t') => app.open_stats(!app.stats_playlist),
+                        _ => (),
+                    },
+                    AppState::Presenting => match key {
+                        Key::Esc => app.state = AppState::Default,
+                        Key::Char(' ') | Key::Char('\n') => app.next_slide(true),
+                        Key::Backspace => app.next_slide(false),
+                        _ if key == app.config.keybinds.down.to_key()
+                            || key == app.config.keybinds.next.to_key() =>
+                        {
+                            app.next_slide(true)
+                        }
+                        _ if key == app.config.keybinds.up.to_key()
+                            || key == app.config.keybinds.back.to_key() =>
+                        {
+                            app.next_slide(false)
+                        }
+                        _ => (),
+                    },
+                    AppState::Sections => {
+                        let count = app.sections().len();
+                        match key {
+                            Key::Esc => app.state = AppState::Default,
+                            Key::Char('\n') => app.jump_to_section(app.section_selected),
+                            Key::Char(c @ '1'..='9') => {
+                                app.jump_to_section(c as usize - '1' as usize)
+                            }
+                            _ if key == app.config.keybinds.up.to_key() => {
+                                app.section_selected = app.section_selected.saturating_sub(1)
+                            }
+                            _ if key == app.config.keybinds.down.to_key() => {
+                                app.section_selected =
+                                    (app.section_selected + 1).min(count.saturating_sub(1))
+                            }
+                            _ => (),
+                        }
                     }
+                    AppState::Help => {
+                        if key == Key::Esc || key == app.config.keybinds.help.to_key() {
+                            app.state = AppState::Default;
+                        }
+                    }
+                    AppState::Linting => match key {
+                        Key::Esc => app.state = AppState::Default,
+                        Key::Char('f') => {
+                            if let Err(e) = app.fix_lints() {
+                                app.notify(format!("Couldn't fix the song: {}", e));
+                            }
+                        }
+                        _ => (),
+                    },
+                    AppState::EditingMetadata => {
+                        if key == Key::Esc {
+                            app.metadata = None;
+                            app.state = AppState::Default;
+                        } else if key == Key::Char('\n') {
+                            if let Err(e) = app.save_metadata() {
+                                app.notify(format!("Couldn't save: {}", e));
+                            }
+                        } else if let Some(form) = &mut app.metadata {
+                            match key {
+                                Key::Down | Key::Char('\t') => form.next(),
+                                Key::Up | Key::BackTab => form.previous(),
+                                Key::Char(c) => form.input().push(c),
+                                Key::Backspace => {
+                                    form.input().pop();
+                                }
+                                _ => (),
+                            }
+                        }
+                    }
+                }
+            }
+            Event::Tick => {
+                app.auto_scroll_tick(Instant::now());
+                app.expire_notification(Instant::now());
+                // Changes made during a scan wait for it to finish, so scans don't pile up
+                if !changed.is_empty() && !app.indexing {
+                    app.library_changed(&changed);
+                    changed.clear();
+                    index_in_background(&events, &app.config);
                 }
             }
-            Event::Tick => (),
+            Event::Changed(paths) => changed.extend(paths),
+            Event::Indexed(index) => app.set_index(index),
+            // The layout is worked out again for the new size when the loop redraws
+            Event::Resize => term.autoresize()?,
         }
     }
+
+    if let Some(path) = &state_path {
+        app.saved.save(path)?;
+    }
     Ok(())
 }
 
+/// Scans the library on another thread, sending the index back as `Event::Indexed`
+fn index_in_background(events: &Events, config: &Config) {
+    let indexer = events.sender();
+    let config = config.clone();
+    thread::spawn(move || {
+        let _ = indexer.send(Event::Indexed(Index::update(&config)));
+    });
+}
+
+/// Opens `path` in the user's editor and waits for it to exit. An editor that exits
+/// with an error counts as failing to edit the file.
+fn edit_file(path: &Path) -> io::Result<()> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"));
+    // Allow editors with arguments, like `code --wait`
+    let mut args = editor.split_whitespace();
+    let program = args.next().unwrap_or("vi");
+    let status = process::Command::new(program)
+        .args(args)
+        .arg(path)
+        .status()
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", program, e)))?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(format!(
+            "{} exited with {}",
+            program, status
+        ))),
+    }
+}
+
 fn keybinds_songlist(key: &Key, app: &mut App) {
     if key == &app.config.keybinds.down.to_key() {
         app.get_nav_mut().forward(1);
@@ -204,11 +763,77 @@ fn keybinds_songlist(key: &Key, app: &mut App) {
     }
 }
 
+fn keybinds_editor(key: &Key, editor: &mut Editor) {
+    match key {
+        Key::Char('\n') => editor.newline(),
+        Key::Char('[') => editor.insert_chord(),
+        Key::Char(']') => editor.close_chord(),
+        Key::Char(c) => editor.insert(*c),
+        Key::Backspace => editor.backspace(),
+        Key::Delete => editor.delete(),
+        Key::Left => editor.left(),
+        Key::Right => editor.right(),
+        Key::Up => editor.up(),
+        Key::Down => editor.down(),
+        Key::Home => editor.home(),
+        Key::End => editor.end(),
+        _ => (),
+    }
+}
+
+/// Keybinds that transpose the open song, these aren't available while typing
+fn keybinds_transpose(key: &Key, app: &mut App) {
+    if key == &app.config.keybinds.transpose_up.to_key() {
+        app.transpose(1);
+    } else if key == &app.config.keybinds.transpose_down.to_key() {
+        app.transpose(-1);
+    } else if key == &app.config.keybinds.transpose_reset.to_key() {
+        app.reset_transposition();
+    }
+}
+
+/// Scrolls the open song, pages are as high as the terminal
+fn keybinds_scroll(key: &Key, app: &mut App) {
+    let page = match termion::terminal_size() {
+        // Without the margin and borders around the song
+        Ok((_, height)) => height.saturating_sub(4) as isize,
+        Err(_) => 20,
+    };
+    if key == &app.config.keybinds.scroll_down.to_key() {
+        app.scroll_by(1);
+    } else if key == &app.config.keybinds.scroll_up.to_key() {
+        app.scroll_by(-1);
+    } else if key == &app.config.keybinds.half_page_down.to_key() {
+        app.scroll_rows(page / 2);
+    } else if key == &app.config.keybinds.half_page_up.to_key() {
+        app.scroll_rows(-page / 2);
+    } else if key == &app.config.keybinds.page_down.to_key() {
+        match app.config.paging {
+            true => app.flip_page(true),
+            false => app.scroll_rows(page),
+        }
+    } else if key == &app.config.keybinds.page_up.to_key() {
+        match app.config.paging {
+            true => app.flip_page(false),
+            false => app.scroll_rows(-page),
+        }
+    } else if key == &app.config.keybinds.paging.to_key() {
+        app.config.paging = !app.config.paging;
+    } else if key == &app.config.keybinds.gutter.to_key() {
+        app.config.gutter = app.config.gutter.next();
+    } else if key == &app.config.keybinds.auto_scroll.to_key() {
+        app.toggle_auto_scroll();
+    } else if key == &app.config.keybinds.scroll_faster.to_key() {
+        app.change_scroll_speed(1.25);
+    } else if key == &app.config.keybinds.scroll_slower.to_key() {
+        app.change_scroll_speed(0.8);
+    }
+}
+
 fn keybinds_song(key: &Key, app: &mut App) {
-    if key == &app.config.keybinds.col_size_inc.to_key() {
-        app.config.extra_column_size += 1;
-    } else if key == &app.config.keybinds.col_size_dec.to_key() && app.config.extra_column_size > 0
-    {
-        app.config.extra_column_size -= 1;
+    if key == &app.config.keybinds.more_columns.to_key() {
+        app.change_columns(1);
+    } else if key == &app.config.keybinds.fewer_columns.to_key() {
+        app.change_columns(-1);
     }
 }
@@ -1,33 +1,97 @@
 extern crate rust_music_theory as rustmt;
 
-use crate::{app::FileType, conf::Theme};
+use crate::{
+    app::FileType,
+    conf::{Accidentals, Theme},
+    convert,
+    layout::LineMetrics,
+};
 use lazy_static::lazy_static;
-use regex::{Captures, Regex};
+use regex::Regex;
 use rustmt::{interval::Interval, note::PitchClass};
-use std::cmp::Ordering;
+use std::{cmp::Ordering, collections::HashMap, error::Error, fmt, ops::Range};
 use tui::text::{Span, Spans};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 lazy_static! {
+    /// Theme lines are formatted with to measure them, the styles don't change their size
+    static ref MEASURING_THEME: Theme = Theme::default();
     static ref RE_NEWLINES: Regex = Regex::new(r"(\n\r?|\r\n?)").unwrap();
     static ref RE_TAGS: Regex = Regex::new(r"\{([^\{\}\n]+?)(?::([^\{\}\n]+))?\}\n?").unwrap();
-    static ref RE_TITLE: Regex = Regex::new(r"\{(?:title|t)(?::([^\{\}\n]+))?\}\n?").unwrap();
+    static ref RE_TITLE: Regex = Regex::new(r"\{(?i:title|t)(?::([^\{\}\n]+))?\}\n?").unwrap();
     static ref RE_SUBTITLE: Regex =
-        Regex::new(r"\{(?:subtitle|st)(?::([^\{\}\n]+))?\}\n?").unwrap();
+        Regex::new(r"\{(?i:subtitle|st)(?::([^\{\}\n]+))?\}\n?").unwrap();
     static ref RE_CHORDS: Regex = Regex::new(r"\[([^\n\[\]]*)\]").unwrap();
     static ref RE_ROOT_NOTE: Regex = Regex::new(r"[ABCDEFG][b#]?").unwrap();
+    // A chord's root, quality, extension, alterations and bass note
+    static ref RE_VALID_CHORD: Regex = Regex::new(
+        r"^[A-G][b#]?(?:maj|min|m|M|dim|aug|°|ø|\+|-|Δ)?(?:6/9|5|6|7|9|11|13)?(?:sus[24]?|add[b#]?(?:9|11|13|2|4|6)|[b#](?:5|9|11|13)|no[35]|alt|maj(?:7|9|11|13)|\([^()]*\))*(?:/[A-G][b#]?)?$"
+    )
+    .unwrap();
+    // A chord's root, what follows it and the bass note after a slash
+    static ref RE_SLASH_CHORD: Regex =
+        Regex::new(r"^([ABCDEFG][b#]?)([^/]*)(?:/([ABCDEFG][b#]?))?$").unwrap();
+    static ref RE_KEY: Regex = Regex::new(r"^([A-Ga-g][b#]?) *(.*)$").unwrap();
     static ref RE_SPACES: Regex = Regex::new(r" +").unwrap();
-    static ref RE_BLOCKS: Regex = Regex::new(r"[^ \n]+ *").unwrap();
+    // Chords can contain spaces, so a block only ends at a space outside of brackets
+    static ref RE_BLOCKS: Regex = Regex::new(r"(?:\[[^\[\]\n]*\]|[^ \n])+ *").unwrap();
+    static ref RE_GRID_BAR: Regex = Regex::new(r"^(?:\|\||\|\.|\|:|:\|:|:\||\||%%?)$").unwrap();
     static ref RE_SONG_TRANSPOSITION: Regex = Regex::new(r" \[([ABCDEFG][b#]?)\]").unwrap();
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum SongString {
     Chord(String),
+    /// Text in brackets that isn't a chord, like `[Gsus44]`. It's shown as written.
+    InvalidChord(String),
+    /// Text written above the lyrics like a chord, from `[*Riff 1]`
+    Annotation(String),
+    /// Roman numeral of the chord before it, drawn on a line of its own below the chords
+    Numeral(String),
     Text(String),
     Comment(String),
+    /// Comment from `{comment_italic}`
+    CommentItalic(String),
+    /// Comment from `{comment_box}`
+    CommentBox(String),
+    /// Section header, like `Verse 2` from `{sov: Verse 2}`
+    Label(String),
+    /// Line of a tab section, kept exactly as written
+    Tab(String),
+    /// Cell of a grid section, padded to `width` so the measures line up
+    Grid {
+        cell: GridCell,
+        width: usize,
+    },
 }
 
-#[derive(Debug, Clone)]
+/// Single token of a grid section line like `|: G . . . | C . . . :|`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum GridCell {
+    /// Bar line or repeat sign
+    Bar(String),
+    Chord(String),
+    /// Beat without a chord change, like `.` or `/`
+    Beat(String),
+}
+
+impl GridCell {
+    fn parse(token: &str, transposition: i32) -> Self {
+        match token {
+            bar if RE_GRID_BAR.is_match(bar) => GridCell::Bar(bar.to_string()),
+            "." | "/" | "~" => GridCell::Beat(token.to_string()),
+            chord => GridCell::Chord(transpose_chord(chord, semitones(transposition))),
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        match self {
+            GridCell::Bar(s) | GridCell::Chord(s) | GridCell::Beat(s) => s,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct SongBlock(Vec<SongString>);
 
 impl SongBlock {
@@ -35,85 +99,293 @@ impl SongBlock {
         SongBlock(
             Song::regex_split_keep(&RE_CHORDS, input)
                 .iter()
-                .map(|part| match RE_CHORDS.captures(&part) {
-                    Some(chord) => {
-                        let chord = chord.get(1).unwrap().as_str();
-                        let transposed = RE_ROOT_NOTE.replace_all(chord, |caps: &Captures| {
-                            PitchClass::from_interval(
-                                PitchClass::from_str(caps.get(0).unwrap().as_str()).unwrap(),
-                                Interval::from_semitone(((transposition + 12) % 12) as u8).unwrap(),
-                            )
-                            .to_string()
-                        });
-                        SongString::Chord(transposed.to_string())
-                    }
+                .map(|part| match RE_CHORDS.captures(part) {
+                    Some(chord) => match chord.get(1).unwrap().as_str() {
+                        annotation if annotation.starts_with('*') => {
+                            SongString::Annotation(annotation[1..].to_string())
+                        }
+                        chord if !is_chord(chord) => SongString::InvalidChord(chord.to_string()),
+                        chord => {
+                            SongString::Chord(transpose_chord(chord, semitones(transposition)))
+                        }
+                    },
                     None => SongString::Text(part.to_string()),
                 })
                 .collect(),
         )
     }
 
+    /// Splits the block between wide characters, like those of Chinese or Japanese text,
+    /// which can be wrapped without a space between them. Chords stay with the text after
+    /// them.
+    fn split_wide(&self) -> Vec<SongBlock> {
+        let mut blocks = vec![];
+        let mut current: Vec<SongString> = vec![];
+        let mut after_wide = false;
+        for string in &self.0 {
+            let text = match string {
+                SongString::Text(text) => text,
+                other => {
+                    if after_wide {
+                        blocks.push(SongBlock(std::mem::take(&mut current)));
+                    }
+                    after_wide = false;
+                    current.push(other.clone());
+                    continue;
+                }
+            };
+            let mut piece = String::new();
+            for c in text.chars() {
+                let wide = c.width().unwrap_or(0) > 1;
+                let has_text =
+                    !piece.is_empty() || current.iter().any(|s| matches!(s, SongString::Text(_)));
+                if (wide || after_wide) && has_text {
+                    if !piece.is_empty() {
+                        current.push(SongString::Text(std::mem::take(&mut piece)));
+                    }
+                    blocks.push(SongBlock(std::mem::take(&mut current)));
+                }
+                piece.push(c);
+                after_wide = wide;
+            }
+            if !piece.is_empty() {
+                current.push(SongString::Text(piece));
+            }
+        }
+        if !current.is_empty() {
+            blocks.push(SongBlock(current));
+        }
+        blocks
+    }
+
+    /// Splits a block wider than `width` into pieces that fit, breaking its word with a
+    /// hyphen. Chords stay above the part of the word they were on.
+    fn hyphenate(&self, width: usize) -> Vec<SongBlock> {
+        let has_text = |strings: &[SongString]| {
+            strings
+                .iter()
+                .any(|s| matches!(s, SongString::Text(t) if !t.is_empty()))
+        };
+        if width < 2 || self.width() <= width || !has_text(&self.0) {
+            return vec![self.clone()];
+        }
+        let fits = |strings: &[SongString], extra: SongString, hyphen: usize| {
+            let mut strings = strings.to_vec();
+            strings.push(extra);
+            SongBlock(strings).width() + hyphen <= width
+        };
+        let mut blocks = vec![];
+        let mut current: Vec<SongString> = vec![];
+        for string in &self.0 {
+            let text = match string {
+                SongString::Text(text) => text,
+                other => {
+                    if !fits(&current, other.clone(), 0) && has_text(&current) {
+                        if let Some(SongString::Text(last)) = current.last_mut() {
+                            if last.ends_with(char::is_alphanumeric) {
+                                last.push('-');
+                            }
+                        }
+                        blocks.push(SongBlock(std::mem::take(&mut current)));
+                    }
+                    current.push(other.clone());
+                    continue;
+                }
+            };
+            let mut piece = String::new();
+            for c in text.chars() {
+                let mut longer = piece.clone();
+                longer.push(c);
+                let can_break = !piece.is_empty() || has_text(&current);
+                if !c.is_whitespace()
+                    && can_break
+                    && !fits(&current, SongString::Text(longer.clone()), 1)
+                {
+                    piece.push('-');
+                    current.push(SongString::Text(std::mem::take(&mut piece)));
+                    blocks.push(SongBlock(std::mem::take(&mut current)));
+                    longer = c.to_string();
+                }
+                piece = longer;
+            }
+            if !piece.is_empty() {
+                current.push(SongString::Text(piece));
+            }
+        }
+        if !current.is_empty() {
+            blocks.push(SongBlock(current));
+        }
+        blocks
+    }
+
     pub fn from_comment(c: &str) -> Self {
         SongBlock(vec![SongString::Comment(c.to_owned())])
     }
 
+    pub fn from_label(label: &str) -> Self {
+        SongBlock(vec![SongString::Label(label.to_owned())])
+    }
+
     pub fn width(&self) -> usize {
         let mut chords: usize = 0;
         let mut text: usize = 0;
         self.0.iter().for_each(|songstring| match songstring {
-            SongString::Chord(c) => {
+            SongString::Chord(c) | SongString::InvalidChord(c) | SongString::Annotation(c) => {
                 match text.cmp(&chords) {
                     Ordering::Less => text = chords,
                     Ordering::Greater => chords = text,
                     Ordering::Equal => (),
                 }
-                chords += c.chars().count() + 1;
+                chords += c.width() + 1;
             }
             SongString::Text(t) => {
-                text += t.chars().count();
+                text += t.width();
+            }
+            // Chords are padded to fit their numeral, so it takes no space of its own
+            SongString::Numeral(_) => (),
+            SongString::Comment(c)
+            | SongString::CommentItalic(c)
+            | SongString::CommentBox(c)
+            | SongString::Label(c)
+            | SongString::Tab(c) => {
+                text += c.width();
             }
-            SongString::Comment(c) => {
-                text += c.chars().count();
+            SongString::Grid { cell, width } => {
+                text += cell.as_str().width().max(*width) + 1;
             }
         });
         std::cmp::max(chords, text)
     }
 }
 
-#[derive(Debug, Default, Clone)]
+/// Kind of section a line is part of
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub enum Section {
+    #[default]
+    None,
+    Chorus,
+    Verse,
+    Bridge,
+    Tab,
+    Grid,
+    /// Any other `{start_of_...}` environment, by name
+    Other(String),
+}
+
+impl Section {
+    /// Marker drawn before every line of the section
+    fn prefix(&self) -> &'static str {
+        match self {
+            Section::None | Section::Verse | Section::Tab | Section::Grid => "",
+            Section::Chorus => "| ",
+            Section::Bridge => "  ",
+            Section::Other(_) => ": ",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
 pub struct SongLine {
     blocks: Vec<SongBlock>,
-    chorus: bool,
+    section: Section,
+    /// Whether the line starts a new column, from `{column_break}` or `{new_page}`
+    column_break: bool,
 }
 
 impl SongLine {
-    pub fn from(blocks: Vec<SongBlock>, chorus: bool) -> Self {
-        SongLine { blocks, chorus }
+    pub fn from(blocks: Vec<SongBlock>, section: Section) -> Self {
+        SongLine {
+            blocks,
+            section,
+            column_break: false,
+        }
     }
 
     pub fn width(&self) -> usize {
-        self.format(&Theme::default())
+        self.metrics().width
+    }
+
+    /// Measures the rows `format` lays the line out in
+    pub fn metrics(&self) -> LineMetrics {
+        let rows = self.format(&MEASURING_THEME);
+        let width = rows.iter().map(Spans::width).max().unwrap_or(0);
+        let mut metrics = LineMetrics::new(width, rows.len());
+        metrics.column_break = self.column_break;
+        metrics.keep_with_next = self.is_chord_line();
+        metrics
+    }
+
+    /// Whether the line only holds chords, either as `[G] [D]` or as a chord line of a
+    /// chords-over-lyrics chart, so it belongs with the lyrics below it
+    pub fn is_chord_line(&self) -> bool {
+        let mut text = String::new();
+        let mut has_chords = false;
+        for songstring in self.blocks.iter().flat_map(|block| block.0.iter()) {
+            match songstring {
+                SongString::Chord(_) | SongString::InvalidChord(_) => has_chords = true,
+                SongString::Text(t) => text.push_str(t),
+                _ => return false,
+            }
+        }
+        match has_chords {
+            true => text.trim().is_empty(),
+            false => convert::is_chord_line(&text),
+        }
+    }
+
+    /// Lyrics, comments and labels of the line without its chords or section marker
+    pub fn text(&self) -> String {
+        self.blocks
             .iter()
-            .map(|s| s.width())
-            .max()
-            .unwrap_or(0)
+            .flat_map(|block| block.0.iter())
+            .filter_map(|songstring| match songstring {
+                SongString::Text(t)
+                | SongString::Comment(t)
+                | SongString::CommentItalic(t)
+                | SongString::CommentBox(t)
+                | SongString::Label(t)
+                | SongString::Tab(t) => Some(t.as_str()),
+                _ => None,
+            })
+            .collect()
     }
 
-    pub fn height(&self) -> usize {
-        self.format(&Theme::default()).len()
+    /// Text of a line that only holds a label or comment, like the `Chorus` of
+    /// `{start_of_chorus: Chorus}` or `{c: Chorus}`
+    pub fn header(&self) -> Option<String> {
+        let mut header = String::new();
+        for songstring in self.blocks.iter().flat_map(|block| block.0.iter()) {
+            match songstring {
+                SongString::Label(t)
+                | SongString::Comment(t)
+                | SongString::CommentItalic(t)
+                | SongString::CommentBox(t) => header.push_str(t),
+                _ => return None,
+            }
+        }
+        let header = header.trim();
+        (!header.is_empty()).then(|| header.to_string())
     }
 
     pub fn format<'a>(&self, theme: &Theme) -> Vec<Spans<'a>> {
         let mut has_chords = false;
         let mut chords: Vec<Span<'a>> = vec![];
         let mut text: Vec<Span<'a>> = vec![];
-        if self.chorus {
-            chords.push(Span::styled("| ", theme.comment.to_style()));
-            text.push(Span::styled("| ", theme.comment.to_style()));
+        let mut numerals: Vec<Span<'a>> = vec![];
+        // Where the last chord starts, for the numeral below it
+        let mut chord_start = 0;
+        let prefix = self.section.prefix();
+        if !prefix.is_empty() {
+            let style = match self.section {
+                Section::Chorus => theme.comment.to_style(),
+                _ => theme.section.to_style(),
+            };
+            chords.push(Span::styled(prefix, style));
+            text.push(Span::styled(prefix, style));
         }
         self.blocks.iter().for_each(|block| {
             block.0.iter().for_each(|songstring| match songstring {
-                SongString::Chord(c) => {
+                SongString::Chord(c) | SongString::InvalidChord(c) | SongString::Annotation(c) => {
                     has_chords = true;
                     let text_len: usize = text.iter().map(Span::width).sum();
                     let chords_len: usize = chords.iter().map(Span::width).sum();
@@ -133,7 +405,13 @@ impl SongLine {
                             chords.push(Span::from(" ".repeat(text_len - chords_len)))
                         }
                     }
-                    chords.push(Span::styled(c.to_owned() + " ", theme.chord.to_style()));
+                    chord_start = chords.iter().map(Span::width).sum();
+                    let style = match songstring {
+                        SongString::Annotation(_) => theme.annotation.to_style(),
+                        SongString::InvalidChord(_) => theme.warning.to_style(),
+                        _ => theme.chord.to_style(),
+                    };
+                    chords.push(Span::styled(c.to_owned() + " ", style));
                 }
                 SongString::Text(t) => {
                     text.push(Span::styled(t.to_owned(), theme.lyrics.to_style()));
@@ -141,189 +419,1027 @@ impl SongLine {
                 SongString::Comment(c) => {
                     text.push(Span::styled(c.to_owned(), theme.comment.to_style()));
                 }
+                SongString::CommentItalic(c) => {
+                    text.push(Span::styled(c.to_owned(), theme.comment_italic.to_style()));
+                }
+                SongString::CommentBox(c) => {
+                    text.push(Span::styled(c.to_owned(), theme.comment_box.to_style()));
+                }
+                SongString::Label(l) => {
+                    text.push(Span::styled(l.to_owned(), theme.section.to_style()));
+                }
+                SongString::Tab(t) => {
+                    text.push(Span::styled(t.to_owned(), theme.lyrics.to_style()));
+                }
+                SongString::Numeral(n) => {
+                    let numerals_len: usize = numerals.iter().map(Span::width).sum();
+                    numerals.push(Span::from(
+                        " ".repeat(chord_start.saturating_sub(numerals_len)),
+                    ));
+                    numerals.push(Span::styled(n.to_owned(), theme.numeral.to_style()));
+                }
+                SongString::Grid { cell, width } => {
+                    let style = match cell {
+                        GridCell::Bar(_) => theme.bar.to_style(),
+                        GridCell::Chord(_) => theme.chord.to_style(),
+                        GridCell::Beat(_) => theme.lyrics.to_style(),
+                    };
+                    let padding = width.saturating_sub(cell.as_str().width());
+                    text.push(Span::styled(
+                        format!("{}{} ", cell.as_str(), " ".repeat(padding)),
+                        style,
+                    ));
+                }
             })
         });
         let mut formatted = vec![];
         if has_chords {
             formatted.push(Spans::from(chords))
         }
+        if !numerals.is_empty() {
+            formatted.push(Spans::from(numerals))
+        }
         formatted.push(Spans::from(text));
+        // The style of the section goes under the styles of the chords and lyrics
+        let section = match self.section {
+            Section::None => return formatted,
+            Section::Chorus => theme.chorus.to_style(),
+            Section::Verse => theme.verse.to_style(),
+            Section::Bridge => theme.bridge.to_style(),
+            Section::Tab | Section::Grid | Section::Other(_) => theme.other_section.to_style(),
+        };
+        for span in formatted.iter_mut().flat_map(|spans| spans.0.iter_mut()) {
+            span.style = section.patch(span.style);
+        }
         formatted
     }
 
     pub fn wrap(&self, max_width: usize) -> Vec<Self> {
-        if max_width >= self.width() {
+        // Wrapping a tab would misalign its strings, so it gets cut off instead
+        if max_width >= self.width() || self.section == Section::Tab {
             return vec![self.clone()];
         }
-        let chorus_width = match self.chorus {
-            true => 2,
-            false => 0,
-        };
+        let prefix_width = self.section.prefix().width();
 
         let mut total_width = 0;
         let mut wrapped_line = vec![];
         let mut wrapped_lines = vec![];
 
-        for block in self.blocks.iter() {
+        let available = max_width.saturating_sub(prefix_width);
+        for block in self
+            .blocks
+            .iter()
+            .flat_map(SongBlock::split_wide)
+            .flat_map(|block| block.hyphenate(available))
+        {
             let block_width = block.width();
 
-            if total_width + block_width + chorus_width < max_width {
-                wrapped_line.push(block.clone());
-                total_width += block.width();
+            // A block wider than `max_width` is only left after hyphenating when it can't
+            // be split, it goes on a line of its own rather than leaving an empty line
+            // before it
+            if wrapped_line.is_empty() || total_width + block_width + prefix_width < max_width {
+                wrapped_line.push(block);
+                total_width += block_width;
             } else {
-                wrapped_lines.push(SongLine::from(wrapped_line, self.chorus));
-                wrapped_line = vec![block.clone()];
-                total_width = block.width();
+                wrapped_lines.push(SongLine::from(wrapped_line, self.section.clone()));
+                wrapped_line = vec![block];
+                total_width = block_width;
             }
         }
-        wrapped_lines.push(SongLine::from(wrapped_line, self.chorus));
+        wrapped_lines.push(SongLine::from(wrapped_line, self.section.clone()));
+        wrapped_lines[0].column_break = self.column_break;
 
         wrapped_lines
     }
 }
 
+/// Custom chord fingering from a `{define}` directive
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChordDefinition {
+    pub name: String,
+    /// Fret the diagram starts at, 1 being the nut
+    pub base_fret: u8,
+    /// Fret per string relative to `base_fret`, from the lowest string up. `Some(0)` is
+    /// an open string and `None` a muted one.
+    pub frets: Vec<Option<u8>>,
+}
+
+impl ChordDefinition {
+    /// Parses the value of `{define: Am base-fret 1 frets x 0 2 2 1 0}`. Fingers and
+    /// other trailing attributes are ignored.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut tokens = value.split_whitespace();
+        let name = tokens.next()?.to_string();
+        let mut base_fret = 1;
+        let mut frets = vec![];
+        let mut in_frets = false;
+        while let Some(token) = tokens.next() {
+            match token.to_lowercase().as_str() {
+                "base-fret" => {
+                    base_fret = tokens.next()?.parse().ok().filter(|fret| *fret > 0)?;
+                    in_frets = false;
+                }
+                "frets" => in_frets = true,
+                "x" | "n" | "-1" if in_frets => frets.push(None),
+                fret if in_frets => match fret.parse() {
+                    Ok(fret) => frets.push(Some(fret)),
+                    Err(_) => in_frets = false,
+                },
+                _ => (),
+            }
+        }
+        (!frets.is_empty()).then_some(ChordDefinition {
+            name,
+            base_fret,
+            frets,
+        })
+    }
+
+    /// Draws the chord as an ASCII chord box, with the name on top, open and muted
+    /// strings above the nut and the fingered frets marked with `*`
+    pub fn diagram(&self) -> Vec<String> {
+        let strings = self.frets.len();
+        let width = strings * 2 - 1;
+        let rows = self
+            .frets
+            .iter()
+            .flatten()
+            .copied()
+            .max()
+            .unwrap_or(0)
+            .max(4);
+
+        let mut diagram = vec![format!("{:^width$}", self.name, width = width)];
+        let markers: Vec<&str> = self
+            .frets
+            .iter()
+            .map(|fret| match fret {
+                None => "x",
+                Some(0) => "o",
+                _ => " ",
+            })
+            .collect();
+        diagram.push(markers.join(" "));
+        diagram.push(match self.base_fret {
+            1 => "=".repeat(width),
+            fret => format!("{} {}fr", "-".repeat(width), fret),
+        });
+        for row in 1..=rows {
+            let frets: Vec<&str> = self
+                .frets
+                .iter()
+                .map(|fret| match fret {
+                    Some(fret) if *fret == row => "*",
+                    _ => "|",
+                })
+                .collect();
+            diagram.push(frets.join(" "));
+        }
+        diagram
+    }
+}
+
+/// Information about a song from meta directives, which isn't part of the chart
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Metadata {
+    pub artist: Option<String>,
+    pub composer: Option<String>,
+    pub album: Option<String>,
+    pub year: Option<String>,
+    pub tempo: Option<String>,
+    pub time: Option<String>,
+    pub duration: Option<String>,
+    pub copyright: Option<String>,
+    pub ccli: Option<String>,
+}
+
+impl Metadata {
+    /// Names of the directives that are stored as metadata
+    const NAMES: [&'static str; 9] = [
+        "artist",
+        "composer",
+        "album",
+        "year",
+        "tempo",
+        "time",
+        "duration",
+        "copyright",
+        "ccli",
+    ];
+
+    /// Stores the value of the meta directive `name`, ignoring names that aren't one.
+    /// Repeated directives, like several artists, are joined.
+    fn set(&mut self, name: &str, value: &str) {
+        let field = match name {
+            "artist" => &mut self.artist,
+            "composer" => &mut self.composer,
+            "album" => &mut self.album,
+            "year" => &mut self.year,
+            "tempo" => &mut self.tempo,
+            "time" => &mut self.time,
+            "duration" => &mut self.duration,
+            "copyright" => &mut self.copyright,
+            "ccli" => &mut self.ccli,
+            _ => return,
+        };
+        match field {
+            Some(field) => {
+                field.push_str(", ");
+                field.push_str(value);
+            }
+            None => *field = Some(value.to_string()),
+        }
+    }
+
+    /// Labels and values of the fields that are set, in display order
+    pub fn fields(&self) -> Vec<(&'static str, &str)> {
+        [
+            ("Artist", &self.artist),
+            ("Composer", &self.composer),
+            ("Album", &self.album),
+            ("Year", &self.year),
+            ("Tempo", &self.tempo),
+            ("Time", &self.time),
+            ("Duration", &self.duration),
+            ("Copyright", &self.copyright),
+            ("CCLI", &self.ccli),
+        ]
+        .iter()
+        .filter_map(|(label, value)| Some((*label, value.as_deref()?)))
+        .collect()
+    }
+}
+
+/// Problem in a song file, lines and columns start at 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl Error for ParseError {}
+
 #[derive(Debug, Default, Clone)]
 pub struct Song {
     pub title: String,
     pub subtitle: String,
     pub transposition: i32,
+    /// Tonic of the key the song is shown in
     pub key: Option<PitchClass>,
+    pub mode: Mode,
     pub content: Vec<SongLine>,
+    /// Encoding the song file was converted from, if it wasn't UTF-8
+    pub converted_from: Option<&'static str>,
+    /// Performance notes from `{x_notes}` directives and blocks, and the sidecar notes
+    /// file, which aren't part of the chart itself
+    pub notes: Vec<String>,
+    /// Chords declared with `{define}`, in order
+    pub definitions: Vec<ChordDefinition>,
+    pub metadata: Metadata,
+    /// Fret of the capo from `{capo}`, the chords are shown as played with it on
+    pub capo: Option<u8>,
+    /// Lines starting with `#` and their line numbers, these are comments in the song
+    /// file that aren't shown in the song
+    pub source_comments: Vec<(usize, String)>,
+    /// Text in brackets that isn't a chord and the line it's on
+    pub invalid_chords: Vec<(usize, String)>,
+    /// Tags from `{tag}` directives, like `christmas` or `hymn`
+    pub tags: Vec<String>,
+    /// How transposed chords are spelled
+    pub accidentals: Accidentals,
+    /// Semitones the song has been transposed by with `change_key` since it was parsed
+    pub offset: i32,
 }
 
 impl Song {
+    /// Parses a song, skipping anything that can't be parsed
     pub fn from(songstring: String) -> Self {
-        Song::new(songstring, None)
+        Song::parse(songstring, None, None).0
     }
 
+    /// Parses a song shown in `key`, skipping anything that can't be parsed
     pub fn in_key(songstring: String, key: PitchClass) -> Self {
-        Song::new(songstring, Some(key))
+        Song::parse(songstring, Some(key), None).0
     }
 
-    pub fn change_key(&mut self, transposition: i32) {
-        let old_key = self.key.unwrap();
-        let interval = Interval::from_semitone(((transposition + 12) % 12) as u8).unwrap();
-        self.content = self
-            .content
+    /// Parses a song, failing on the first problem found. See `parse`.
+    pub fn new(
+        songstring: String,
+        key: Option<PitchClass>,
+        instrument: Option<&str>,
+    ) -> Result<Self, ParseError> {
+        match Song::parse(songstring, key, instrument) {
+            (song, None) => Ok(song),
+            (_, Some(error)) => Err(error),
+        }
+    }
+
+    /// Every chord in the song in order, as currently transposed
+    pub fn chords(&self) -> impl Iterator<Item = &str> {
+        self.content
             .iter()
-            .map(|line| {
-                SongLine::from(
-                    line.blocks
-                        .iter()
-                        .map(|block| {
-                            SongBlock(
-                                block
-                                    .0
-                                    .iter()
-                                    .map(|string| match string {
-                                        SongString::Chord(chord) => {
-                                            let transposed = RE_ROOT_NOTE.replace_all(
-                                                &chord,
-                                                |caps: &Captures| {
-                                                    PitchClass::from_interval(
-                                                        PitchClass::from_str(
-                                                            caps.get(0).unwrap().as_str(),
-                                                        )
-                                                        .unwrap(),
-                                                        interval,
-                                                    )
-                                                    .to_string()
-                                                },
-                                            );
-                                            SongString::Chord(transposed.to_string())
-                                        }
-                                        s => s.clone(), //TODO: remove .clone() call?
-                                    })
-                                    .collect(),
-                            )
-                        })
-                        .collect(),
-                    line.chorus,
-                )
+            .flat_map(|line| line.blocks.iter())
+            .flat_map(|block| block.0.iter())
+            .filter_map(|string| match string {
+                SongString::Chord(chord)
+                | SongString::Grid {
+                    cell: GridCell::Chord(chord),
+                    ..
+                } => Some(chord.as_str()),
+                _ => None,
             })
-            .collect();
+    }
+
+    /// Every chord used in the song once, in the order they first appear
+    pub fn unique_chords(&self) -> Vec<&str> {
+        let mut chords: Vec<&str> = vec![];
+        for chord in self.chords() {
+            if !chords.contains(&chord) {
+                chords.push(chord);
+            }
+        }
+        chords
+    }
+
+    pub fn change_key(&mut self, transposition: i32) {
+        let old_key = match self.key {
+            Some(key) => key,
+            None => return,
+        };
+        let interval = semitones(transposition);
+        self.map_chords(|chord| transpose_chord(chord, interval));
         self.key = Some(PitchClass::from_interval(old_key, interval));
+        self.offset = (self.offset + transposition).rem_euclid(12);
+        self.respell();
+    }
+
+    /// Transposition since parsing as the smallest step up or down, e.g. -1 rather than 11
+    pub fn signed_offset(&self) -> i32 {
+        match self.offset {
+            offset if offset > 6 => offset - 12,
+            offset => offset,
+        }
+    }
+
+    /// Sets how chords are spelled. Anything but `Auto` also respells chords that weren't
+    /// transposed.
+    pub fn set_accidentals(&mut self, accidentals: Accidentals) {
+        self.accidentals = accidentals;
+        if accidentals != Accidentals::Auto {
+            self.respell();
+        }
+    }
+
+    /// Whether chords are written with flats rather than sharps, if that's known
+    fn flats(&self) -> Option<bool> {
+        self.flats_in(self.key)
+    }
+
+    /// Whether chords in `key`, with the mode of the song, are written with flats
+    fn flats_in(&self, key: Option<PitchClass>) -> Option<bool> {
+        match self.accidentals {
+            // Modes are spelled like the major key that shares their notes, so A minor
+            // like C major
+            Accidentals::Auto => key.map(|key| {
+                FLAT_KEYS.contains(&((key.into_u8() + self.mode.relative_major()) % 12))
+            }),
+            Accidentals::Sharps => Some(false),
+            Accidentals::Flats => Some(true),
+        }
+    }
+
+    /// Writes every chord with the accidentals that fit the key
+    fn respell(&mut self) {
+        if let Some(flats) = self.flats() {
+            self.map_chords(|chord| spell_chord(chord, flats));
+        }
+    }
+
+    /// Name of the key, spelled the same way as the chords, like `F#m`
+    pub fn key_name(&self) -> Option<String> {
+        let tonic = spell(self.key?, self.flats().unwrap_or(false));
+        Some(format!("{}{}", tonic, self.mode.suffix()))
+    }
+
+    /// Key the song sounds in, the chords are shown as played with the capo on
+    pub fn concert_key(&self) -> Option<PitchClass> {
+        let capo = self.capo.unwrap_or(0) as i32;
+        Some(PitchClass::from_u8(
+            (self.key?.into_u8() as i32 + capo).rem_euclid(12) as u8,
+        ))
+    }
+
+    /// Capo position and key to play in that give the friendliest open chord shapes for
+    /// the concert key, or `None` if the song is best played without a capo
+    pub fn capo_suggestion(&self) -> Option<(u8, String)> {
+        let concert = self.concert_key()?.into_u8();
+        let open_keys: &[u8] = match self.mode {
+            Mode::Minor => &OPEN_MINOR_KEYS,
+            _ => &OPEN_KEYS,
+        };
+        let (capo, shape) = open_keys
+            .iter()
+            .enumerate()
+            .filter_map(|(rank, &shape)| {
+                let capo = (concert as i32 - shape as i32).rem_euclid(12) as u8;
+                // Past the seventh fret a capo leaves too little of the neck
+                (capo <= MAX_CAPO).then_some((rank / 3, capo, shape))
+            })
+            .min()
+            .map(|(_, capo, shape)| (capo, shape))?;
+        // Nothing to suggest when the song already uses that capo, or needs none
+        match capo {
+            0 => None,
+            capo if Some(capo) == self.capo => None,
+            _ => Some((
+                capo,
+                format!("{}{}", SHARP_NAMES[shape as usize], self.mode.suffix()),
+            )),
+        }
     }
 
-    fn new(songstring: String, key: Option<PitchClass>) -> Self {
+    /// Parses as much of a song as possible, shown in `key` when given, along with the
+    /// first problem found. Directives for a specific instrument, like
+    /// `{capo-guitar: 2}`, are only used when it's `instrument`.
+    pub fn parse(
+        songstring: String,
+        key: Option<PitchClass>,
+        instrument: Option<&str>,
+    ) -> (Self, Option<ParseError>) {
         let songstring = RE_NEWLINES.replace_all(&songstring, "\n");
-        let songstring = RE_SPACES.replace_all(&songstring, " ");
 
         let mut song = Song {
             key,
             ..Default::default()
         };
 
-        let mut chorus = false;
+        let mut current_section = Section::None;
         let mut comment = false;
-        for line in songstring.lines() {
+        let mut notes = false;
+        let mut tab = false;
+        // Index of the first line of the grid section that's being parsed
+        let mut grid: Option<usize> = None;
+        // Index of the first line of the chorus being parsed, and the last complete chorus
+        let mut chorus: Option<usize> = None;
+        let mut last_chorus: Vec<SongLine> = vec![];
+        let mut errors: Vec<ParseError> = vec![];
+        // Lines that start a new column
+        let mut breaks: Vec<usize> = vec![];
+        for (number, line) in songstring.lines().enumerate() {
+            // Columns are counted after collapsing spaces, but point into the line as written
+            let mut error = |column: usize, message: String| {
+                errors.push(ParseError {
+                    line: number + 1,
+                    column: uncollapsed(line, column) + 1,
+                    message,
+                })
+            };
+            if line.starts_with('#') {
+                song.source_comments.push((number + 1, line.to_string()));
+                continue;
+            }
+            if notes {
+                match Song::directive(line) {
+                    Some((name, _)) if name.eq_ignore_ascii_case("end_of_x_notes") => notes = false,
+                    _ => song.notes.push(line.to_string()),
+                }
+                continue;
+            }
+            if tab {
+                match Song::directive(line) {
+                    Some((name, _))
+                        if matches!(name.to_lowercase().as_str(), "eot" | "end_of_tab") =>
+                    {
+                        tab = false;
+                        current_section = Section::None;
+                    }
+                    _ => song.content.push(SongLine::from(
+                        vec![SongBlock(vec![SongString::Tab(line.to_string())])],
+                        Section::Tab,
+                    )),
+                }
+                continue;
+            }
+            if let Some(start) = grid {
+                match Song::directive(line) {
+                    Some((name, _))
+                        if matches!(name.to_lowercase().as_str(), "eog" | "end_of_grid") =>
+                    {
+                        Song::align_grid(&mut song.content[start..]);
+                        grid = None;
+                        current_section = Section::None;
+                    }
+                    _ => song.content.push(SongLine::from(
+                        Song::parse_grid(line, song.transposition),
+                        Section::Grid,
+                    )),
+                }
+                continue;
+            }
+            let line = RE_SPACES.replace_all(line, " ");
             let mut tag = false;
             let mut blocks: Vec<SongBlock> = vec![];
+            let mut column = 0;
             for section in Song::regex_split_keep(&RE_TAGS, &line) {
-                match RE_TAGS.captures(&section) {
+                let start = column;
+                column += section.chars().count();
+                match RE_TAGS.captures(section) {
                     Some(cap) => {
                         tag = true;
-                        match cap.get(1).unwrap().as_str() {
-                            "t" | "title" => {
-                                song.title = String::from(cap.get(2).unwrap().as_str().trim());
+                        let value = cap.get(2).map(|value| value.as_str().trim());
+                        // Directive names are case insensitive
+                        let name = cap.get(1).unwrap().as_str().trim().to_lowercase();
+                        let name = match Song::select(&name, instrument) {
+                            Some(name) => name,
+                            None => continue,
+                        };
+                        match (name, value) {
+                            (
+                                "t" | "title" | "st" | "subtitle" | "key" | "capo" | "transpose"
+                                | "c" | "comment" | "ci" | "comment_italic" | "cb" | "comment_box"
+                                | "define" | "meta",
+                                None,
+                            ) => error(start, format!("{{{}}} needs a value", name)),
+                            ("t" | "title", Some(value)) => {
+                                song.title = String::from(value);
                             }
-                            "st" | "subtitle" => {
-                                song.subtitle = String::from(cap.get(2).unwrap().as_str().trim());
+                            ("st" | "subtitle", Some(value)) => {
+                                song.subtitle = String::from(value);
                             }
-                            "key" => {
-                                let original_key =
-                                    PitchClass::from_str(cap.get(2).unwrap().as_str().trim());
-                                match song.key {
-                                    Some(display_key) => {
-                                        song.transposition += display_key.into_u8() as i32
-                                            - original_key
-                                                .expect("Error parsing key tag in song")
-                                                .into_u8()
-                                                as i32
+                            ("key", Some(value)) => {
+                                if let Some((original_key, mode)) = parse_key(value) {
+                                    song.mode = mode;
+                                    match song.key {
+                                        Some(display_key) => {
+                                            song.transposition = (song.transposition
+                                                + display_key.into_u8() as i32
+                                                - original_key.into_u8() as i32)
+                                                .rem_euclid(12)
+                                        }
+                                        // A capo or transposition before the key applies to it too
+                                        None => {
+                                            song.key = Some(PitchClass::from_u8(
+                                                (original_key.into_u8() as i32 + song.transposition)
+                                                    .rem_euclid(12)
+                                                    as u8,
+                                            ))
+                                        }
                                     }
-                                    None => song.key = original_key,
+                                } else {
+                                    error(start, format!("'{}' is not a key", value));
                                 }
                             }
-                            "Capo-Bass_Guitar" => {
-                                let diff =
-                                    cap.get(2).unwrap().as_str().trim().parse::<i32>().unwrap();
-                                song.transposition -= diff;
-                                if let Some(key) = song.key {
-                                    song.key = Some(PitchClass::from_u8(
-                                        (key.into_u8() as i32 - diff + 12) as u8 % 12,
-                                    ))
+                            ("capo" | "capo-bass_guitar", Some(value)) => {
+                                if let Ok(diff) = value.parse::<u8>() {
+                                    if diff > 0 {
+                                        song.capo = Some(diff);
+                                    }
+                                    song.shift(-(diff as i32));
+                                } else {
+                                    error(start, format!("'{}' is not a fret", value));
+                                }
+                            }
+                            ("transpose", Some(value)) => match value.parse::<i32>() {
+                                Ok(diff) => song.shift(diff),
+                                Err(_) => error(
+                                    start,
+                                    format!("'{}' is not a number of semitones", value),
+                                ),
+                            },
+                            ("c" | "comment", Some(value)) => {
+                                blocks.append(&mut Song::parse_comment(value, SongString::Comment))
+                            }
+                            ("ci" | "comment_italic", Some(value)) => blocks
+                                .append(&mut Song::parse_comment(value, SongString::CommentItalic)),
+                            ("cb" | "comment_box", Some(value)) => blocks
+                                .append(&mut Song::parse_comment(value, SongString::CommentBox)),
+                            ("soc" | "start_of_chorus", label) => {
+                                current_section = Section::Chorus;
+                                song.push_label(label);
+                                chorus = Some(song.content.len());
+                            }
+                            ("eoc" | "end_of_chorus", _) => {
+                                current_section = Section::None;
+                                if let Some(start) = chorus.take() {
+                                    last_chorus = song.content[start..].to_vec();
+                                }
+                            }
+                            ("chorus", label) => {
+                                song.push_label(label.or(Some("Chorus")));
+                                song.content.extend(last_chorus.iter().cloned());
+                            }
+                            ("sov" | "start_of_verse", label) => {
+                                current_section = Section::Verse;
+                                song.push_label(label);
+                            }
+                            ("sot" | "start_of_tab", label) => {
+                                tab = true;
+                                current_section = Section::Tab;
+                                song.push_label(label);
+                            }
+                            ("sog" | "start_of_grid", label) => {
+                                current_section = Section::Grid;
+                                // The value is either a label or the shape of the grid,
+                                // like `1+4x2+4`, which isn't needed to draw it
+                                song.push_label(label.filter(|label| {
+                                    !label.starts_with(|c: char| c.is_ascii_digit())
+                                }));
+                                grid = Some(song.content.len());
+                            }
+                            ("sob" | "start_of_bridge", label) => {
+                                current_section = Section::Bridge;
+                                song.push_label(label);
+                            }
+                            ("eov" | "eob", _) => current_section = Section::None,
+                            ("meta", Some(value)) => {
+                                if let Some((name, value)) = value.split_once(' ') {
+                                    song.metadata.set(&name.to_lowercase(), value.trim());
+                                }
+                            }
+                            (name, Some(value)) if Metadata::NAMES.contains(&name) => {
+                                song.metadata.set(name, value)
+                            }
+                            ("tag", Some(value)) => song.tags.extend(split_tags(value)),
+                            ("define", Some(value)) => {
+                                if let Some(definition) = ChordDefinition::parse(value) {
+                                    song.definitions.push(definition);
                                 }
                             }
-                            "c" => blocks
-                                .append(&mut Song::parse_comment(cap.get(2).unwrap().as_str())),
-                            "soc" | "start_of_chorus" => {
-                                chorus = true;
+                            // There are no pages on screen, so a new page starts a new column
+                            ("colb" | "column_break" | "np" | "new_page", _) => {
+                                breaks.push(song.content.len())
                             }
-                            "eoc" | "end_of_chorus" => {
-                                chorus = false;
+                            ("x_notes", Some(value)) => song.notes.push(value.to_string()),
+                            ("start_of_x_notes", _) => notes = true,
+                            ("soh", _) => comment = true,
+                            ("eoh", _) => comment = false,
+                            (name, label) if name.starts_with("start_of_") => {
+                                current_section =
+                                    Section::Other(name["start_of_".len()..].to_string());
+                                song.push_label(label);
+                            }
+                            (name, _) if name.starts_with("end_of_") => {
+                                current_section = Section::None
                             }
-                            "soh" => comment = true,
-                            "eoh" => comment = false,
                             _ => (),
                         }
                     }
-                    None => match comment {
-                        true => blocks.append(&mut Song::parse_comment(section)),
-                        false => blocks.append(&mut Song::parse_line(section, song.transposition)),
-                    },
+                    None => {
+                        if let Some(i) = unclosed(section, '{', '}') {
+                            error(start + i, String::from("Directive is never closed"));
+                        }
+                        match comment {
+                            true => blocks
+                                .append(&mut Song::parse_comment(section, SongString::Comment)),
+                            false => {
+                                if let Some(i) = unclosed(section, '[', ']') {
+                                    error(start + i, String::from("Chord is never closed"));
+                                }
+                                blocks.append(&mut Song::parse_line(section, song.transposition))
+                            }
+                        }
+                    }
+                }
+            }
+            let invalid = blocks.iter().flat_map(|block| block.0.iter());
+            for string in invalid {
+                if let SongString::InvalidChord(chord) = string {
+                    song.invalid_chords.push((number + 1, chord.clone()));
                 }
             }
             if !blocks.is_empty() || !tag {
-                song.content.push(SongLine::from(blocks, chorus));
+                song.content
+                    .push(SongLine::from(blocks, current_section.clone()));
+            }
+        }
+        if let Some(start) = grid {
+            Song::align_grid(&mut song.content[start..]);
+        }
+        for i in breaks {
+            if let Some(line) = song.content.get_mut(i) {
+                line.column_break = true;
+            }
+        }
+        // Chords of a transposed song have to be spelled for their new key
+        if song.transposition != 0 {
+            song.respell();
+        }
+        (song, errors.into_iter().next())
+    }
+
+    /// Name of a directive without its selector, or None if the selector says it's meant
+    /// for another instrument. `c-guitar` is only for guitar and `c-!guitar` for
+    /// everything but. Without an instrument the name is kept as written, so songs read
+    /// the same as before instruments could be set.
+    fn select<'a>(name: &'a str, instrument: Option<&str>) -> Option<&'a str> {
+        let instrument = match instrument {
+            Some(instrument) => instrument,
+            None => return Some(name),
+        };
+        let (name, selector) = match name.split_once('-') {
+            Some(parts) => parts,
+            None => return Some(name),
+        };
+        let matches = |selector: &str| selector.eq_ignore_ascii_case(instrument);
+        match selector.strip_prefix('!') {
+            Some(selector) => (!matches(selector)).then_some(name),
+            None => matches(selector).then_some(name),
+        }
+    }
+
+    /// Key of the song from `{key}`, or else guessed from its first chord
+    pub fn detect_key(&self) -> Option<PitchClass> {
+        self.key.or_else(|| {
+            let chord = self.chords().next()?;
+            PitchClass::from_str(RE_ROOT_NOTE.find(chord)?.as_str())
+        })
+    }
+
+    /// Copy of the song with every chord written as a Nashville number relative to its
+    /// key, like `4` for `F` in the key of `C`. Songs without chords are left as is.
+    pub fn to_nashville(&self) -> Song {
+        let mut song = self.clone();
+        if let Some(key) = self.detect_key() {
+            song.map_chords(|chord| nashville_chord(chord, key));
+        }
+        song
+    }
+
+    /// Copy of the song with the chord as written in the file after every transposed
+    /// chord, like `A (G)`. Songs shown as written are left as is.
+    pub fn with_original_chords(&self) -> Song {
+        let mut song = self.clone();
+        // Semitones from the chords as shown back to the chords in the file
+        let back = (12 - (self.transposition + self.offset).rem_euclid(12)) % 12;
+        if back != 0 {
+            let original_key = self
+                .key
+                .map(|key| PitchClass::from_u8((key.into_u8() + back as u8) % 12));
+            let flats = self.flats_in(original_key);
+            song.map_chords(|chord| {
+                format!("{} ({})", chord, rewrite_chord(chord, back as u8, flats))
+            });
+        }
+        song
+    }
+
+    /// Copy of the song with only the lyrics, for singers. Chords are taken out of the
+    /// lyrics and chord lines, grids and tabs are left out, so every line is a single
+    /// row high.
+    pub fn lyrics_only(&self) -> Song {
+        let mut song = self.clone();
+        song.content.retain(|line| {
+            !line.is_chord_line() && !matches!(line.section, Section::Grid | Section::Tab)
+        });
+        for line in song.content.iter_mut() {
+            for block in line.blocks.iter_mut() {
+                block.0.retain(|string| {
+                    !matches!(
+                        string,
+                        SongString::Chord(_)
+                            | SongString::InvalidChord(_)
+                            | SongString::Annotation(_)
+                            | SongString::Numeral(_)
+                    )
+                });
+            }
+        }
+        song
+    }
+
+    /// Copy of the song as a compact chart for instrumentalists, with only the chords,
+    /// section labels, comments, grids and tabs. Chords of a line are put next to each
+    /// other on a single row and lyrics are left out.
+    pub fn chords_only(&self) -> Song {
+        let mut song = self.clone();
+        let mut content: Vec<SongLine> = vec![];
+        for mut line in song.content.drain(..) {
+            if matches!(line.section, Section::Grid | Section::Tab) || line.is_chord_line() {
+                content.push(line);
+                continue;
+            }
+            let strings: Vec<SongString> = line
+                .blocks
+                .drain(..)
+                .flat_map(|block| block.0)
+                .filter_map(|string| match string {
+                    SongString::Chord(chord) | SongString::InvalidChord(chord) => {
+                        Some(SongString::Grid {
+                            cell: GridCell::Chord(chord),
+                            width: 0,
+                        })
+                    }
+                    SongString::Annotation(annotation) => Some(SongString::Comment(annotation)),
+                    SongString::Comment(_)
+                    | SongString::CommentItalic(_)
+                    | SongString::CommentBox(_)
+                    | SongString::Label(_) => Some(string),
+                    _ => None,
+                })
+                .collect();
+            let blank = strings.is_empty();
+            line.blocks = vec![SongBlock(strings)];
+            // Lines that only had lyrics are left out, and so are blank lines after them
+            let last_blank = content.last().is_none_or(|last| last.width() == 0);
+            if !blank || !last_blank {
+                content.push(line);
             }
         }
+        song.content = content;
         song
     }
 
-    fn parse_comment(input: &str) -> Vec<SongBlock> {
+    /// Copy of the song with the Roman numeral of every chord relative to its key below
+    /// it, like `vi` for `Am` in the key of `C`. Songs without chords are left as is.
+    pub fn with_roman_numerals(&self) -> Song {
+        let mut song = self.clone();
+        let key = match self.detect_key() {
+            Some(key) => key,
+            None => return song,
+        };
+        let blocks = song
+            .content
+            .iter_mut()
+            .flat_map(|line| line.blocks.iter_mut());
+        for block in blocks {
+            block.0 = block
+                .0
+                .drain(..)
+                .flat_map(|string| match string {
+                    SongString::Chord(chord) => {
+                        let numeral = roman_chord(&chord, key);
+                        let width = numeral.width();
+                        vec![
+                            SongString::Chord(format!("{:width$}", chord, width = width)),
+                            SongString::Numeral(numeral),
+                        ]
+                    }
+                    string => vec![string],
+                })
+                .collect();
+        }
+        song
+    }
+
+    /// Replaces every chord, including the ones in grids, with `f(chord)`
+    fn map_chords(&mut self, f: impl Fn(&str) -> String) {
+        let strings = self
+            .content
+            .iter_mut()
+            .flat_map(|line| line.blocks.iter_mut())
+            .flat_map(|block| block.0.iter_mut());
+        for string in strings {
+            if let SongString::Chord(chord)
+            | SongString::Grid {
+                cell: GridCell::Chord(chord),
+                ..
+            } = string
+            {
+                *chord = f(chord);
+            }
+        }
+    }
+
+    /// Transposes the chords that follow and the key by `semitones` while parsing, a key
+    /// that comes later is shifted when it's read
+    fn shift(&mut self, semitones: i32) {
+        let semitones = semitones.rem_euclid(12);
+        self.transposition = (self.transposition + semitones).rem_euclid(12);
+        if let Some(key) = self.key {
+            self.key = Some(PitchClass::from_u8(
+                (key.into_u8() as i32 + semitones).rem_euclid(12) as u8,
+            ))
+        }
+    }
+
+    /// Start of every part of the song with its name, as indices into `content`. Parts
+    /// start at a label or a comment on a line of its own, like `{c: Chorus}`, or at a
+    /// verse, chorus or bridge without a label, which are numbered like `Verse 2`.
+    /// Names used more than once get numbered too.
+    pub fn outline(&self) -> Vec<(usize, String)> {
+        let mut outline: Vec<(usize, String)> = vec![];
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        let mut previous = &Section::None;
+        let mut labeled = false;
+        for (i, line) in self.content.iter().enumerate() {
+            let section = std::mem::replace(&mut previous, &line.section);
+            let name = match line.header() {
+                Some(header) => header,
+                None if labeled || line.section == *section => {
+                    labeled = false;
+                    continue;
+                }
+                None => match &line.section {
+                    Section::Verse => "Verse".to_string(),
+                    Section::Chorus => "Chorus".to_string(),
+                    Section::Bridge => "Bridge".to_string(),
+                    Section::Other(name) => {
+                        let mut chars = name.chars();
+                        match chars.next() {
+                            Some(first) => first.to_uppercase().chain(chars).collect(),
+                            None => continue,
+                        }
+                    }
+                    _ => continue,
+                },
+            };
+            labeled = line.header().is_some();
+            let count = counts.entry(name.clone()).or_default();
+            *count += 1;
+            outline.push((i, name));
+        }
+        let mut numbers: HashMap<String, usize> = HashMap::new();
+        for (_, name) in outline.iter_mut() {
+            if counts[name.as_str()] > 1 {
+                let number = numbers.entry(name.clone()).or_default();
+                *number += 1;
+                *name = format!("{} {}", name, number);
+            }
+        }
+        outline
+    }
+
+    /// Lines of every part of the song from `outline`, with the lines before the first
+    /// part as a part of their own. A song without parts is a single part.
+    pub fn parts(&self) -> Vec<Range<usize>> {
+        let mut starts: Vec<usize> = self.outline().into_iter().map(|(line, _)| line).collect();
+        if starts.first() != Some(&0) {
+            starts.insert(0, 0);
+        }
+        starts.push(self.content.len());
+        starts
+            .windows(2)
+            .map(|bounds| bounds[0]..bounds[1])
+            .filter(|part| {
+                self.content[part.clone()]
+                    .iter()
+                    .any(|line| line.width() > 0)
+            })
+            .collect()
+    }
+
+    /// Adds a section header if the section has a label
+    fn push_label(&mut self, label: Option<&str>) {
+        if let Some(label) = label.filter(|label| !label.is_empty()) {
+            self.content.push(SongLine::from(
+                vec![SongBlock::from_label(label)],
+                Section::None,
+            ));
+        }
+    }
+
+    fn parse_grid(input: &str, transposition: i32) -> Vec<SongBlock> {
+        input
+            .split_whitespace()
+            .map(|token| {
+                SongBlock(vec![SongString::Grid {
+                    cell: GridCell::parse(token, transposition),
+                    width: 0,
+                }])
+            })
+            .collect()
+    }
+
+    /// Pads the cells of grid lines so every column is as wide as its widest cell
+    fn align_grid(lines: &mut [SongLine]) {
+        let mut widths: Vec<usize> = vec![];
+        for line in lines.iter() {
+            for (i, block) in line.blocks.iter().enumerate() {
+                if let [SongString::Grid { cell, .. }] = block.0.as_slice() {
+                    let width = cell.as_str().width();
+                    match widths.get_mut(i) {
+                        Some(max) => *max = (*max).max(width),
+                        None => widths.push(width),
+                    }
+                }
+            }
+        }
+        for line in lines.iter_mut() {
+            for (block, max) in line.blocks.iter_mut().zip(widths.iter()) {
+                if let [SongString::Grid { width, .. }] = block.0.as_mut_slice() {
+                    *width = *max;
+                }
+            }
+        }
+    }
+
+    /// Splits a comment into blocks per word so it can wrap, using `kind` for the flavor
+    /// of comment
+    fn parse_comment(input: &str, kind: fn(String) -> SongString) -> Vec<SongBlock> {
         RE_BLOCKS
             .captures_iter(input)
-            .map(|cap| SongBlock::from_comment(cap.get(0).unwrap().as_str()))
+            .map(|cap| SongBlock(vec![kind(cap.get(0).unwrap().as_str().to_owned())]))
             .collect()
     }
 
@@ -351,24 +1467,457 @@ impl Song {
     }
 
     pub fn get_name(songstring: &str) -> Option<String> {
-        let songstring = RE_SPACES.replace_all(songstring, " ");
-        let title = match RE_TITLE.captures(&songstring) {
-            Some(cap) => Some(cap.get(1).unwrap().as_str().trim().to_owned()),
-            None => None,
-        };
-        let subtitle = match RE_SUBTITLE.captures(&songstring) {
-            Some(cap) => Some(cap.get(1).unwrap().as_str().trim()),
-            None => None,
-        };
+        let songstring: String = songstring
+            .split_inclusive('\n')
+            .filter(|line| !line.starts_with('#'))
+            .collect();
+        let songstring = RE_SPACES.replace_all(&songstring, " ");
+        let title = RE_TITLE
+            .captures(&songstring)
+            .and_then(|cap| cap.get(1))
+            .map(|title| title.as_str().trim().to_owned());
+        let subtitle = RE_SUBTITLE
+            .captures(&songstring)
+            .and_then(|cap| cap.get(1))
+            .map(|subtitle| subtitle.as_str().trim());
 
         let title = match (title, subtitle) {
             (Some(t), Some(st)) => format!("{} - {}", t, st),
-            (Some(t), None) => format!("{}", t),
-            (None, Some(st)) => format!("{}", st),
+            (Some(t), None) => t.to_string(),
+            (None, Some(st)) => st.to_string(),
             (None, None) => return None,
         };
         Some(RE_SPACES.replace_all(&title, " ").to_string())
     }
+
+    /// Splits a file holding several songs separated by `{new_song}` into the text of
+    /// each song
+    pub fn split(filestring: &str) -> Vec<&str> {
+        let mut songs = vec![];
+        let mut start = 0;
+        let mut offset = 0;
+        for line in filestring.split_inclusive('\n') {
+            if let Some((name, _)) = Song::directive(line) {
+                if matches!(name.to_lowercase().as_str(), "ns" | "new_song") {
+                    songs.push(&filestring[start..offset]);
+                    start = offset + line.len();
+                }
+            }
+            offset += line.len();
+        }
+        songs.push(&filestring[start..]);
+        songs.retain(|song| !song.trim().is_empty());
+        if songs.is_empty() {
+            songs.push(filestring);
+        }
+        songs
+    }
+
+    /// Names of every song in a file, see `split`. Songs without a title are named after
+    /// `file_name`, numbered when the file holds more than one.
+    pub fn get_names(filestring: &str, file_name: &str) -> Vec<String> {
+        Song::split(filestring)
+            .iter()
+            .enumerate()
+            .map(|(i, song)| {
+                Song::get_name(song).unwrap_or_else(|| match i {
+                    0 => file_name.to_string(),
+                    i => format!("{} ({})", file_name, i + 1),
+                })
+            })
+            .collect()
+    }
+
+    /// Tags from the `{tag}` directives of a song, without parsing all of it
+    pub fn get_tags(songstring: &str) -> Vec<String> {
+        songstring
+            .lines()
+            .filter_map(Song::directive)
+            .flat_map(
+                |(name, value)| match (name.to_lowercase().as_str(), value) {
+                    ("tag", Some(value)) => split_tags(value),
+                    _ => vec![],
+                },
+            )
+            .collect()
+    }
+
+    /// Name and value of the directive on `line`, if the line consists of just a directive
+    pub(crate) fn directive(line: &str) -> Option<(&str, Option<&str>)> {
+        let line = line.trim();
+        let cap = RE_TAGS.captures(line)?;
+        if cap.get(0)?.as_str().trim_end() != line {
+            return None;
+        }
+        Some((
+            cap.get(1)?.as_str().trim(),
+            cap.get(2).map(|value| value.as_str().trim()),
+        ))
+    }
+
+    /// Value of the first directive called any of `names`
+    pub fn get_directive(songstring: &str, names: &[&str]) -> Option<String> {
+        songstring
+            .lines()
+            .filter_map(Song::directive)
+            .find(|(name, _)| names.iter().any(|n| n.eq_ignore_ascii_case(name)))
+            .and_then(|(_, value)| value.map(String::from))
+    }
+
+    /// Rewrites the first directive called any of `names` to hold `value`, removing it
+    /// when `value` is empty. Missing directives are added after the directives at the
+    /// top of the song, using the first of `names`.
+    pub fn set_directive(songstring: &str, names: &[&str], value: &str) -> String {
+        let newline = if songstring.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        };
+        let mut lines: Vec<String> = songstring.lines().map(String::from).collect();
+        let value = value.trim();
+        let existing = lines.iter().position(|line| {
+            Song::directive(line)
+                .is_some_and(|(name, _)| names.iter().any(|n| n.eq_ignore_ascii_case(name)))
+        });
+        match existing {
+            Some(i) if value.is_empty() => {
+                lines.remove(i);
+            }
+            Some(i) => {
+                let name = Song::directive(&lines[i]).unwrap().0.to_string();
+                lines[i] = format!("{{{}: {}}}", name, value);
+            }
+            None if value.is_empty() => (),
+            None => {
+                let header = lines
+                    .iter()
+                    .take_while(|line| Song::directive(line).is_some())
+                    .count();
+                lines.insert(header, format!("{{{}: {}}}", names[0], value));
+            }
+        }
+        let mut result = lines.join(newline);
+        if songstring.ends_with('\n') || songstring.is_empty() {
+            result.push_str(newline);
+        }
+        result
+    }
+
+    /// Rewrites the chords in `songstring` up by `transposition` semitones, spelled for
+    /// the new key, and updates its `{key}` so the song reads the same in other ChordPro
+    /// programs. Grids are transposed too, tabs and source comments are left alone.
+    pub fn transpose_source(songstring: &str, transposition: i32) -> String {
+        if transposition.rem_euclid(12) == 0 {
+            return songstring.to_string();
+        }
+        let mut song = Song::from(songstring.to_string());
+        song.change_key(transposition);
+        let interval = semitones(transposition).semitone_count;
+        let flats = song.flats();
+        let rewrite = |chord: &str| match is_chord(chord) {
+            true => rewrite_chord(chord, interval, flats),
+            false => chord.to_string(),
+        };
+        let mut result = String::new();
+        let (mut grid, mut tab) = (false, false);
+        for line in songstring.split_inclusive('\n') {
+            let text = line.trim_end_matches(['\r', '\n']);
+            let ending = &line[text.len()..];
+            if let Some((name, _)) = Song::directive(text) {
+                match name.to_lowercase().as_str() {
+                    "sog" | "start_of_grid" => grid = true,
+                    "eog" | "end_of_grid" => grid = false,
+                    "sot" | "start_of_tab" => tab = true,
+                    "eot" | "end_of_tab" => tab = false,
+                    _ => (),
+                }
+                result.push_str(line);
+            } else if tab || text.starts_with('#') {
+                result.push_str(line);
+            } else if grid {
+                result.push_str(&text.split(' ').map(rewrite).collect::<Vec<_>>().join(" "));
+                result.push_str(ending);
+            } else {
+                let text = RE_CHORDS.replace_all(text, |caps: &regex::Captures| {
+                    format!("[{}]", rewrite(&caps[1]))
+                });
+                result.push_str(&text);
+                result.push_str(ending);
+            }
+        }
+        match song.key_name() {
+            Some(key) => Song::set_directive(&result, &["key"], &key),
+            None => result,
+        }
+    }
+}
+
+/// Interval to transpose by, `transposition` can be any amount of semitones up or down
+fn semitones(transposition: i32) -> Interval {
+    Interval::from_semitone(transposition.rem_euclid(12) as u8).unwrap()
+}
+
+/// Column of an `open` bracket that isn't followed by its `close` bracket
+fn unclosed(text: &str, open: char, close: char) -> Option<usize> {
+    let mut opened = None;
+    for (i, c) in text.chars().enumerate() {
+        if c == open && opened.is_none() {
+            opened = Some(i);
+        } else if c == close {
+            opened = None;
+        }
+    }
+    opened
+}
+
+/// Column in `line` of the character at `column` once runs of spaces are collapsed
+fn uncollapsed(line: &str, column: usize) -> usize {
+    let mut collapsed = 0;
+    let mut previous = None;
+    for (i, c) in line.chars().enumerate() {
+        if c == ' ' && previous == Some(' ') {
+            continue;
+        }
+        if collapsed == column {
+            return i;
+        }
+        collapsed += 1;
+        previous = Some(c);
+    }
+    line.chars().count()
+}
+
+/// Roman numeral of every semitone above the key, for major chords
+const ROMAN_DEGREES: [&str; 12] = [
+    "I", "bII", "II", "bIII", "III", "IV", "bV", "V", "bVI", "VI", "bVII", "VII",
+];
+
+/// Writes every chord in `chord` as a Roman numeral of `key`: lower case for minor and
+/// diminished chords, with their sevenths but without any other extensions or the bass
+/// note. The chord as written in dual chord mode, in parentheses, is skipped.
+fn roman_chord(chord: &str, key: PitchClass) -> String {
+    chord
+        .split(' ')
+        .filter_map(|token| {
+            let caps = RE_SLASH_CHORD.captures(token)?;
+            let root = PitchClass::from_str(&caps[1])?;
+            let degree = (root.into_u8() as i32 - key.into_u8() as i32).rem_euclid(12);
+            let numeral = ROMAN_DEGREES[degree as usize];
+            let quality = &caps[2];
+            let minor = quality.starts_with('m') && !quality.starts_with("maj");
+            let diminished = quality.starts_with("dim") || quality.starts_with('°');
+            let mut roman = match minor || diminished {
+                true => numeral.to_lowercase(),
+                false => numeral.to_string(),
+            };
+            if diminished {
+                roman.push('°');
+            } else if quality.starts_with("aug") || quality.starts_with('+') {
+                roman.push('+');
+            }
+            if quality.contains("maj7") {
+                roman.push_str("maj7");
+            } else if quality.contains('7') {
+                roman.push('7');
+            }
+            Some(roman)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Scale degree of every semitone above the key, as written in the Nashville number
+/// system
+const NASHVILLE_DEGREES: [&str; 12] = [
+    "1", "b2", "2", "b3", "3", "4", "b5", "5", "b6", "6", "b7", "7",
+];
+
+/// Writes the roots of `chord` as scale degrees of `key`. Extensions right after a root
+/// are superscript, so the `7` in `5⁷` can't be mistaken for part of the degree.
+/// Parts that don't start with a root, like `N.C.`, are left as they are.
+fn nashville_chord(chord: &str, key: PitchClass) -> String {
+    chord
+        .split(' ')
+        .map(|token| match RE_ROOT_NOTE.find(token) {
+            Some(root) if root.start() == 0 => nashville_token(token, key),
+            _ => token.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn nashville_token(chord: &str, key: PitchClass) -> String {
+    let mut result = String::new();
+    let mut last = 0;
+    for root in RE_ROOT_NOTE.find_iter(chord) {
+        result.push_str(&chord[last..root.start()]);
+        let note = PitchClass::from_str(root.as_str()).unwrap();
+        let degree = (note.into_u8() as i32 - key.into_u8() as i32).rem_euclid(12);
+        result.push_str(NASHVILLE_DEGREES[degree as usize]);
+
+        let rest = &chord[root.end()..];
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        result.extend(
+            rest[..digits]
+                .chars()
+                .filter_map(|digit| "⁰¹²³⁴⁵⁶⁷⁸⁹".chars().nth(digit.to_digit(10)? as usize)),
+        );
+        last = root.end() + digits;
+    }
+    result.push_str(&chord[last..]);
+    result
+}
+
+/// Keys with the easiest open chord shapes on guitar, G, C and D before A and E
+const OPEN_KEYS: [u8; 5] = [7, 0, 2, 9, 4];
+/// Minor keys with the easiest open chord shapes, Em, Am and Dm
+const OPEN_MINOR_KEYS: [u8; 3] = [4, 9, 2];
+const MAX_CAPO: u8 = 7;
+
+/// Mode of a key, the scale built on its tonic
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Mode {
+    #[default]
+    Major,
+    Minor,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
+}
+
+impl Mode {
+    /// Parses the part of a key after the tonic, like `m` or `minor` in `Am`
+    fn parse(mode: &str) -> Option<Mode> {
+        match mode {
+            "" | "M" => return Some(Mode::Major),
+            "m" => return Some(Mode::Minor),
+            _ => (),
+        }
+        match mode.to_lowercase().as_ref() {
+            "maj" | "major" | "ionian" => Some(Mode::Major),
+            "min" | "minor" | "aeolian" => Some(Mode::Minor),
+            "dorian" => Some(Mode::Dorian),
+            "phrygian" => Some(Mode::Phrygian),
+            "lydian" => Some(Mode::Lydian),
+            "mixolydian" => Some(Mode::Mixolydian),
+            "locrian" => Some(Mode::Locrian),
+            _ => None,
+        }
+    }
+
+    fn suffix(&self) -> &'static str {
+        match self {
+            Mode::Major => "",
+            Mode::Minor => "m",
+            Mode::Dorian => " dorian",
+            Mode::Phrygian => " phrygian",
+            Mode::Lydian => " lydian",
+            Mode::Mixolydian => " mixolydian",
+            Mode::Locrian => " locrian",
+        }
+    }
+
+    /// Semitones from the tonic up to the tonic of the major key with the same notes
+    fn relative_major(&self) -> u8 {
+        match self {
+            Mode::Major => 0,
+            Mode::Minor => 3,
+            Mode::Dorian => 10,
+            Mode::Phrygian => 8,
+            Mode::Lydian => 7,
+            Mode::Mixolydian => 5,
+            Mode::Locrian => 1,
+        }
+    }
+}
+
+/// Parses a key like `G`, `F#m` or `D dorian` into its tonic and mode
+pub fn parse_key(key: &str) -> Option<(PitchClass, Mode)> {
+    let caps = RE_KEY.captures(key.trim())?;
+    Some((
+        PitchClass::from_str(caps.get(1)?.as_str())?,
+        Mode::parse(caps.get(2)?.as_str())?,
+    ))
+}
+
+/// Tags of a `{tag}` directive, several can be given separated by commas
+fn split_tags(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Pitch classes of the major keys that are written with flats, F, Bb, Eb, Ab and Db
+const FLAT_KEYS: [u8; 5] = [5, 10, 3, 8, 1];
+
+const SHARP_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+const FLAT_NAMES: [&str; 12] = [
+    "C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab", "A", "Bb", "B",
+];
+
+fn spell(note: PitchClass, flats: bool) -> &'static str {
+    match flats {
+        true => FLAT_NAMES[note.into_u8() as usize],
+        false => SHARP_NAMES[note.into_u8() as usize],
+    }
+}
+
+/// Whether every chord in `chord` can be read, `No chord` included
+pub fn is_chord(chord: &str) -> bool {
+    chord
+        .split(' ')
+        .filter(|token| !token.is_empty())
+        .all(|token| matches!(token, "N.C." | "NC") || RE_VALID_CHORD.is_match(token))
+}
+
+fn spell_chord(chord: &str, flats: bool) -> String {
+    rewrite_chord(chord, 0, Some(flats))
+}
+
+fn transpose_chord(chord: &str, interval: Interval) -> String {
+    // Chords that stay put keep the spelling they were written with
+    if interval.semitone_count == 0 {
+        return chord.to_string();
+    }
+    rewrite_chord(chord, interval.semitone_count, None)
+}
+
+/// Moves the root and bass note of every chord in `chord` up by `semitones`, writing both
+/// with flats or sharps. Without `flats` the spelling of the root is kept, so `Bb/D`
+/// becomes `Db/F` rather than `C#/F`. Anything that isn't a chord, like `N.C.`, is left
+/// alone.
+fn rewrite_chord(chord: &str, semitones: u8, flats: Option<bool>) -> String {
+    let rewrite_token = |token: &str| {
+        let caps = match RE_SLASH_CHORD.captures(token) {
+            Some(caps) => caps,
+            None => return token.to_string(),
+        };
+        let root = caps.get(1).unwrap().as_str();
+        let flats = flats.unwrap_or_else(|| root.ends_with('b'));
+        let note = |name: &str| {
+            let note = PitchClass::from_str(name).unwrap();
+            spell(
+                PitchClass::from_u8((note.into_u8() + semitones) % 12),
+                flats,
+            )
+        };
+        match caps.get(3) {
+            Some(bass) => format!("{}{}/{}", note(root), &caps[2], note(bass.as_str())),
+            None => format!("{}{}", note(root), &caps[2]),
+        }
+    };
+    chord
+        .split(' ')
+        .map(rewrite_token)
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
 pub struct Playlist {
@@ -377,19 +1926,603 @@ pub struct Playlist {
 }
 
 impl Playlist {
+    /// Extensions of playlist files: the title on the first line, then a song per line
+    pub const EXTENSIONS: [&'static str; 2] = ["lst", "playlist"];
+
     pub fn from(playliststring: &str) -> Self {
-        let playliststring = RE_SPACES.replace_all(&playliststring, " ").to_string();
+        let playliststring = RE_SPACES.replace_all(playliststring, " ").to_string();
         let mut lines = playliststring.lines();
         Playlist {
-            title: lines.next().unwrap().to_string(),
-            songs: lines.map(|s| FileType::Song(s.to_string())).collect(),
+            title: lines.next().unwrap_or_default().to_string(),
+            songs: lines
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|s| FileType::Song(s.to_string()))
+                .collect(),
         }
     }
 
     pub fn get_name(playliststring: &str) -> Option<String> {
-        match playliststring.lines().next() {
-            Some(line) => Some(line.to_string()),
-            None => None,
+        playliststring.lines().next().map(|line| line.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conf::ConfStyle;
+
+    #[test]
+    fn playlists_skip_blank_lines() {
+        let playlist = Playlist::from("Sunday\nAmazing  Grace\n\n  \nBe Thou My Vision \n");
+        assert_eq!(playlist.title, "Sunday");
+        assert_eq!(
+            playlist.songs,
+            vec![
+                FileType::Song(String::from("Amazing Grace")),
+                FileType::Song(String::from("Be Thou My Vision")),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_input_does_not_panic() {
+        let inputs = [
+            "{title}\n{subtitle}\n",
+            "{key}\n{key: H}\n{key: G}\n",
+            "{c}\n{soc:}\n{Capo-Bass_Guitar}\n{Capo-Bass_Guitar: x}\n",
+            "{Capo-Bass_Guitar: -40}\n[G]la [Ebm7]la\n",
+            "[[G]]la [unclosed\n]{",
+            "é[Ć]ö [⁄]ü\r\n\r[]",
+            "",
+        ];
+        for input in inputs.iter() {
+            let mut song = Song::in_key(input.to_string(), PitchClass::C);
+            song.change_key(-25);
+            Song::from(input.to_string()).change_key(13);
+            Song::get_name(input);
+            Playlist::from(input);
+        }
+    }
+
+    #[test]
+    fn large_capo_transposes_chords() {
+        let song = Song::from(String::from("{Capo-Bass_Guitar: 14}\n[A]la\n"));
+        assert_eq!(song.transposition, 10);
+        assert_eq!(song.capo, Some(14));
+        match &song.content[0].blocks[0].0[0] {
+            SongString::Chord(chord) => assert_eq!(chord, "G"),
+            other => panic!("Expected chord, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn directives_are_rewritten_in_place() {
+        let song = "{title: Old}\r\n{key: G}\r\n[G]la\r\n";
+        assert_eq!(Song::get_directive(song, &["t", "title"]).unwrap(), "Old");
+        let song = Song::set_directive(song, &["title", "t"], "New");
+        let song = Song::set_directive(&song, &["capo"], "2");
+        let song = Song::set_directive(&song, &["key"], "");
+        assert_eq!(song, "{title: New}\r\n{capo: 2}\r\n[G]la\r\n");
+        assert_eq!(
+            Song::set_directive("la", &["tempo"], "90"),
+            "{tempo: 90}\nla"
+        );
+    }
+
+    #[test]
+    fn source_is_transposed() {
+        let song =
+            "{key: G}\r\n[G]la [D/F#]la [*Coda]\r\n# [G]\r\n{sog}\r\n| G . | C . |\r\n{eog}\r\n";
+        assert_eq!(
+            Song::transpose_source(song, 3),
+            "{key: Bb}\r\n[Bb]la [F/A]la [*Coda]\r\n# [G]\r\n{sog}\r\n| Bb . | Eb . |\r\n{eog}\r\n"
+        );
+        assert_eq!(Song::transpose_source("[G]la", 12), "[G]la");
+    }
+
+    #[test]
+    fn notes_are_kept_out_of_the_chart() {
+        let song = Song::from(String::from(
+            "{x_notes: Count in 4}\n[G]la\n{start_of_x_notes}\nAnna sings\n\n{end_of_x_notes}\n",
+        ));
+        assert_eq!(song.notes, vec!["Count in 4", "Anna sings", ""]);
+        assert_eq!(song.content.len(), 1);
+    }
+
+    #[test]
+    fn verses_are_labeled() {
+        let song = Song::from(String::from(
+            "{sov: Verse 2}\nla\n{eov}\n{soc}\nlo\n{eoc}\n",
+        ));
+        let sections: Vec<Section> = song
+            .content
+            .iter()
+            .map(|line| line.section.clone())
+            .collect();
+        assert_eq!(
+            sections,
+            vec![Section::None, Section::Verse, Section::Chorus]
+        );
+        match &song.content[0].blocks[0].0[0] {
+            SongString::Label(label) => assert_eq!(label, "Verse 2"),
+            other => panic!("Expected label, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn outline_names_every_part() {
+        let song = Song::from(String::from(
+            "{c: Intro}\n[G]la\n{sov}\nla\nla\n{eov}\n{soc: Refrain}\nlo\n{eoc}\n{sov}\nla\n{eov}\n{chorus}\n",
+        ));
+        let outline: Vec<String> = song.outline().into_iter().map(|(_, name)| name).collect();
+        assert_eq!(
+            outline,
+            vec!["Intro", "Verse 1", "Refrain", "Verse 2", "Chorus"]
+        );
+        let (line, _) = song.outline()[2];
+        assert!(song.content[line].header().is_some());
+    }
+
+    #[test]
+    fn song_is_split_into_parts() {
+        let song = Song::from(String::from("la\n{sov}\nli\n{eov}\n{soc}\nlo\n{eoc}\n"));
+        assert_eq!(song.parts(), vec![0..1, 1..2, 2..3]);
+        let song = Song::from(String::from("la\nli\n"));
+        assert_eq!(song.parts(), vec![0..2]);
+    }
+
+    #[test]
+    fn sections_are_styled() {
+        let song = Song::from(String::from("la\n{soc}\n[G]lo\n{eoc}\n"));
+        let theme = Theme {
+            chorus: ConfStyle::default().bg(tui::style::Color::Blue),
+            ..Theme::default()
+        };
+        let background = |line: &SongLine| -> Vec<Option<tui::style::Color>> {
+            line.format(&theme)
+                .iter()
+                .flat_map(|spans| spans.0.iter().map(|span| span.style.bg))
+                .collect()
+        };
+        assert!(background(&song.content[0]).iter().all(Option::is_none));
+        let chorus = background(&song.content[1]);
+        assert!(!chorus.is_empty());
+        assert!(chorus.iter().all(|bg| *bg == Some(tui::style::Color::Blue)));
+    }
+
+    #[test]
+    fn generic_sections() {
+        let song = Song::from(String::from(
+            "{sob}\nla\n{eob}\n{start_of_intro}\nlo\n{end_of_intro}\nli\n",
+        ));
+        let sections: Vec<Section> = song
+            .content
+            .iter()
+            .map(|line| line.section.clone())
+            .collect();
+        assert_eq!(
+            sections,
+            vec![
+                Section::Bridge,
+                Section::Other(String::from("intro")),
+                Section::None
+            ]
+        );
+    }
+
+    #[test]
+    fn chord_definitions() {
+        let song = Song::from(String::from(
+            "{define: Am base-fret 1 frets x 0 2 2 1 0}\n{define: Bb base-fret 6 frets 1 1 3 3 3 1 fingers 1 1 3 4 2 1}\n{define: C}\n",
+        ));
+        assert!(song.content.is_empty());
+        assert_eq!(song.definitions.len(), 2);
+        assert_eq!(song.definitions[1].base_fret, 6);
+        assert_eq!(
+            song.definitions[0].diagram(),
+            vec![
+                "    Am     ",
+                "x o       o",
+                "===========",
+                "| | | | * |",
+                "| | * * | |",
+                "| | | | | |",
+                "| | | | | |",
+            ]
+        );
+    }
+
+    #[test]
+    fn tabs_are_kept_verbatim() {
+        let tab = "e|---[0]---3--|\nB|-----  -1--|";
+        let song = Song::from(format!("{{sot: Riff}}\n{}\n{{eot}}\n[G]a  b\n", tab));
+        let lines: Vec<String> = song.content[1..3]
+            .iter()
+            .flat_map(|line| line.format(&Theme::default()))
+            .map(|spans| spans.0.iter().map(|span| span.content.as_ref()).collect())
+            .collect();
+        assert_eq!(lines, tab.lines().collect::<Vec<_>>());
+        assert_eq!(song.content[1].wrap(5).len(), 1);
+        assert_eq!(song.content[3].section, Section::None);
+        assert_eq!(song.chords().collect::<Vec<_>>(), vec!["G"]);
+    }
+
+    #[test]
+    fn grids_are_aligned() {
+        let song = Song::from(String::from(
+            "{key: G}\n{start_of_grid: 1+4x2+4}\n|: G . . . | Em . . . :|\n|  C7 . . . | D . % . |\n{eog}\n",
+        ));
+        let lines: Vec<String> = song
+            .content
+            .iter()
+            .flat_map(|line| line.format(&Theme::default()))
+            .map(|spans| spans.0.iter().map(|span| span.content.as_ref()).collect())
+            .collect();
+        assert_eq!(
+            lines,
+            vec!["|: G  . . . | Em . . . :| ", "|  C7 . . . | D  . % . |  ",]
+        );
+        assert_eq!(
+            song.chords().collect::<Vec<_>>(),
+            vec!["G", "Em", "C7", "D"]
+        );
+    }
+
+    #[test]
+    fn metadata_is_collected() {
+        let song = Song::from(String::from(
+            "{artist: A}\n{meta: artist B}\n{Year: 1999}\n{tempo: 120}\n[G]la\n",
+        ));
+        assert_eq!(
+            song.metadata.fields(),
+            vec![("Artist", "A, B"), ("Year", "1999"), ("Tempo", "120")]
+        );
+        assert_eq!(song.content.len(), 1);
+    }
+
+    #[test]
+    fn tags_are_collected() {
+        let song = "{title: Silent Night}\n{tag: christmas}\n{tag: hymn, slow}\n[G]la\n";
+        assert_eq!(Song::get_tags(song), vec!["christmas", "hymn", "slow"]);
+        assert_eq!(Song::from(song.to_string()).tags, Song::get_tags(song));
+    }
+
+    #[test]
+    fn lyrics_only_drops_chords() {
+        let song = Song::from("G       D\nAmazing grace\n[C]How [G]sweet\n".to_string());
+        let lyrics = song.lyrics_only();
+        assert_eq!(lyrics.content.len(), 2);
+        assert!(lyrics.content.iter().all(|line| line.metrics().height == 1));
+        assert_eq!(lyrics.content[1].width(), "How sweet".len());
+    }
+
+    #[test]
+    fn chords_only_drops_lyrics() {
+        let song = Song::from(
+            "{c: Verse 1}\n[G]Amazing [D]grace\nNo chords here\n\n[C]How [G]sweet\n".to_string(),
+        );
+        let chart = song.chords_only();
+        assert_eq!(chart.content.len(), 4);
+        assert!(chart.content.iter().all(|line| line.metrics().height == 1));
+        assert_eq!(chart.content[1].width(), "G D ".len());
+    }
+
+    #[test]
+    fn capo_transposes_chords_down() {
+        let song = Song::from(String::from("{key: A}\n{capo: 2}\n[A]la\n"));
+        assert_eq!(song.capo, Some(2));
+        assert_eq!(song.key, PitchClass::from_str("G"));
+        assert_eq!(song.chords().collect::<Vec<_>>(), vec!["G"]);
+    }
+
+    #[test]
+    fn capo_before_key_shifts_the_key() {
+        for source in [
+            "{capo: 2}\n{key: G}\n[G]la\n",
+            "{key: G}\n{capo: 2}\n[G]la\n",
+        ]
+        .iter()
+        {
+            let song = Song::from(String::from(*source));
+            assert_eq!(song.key, PitchClass::from_str("F"));
+            assert_eq!(song.key_name(), Some(String::from("F")));
+            assert_eq!(song.concert_key(), PitchClass::from_str("G"));
+            assert_eq!(song.chords().collect::<Vec<_>>(), vec!["F"]);
+        }
+    }
+
+    #[test]
+    fn files_are_split_into_songs() {
+        let file = "{title: One}\n[G]la\n{new_song}\n[C]lo\n{ns}\n{t: Three}\n";
+        assert_eq!(
+            Song::split(file),
+            vec!["{title: One}\n[G]la\n", "[C]lo\n", "{t: Three}\n"]
+        );
+        assert_eq!(
+            Song::get_names(file, "file.txt"),
+            vec!["One", "file.txt (2)", "Three"]
+        );
+        assert_eq!(Song::split("{ns}\n"), vec!["{ns}\n"]);
+    }
+
+    #[test]
+    fn transpose_combines_with_capo() {
+        let song = Song::from(String::from(
+            "{key: C}\n{transpose: -3}\n{capo: 1}\n[C]la [G/B]lo\n",
+        ));
+        assert_eq!(song.key, PitchClass::from_str("G#"));
+        assert_eq!(song.chords().collect::<Vec<_>>(), vec!["Ab", "Eb/G"]);
+    }
+
+    #[test]
+    fn transpose_before_key_shifts_the_key() {
+        for source in [
+            "{transpose: 2}\n{key: G}\n[G]la\n",
+            "{key: G}\n{transpose: 2}\n[G]la\n",
+        ]
+        .iter()
+        {
+            let song = Song::from(String::from(*source));
+            assert_eq!(song.key, PitchClass::from_str("A"));
+            assert_eq!(song.chords().collect::<Vec<_>>(), vec!["A"]);
+        }
+    }
+
+    #[test]
+    fn chorus_is_recalled() {
+        let song = Song::from(String::from(
+            "{chorus}\n{soc: Refrain}\n[G]la\n{eoc}\nverse\n{chorus: Again}\n",
+        ));
+        let lines: Vec<String> = song
+            .content
+            .iter()
+            .flat_map(|line| line.format(&Theme::default()))
+            .map(|spans| spans.0.iter().map(|span| span.content.as_ref()).collect())
+            .collect();
+        assert_eq!(
+            lines,
+            vec!["Chorus", "Refrain", "| G ", "| la", "verse", "Again", "| G ", "| la"]
+        );
+    }
+
+    #[test]
+    fn directives_for_other_instruments_are_skipped() {
+        let songstring = "{c-guitar: Guitar}\n{c-!guitar: Others}\n{capo-guitar: 2}\n[A]la\n";
+        let guitar = Song::new(String::from(songstring), None, Some("Guitar")).unwrap();
+        assert_eq!(guitar.chords().collect::<Vec<_>>(), vec!["G"]);
+        assert_eq!(guitar.content.len(), 2);
+        let piano = Song::new(String::from(songstring), None, Some("piano")).unwrap();
+        assert_eq!(piano.capo, None);
+        assert_eq!(piano.content.len(), 2);
+        let comment = |song: &Song| -> String {
+            song.content[0].format(&Theme::default())[0]
+                .0
+                .iter()
+                .map(|span| span.content.as_ref())
+                .collect()
+        };
+        assert_eq!(comment(&guitar), "Guitar");
+        assert_eq!(comment(&piano), "Others");
+    }
+
+    #[test]
+    fn errors_have_positions() {
+        let error = |songstring: &str| Song::new(String::from(songstring), None, None).unwrap_err();
+        assert_eq!(
+            error("{title: a}\nla {key}"),
+            ParseError {
+                line: 2,
+                column: 4,
+                message: String::from("{key} needs a value"),
+            }
+        );
+        assert_eq!(error("[G]la [C").column, 7);
+        assert_eq!(error("[G]la   [C").column, 9);
+        assert_eq!(error("la  lo   {key}").column, 10);
+        assert_eq!(error("{soc}\n{capo: x}\n").line, 2);
+        assert_eq!(error("{title: a").message, "Directive is never closed");
+        assert!(Song::new(String::from("{soc}\n[G]a {} ]\n"), None, None).is_ok());
+    }
+
+    #[test]
+    fn source_comments_are_skipped() {
+        let songstring = "# {title: Old}\n{title: New}\n#[G]la\n[C]lo\n";
+        let song = Song::from(String::from(songstring));
+        assert_eq!(song.title, "New");
+        assert_eq!(Song::get_name(songstring).unwrap(), "New");
+        assert_eq!(song.chords().collect::<Vec<_>>(), vec!["C"]);
+        assert_eq!(
+            song.source_comments,
+            vec![
+                (1, String::from("# {title: Old}")),
+                (3, String::from("#[G]la"))
+            ]
+        );
+    }
+
+    #[test]
+    fn annotations_are_not_transposed() {
+        let mut song = Song::from(String::from("{key: C}\n[*Riff 1]la [C]lo\n"));
+        song.change_key(2);
+        assert_eq!(song.chords().collect::<Vec<_>>(), vec!["D"]);
+        let chords: String = song.content[0].format(&Theme::default())[0]
+            .0
+            .iter()
+            .map(|span| span.content.as_ref())
+            .collect();
+        assert_eq!(chords, "Riff 1 D ");
+    }
+
+    #[test]
+    fn wide_characters_are_measured_by_width() {
+        let song = Song::from(String::from("[G]歌詞[C]です\n"));
+        assert_eq!(song.content[0].width(), 8);
+        let chords: String = song.content[0].format(&Theme::default())[0]
+            .0
+            .iter()
+            .map(|span| span.content.as_ref())
+            .collect();
+        assert_eq!(chords, "G   C ");
+    }
+
+    #[test]
+    fn chords_as_nashville_numbers() {
+        let song = Song::from(String::from(
+            "{key: C}\n[C]la [Am7]la [F/A]la [Bb]la [G7sus4]la [N.C.]\n",
+        ));
+        assert_eq!(
+            song.to_nashville().chords().collect::<Vec<_>>(),
+            vec!["1", "6m7", "4/6", "b7", "5⁷sus4", "N.C."]
+        );
+        let song = Song::from(String::from("[D]la [G]la [A]\n"));
+        assert_eq!(
+            song.to_nashville().chords().collect::<Vec<_>>(),
+            vec!["1", "4", "5"]
+        );
+    }
+
+    #[test]
+    fn transposed_chords_are_spelled_for_their_key() {
+        let mut song = Song::from(String::from("{key: G}\n[G]la [Bb]la [F#m]\n"));
+        assert_eq!(song.chords().collect::<Vec<_>>(), vec!["G", "Bb", "F#m"]);
+        song.change_key(3);
+        assert_eq!(song.key_name().as_deref(), Some("Bb"));
+        assert_eq!(song.chords().collect::<Vec<_>>(), vec!["Bb", "Db", "Am"]);
+        song.change_key(-1);
+        assert_eq!(song.chords().collect::<Vec<_>>(), vec!["A", "C", "G#m"]);
+        song.set_accidentals(Accidentals::Flats);
+        assert_eq!(song.chords().collect::<Vec<_>>(), vec!["A", "C", "Abm"]);
+        assert_eq!(song.key_name().as_deref(), Some("A"));
+    }
+
+    #[test]
+    fn offset_is_the_smallest_step() {
+        let mut song = Song::from(String::from("{key: C}\n[C]la\n"));
+        song.change_key(11);
+        assert_eq!(song.signed_offset(), -1);
+        song.change_key(3);
+        assert_eq!(song.signed_offset(), 2);
+        song.change_key(-2);
+        assert_eq!(song.offset, 0);
+    }
+
+    #[test]
+    fn capo_suggestions() {
+        let suggest = |song: &str| Song::from(String::from(song)).capo_suggestion();
+        assert_eq!(suggest("{key: G}\n"), None);
+        assert_eq!(suggest("{key: Bb}\n"), Some((3, "G".to_string())));
+        assert_eq!(suggest("{key: F}\n"), Some((3, "D".to_string())));
+        assert_eq!(
+            suggest("{key: Bb}\n{capo: 1}\n"),
+            Some((3, "G".to_string()))
+        );
+        assert_eq!(suggest("{key: Gm}\n"), Some((3, "Em".to_string())));
+        assert_eq!(suggest("{key: Bb}\n{capo: 3}\n"), None);
+        assert_eq!(suggest("[C]la\n"), None);
+    }
+
+    #[test]
+    fn minor_and_modal_keys() {
+        assert_eq!(parse_key("F#m"), Some((PitchClass::Fs, Mode::Minor)));
+        assert_eq!(parse_key("D dorian"), Some((PitchClass::D, Mode::Dorian)));
+        assert_eq!(parse_key("Bb Major"), Some((PitchClass::As, Mode::Major)));
+        assert_eq!(parse_key("Am7"), None);
+
+        let mut song = Song::from(String::from("{key: Am}\n[Am]la [E7]lo\n"));
+        assert_eq!(song.key, Some(PitchClass::A));
+        song.change_key(-2);
+        assert_eq!(song.key_name().as_deref(), Some("Gm"));
+        assert_eq!(song.chords().collect::<Vec<_>>(), vec!["Gm", "D7"]);
+        song.change_key(1);
+        // Ab minor has seven flats, G# minor is written with sharps
+        assert_eq!(song.key_name().as_deref(), Some("G#m"));
+
+        let song = Song::in_key(String::from("{key: Em}\n[Em]la\n"), PitchClass::C);
+        assert_eq!(song.chords().collect::<Vec<_>>(), vec!["Cm"]);
+    }
+
+    #[test]
+    fn slash_chords_are_transposed() {
+        let song = |chords: &str, transpose: i32| {
+            let mut song = Song::from(format!("{}\n", chords));
+            song.key = Some(PitchClass::C);
+            song.change_key(transpose);
+            song.chords().map(String::from).collect::<Vec<_>>()
+        };
+        assert_eq!(song("[D/F#] [Bb/D] [N.C.]", 2), vec!["E/G#", "C/E", "N.C."]);
+        // F is written with flats, bass notes included
+        assert_eq!(song("[D/F#] [Asus4/C#]", 5), vec!["G/B", "Dsus4/Gb"]);
+        assert_eq!(song("[Bb/D]", 4), vec!["D/F#"]);
+    }
+
+    #[test]
+    fn unkeyed_chords_keep_their_spelling() {
+        assert_eq!(rewrite_chord("Bb/D", 3, None), "Db/F");
+        assert_eq!(rewrite_chord("F#m7/C#", 1, None), "Gm7/D");
+        assert_eq!(rewrite_chord("G C/E", 2, Some(true)), "A D/Gb");
+    }
+
+    #[test]
+    fn chords_are_validated() {
+        for chord in [
+            "G", "Bbmaj7", "F#m7b5", "C7#9", "Dm7/C", "Cadd9", "E7sus4", "C6/9", "Cm(maj7)",
+            "N.C.", "G C/B", "Dsus", "Asus2",
+        ] {
+            assert!(is_chord(chord), "{}", chord);
+        }
+        for chord in ["Gsus44", "H7", "Cmajj7", "x2", "G/"] {
+            assert!(!is_chord(chord), "{}", chord);
         }
+        let song = Song::from(String::from("{key: G}\n[G]la [Gsus44]lo\n"));
+        assert_eq!(song.invalid_chords, vec![(2, String::from("Gsus44"))]);
+        assert_eq!(song.chords().collect::<Vec<_>>(), vec!["G"]);
+    }
+
+    #[test]
+    fn original_chords_are_shown_next_to_transposed_ones() {
+        let mut song = Song::from(String::from("{key: F}\n{capo: 3}\n[F]la [Bb/D]lo\n"));
+        assert_eq!(
+            song.with_original_chords().chords().collect::<Vec<_>>(),
+            vec!["D (F)", "G/B (Bb/D)"]
+        );
+        song.change_key(1);
+        assert_eq!(
+            song.with_original_chords().chords().collect::<Vec<_>>(),
+            vec!["Eb (F)", "Ab/C (Bb/D)"]
+        );
+        let song = Song::from(String::from("{key: G}\n[G]la\n"));
+        assert_eq!(
+            song.with_original_chords().chords().collect::<Vec<_>>(),
+            vec!["G"]
+        );
+    }
+
+    #[test]
+    fn unique_chords_keep_their_order() {
+        let song = Song::from(String::from("[G]la [C]la [G]la\n[D]la [Em]la [C]\n"));
+        assert_eq!(song.unique_chords(), vec!["G", "C", "D", "Em"]);
+    }
+
+    #[test]
+    fn chords_as_roman_numerals() {
+        let key = PitchClass::C;
+        let romans: Vec<String> = ["C", "Am", "F", "G7", "Bdim", "Bb", "Fmaj7", "Em/B", "Ebm"]
+            .iter()
+            .map(|chord| roman_chord(chord, key))
+            .collect();
+        assert_eq!(
+            romans,
+            vec!["I", "vi", "IV", "V7", "vii°", "bVII", "IVmaj7", "iii", "biii"]
+        );
+
+        let song = Song::from(String::from("{key: C}\n[C]la [Am]lo [Bdim]\n"));
+        let song = song.with_roman_numerals();
+        let lines: Vec<String> = song.content[0]
+            .format(&Theme::default())
+            .iter()
+            .map(|spans| spans.0.iter().map(|span| span.content.as_ref()).collect())
+            .collect();
+        assert_eq!(lines, vec!["C  Am Bdim ", "I  vi vii°", "la lo "]);
     }
 }
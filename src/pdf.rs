@@ -0,0 +1,342 @@
+use crate::{
+    conf::Theme,
+    layout::{self, LineMetrics},
+    parser::{Song, SongLine},
+};
+use encoding_rs::WINDOWS_1252;
+use printpdf::{
+    BuiltinFont, Color as PdfColor, IndirectFontRef, Mm, PdfDocument, PdfDocumentReference,
+    PdfLayerReference, Rgb,
+};
+use std::{collections::BTreeSet, io};
+use tui::{
+    style::{Color, Modifier, Style},
+    text::Spans,
+};
+use unicode_width::UnicodeWidthStr;
+
+const MM_PER_PT: f32 = 25.4 / 72.0;
+/// Size of an A4 page in millimeters
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 15.0;
+/// Space above the song for its title, and below it for the page number
+const HEADER: f32 = 16.0;
+const FOOTER: f32 = 8.0;
+/// Size of the song text in points. It's set in Courier, which is 0.6 times as wide as
+/// it is high, so the chords line up with the lyrics below them.
+const FONT_SIZE: f32 = 10.0;
+const CHAR_WIDTH: f32 = FONT_SIZE * 0.6 * MM_PER_PT;
+/// Size of the entries of the table of contents, also in Courier so they can be measured
+const CONTENTS_SIZE: f32 = 11.0;
+const CONTENTS_CHAR_WIDTH: f32 = CONTENTS_SIZE * 0.6 * MM_PER_PT;
+const LINE_HEIGHT: f32 = FONT_SIZE * 1.2 * MM_PER_PT;
+/// Characters between two columns
+const COLUMN_PADDING: usize = 3;
+/// Songs listed on each page of the table of contents
+const CONTENTS_ROWS: usize = 40;
+
+/// Fonts used in the songbook
+struct Fonts {
+    heading: IndirectFontRef,
+    text: IndirectFontRef,
+    regular: IndirectFontRef,
+    bold: IndirectFontRef,
+    italic: IndirectFontRef,
+    bold_italic: IndirectFontRef,
+}
+
+impl Fonts {
+    fn new(doc: &PdfDocumentReference) -> io::Result<Self> {
+        let font = |font| doc.add_builtin_font(font).map_err(pdf_error);
+        Ok(Fonts {
+            heading: font(BuiltinFont::HelveticaBold)?,
+            text: font(BuiltinFont::Helvetica)?,
+            regular: font(BuiltinFont::Courier)?,
+            bold: font(BuiltinFont::CourierBold)?,
+            italic: font(BuiltinFont::CourierOblique)?,
+            bold_italic: font(BuiltinFont::CourierBoldOblique)?,
+        })
+    }
+
+    /// Courier in the weight and slant of `style`
+    fn for_style(&self, style: Style) -> &IndirectFontRef {
+        let bold = style.add_modifier.contains(Modifier::BOLD);
+        let italic = style.add_modifier.contains(Modifier::ITALIC);
+        match (bold, italic) {
+            (false, false) => &self.regular,
+            (true, false) => &self.bold,
+            (false, true) => &self.italic,
+            (true, true) => &self.bold_italic,
+        }
+    }
+}
+
+/// Songbook rendered by `songbook`
+pub struct Songbook {
+    pub pdf: Vec<u8>,
+    /// Characters the built-in PDF fonts don't have, which are printed as `?`. They only
+    /// cover the Windows-1252 character set.
+    pub unprintable: Vec<char>,
+}
+
+/// Renders the songs as a printable A4 songbook: a title page listing the songs, then
+/// every song starting on a new page with its chords above the lyrics in `columns`
+/// columns. Chords and lyrics are colored like `theme`, with light colors darkened so
+/// they show up on paper.
+pub fn songbook(
+    title: &str,
+    songs: &[Song],
+    columns: usize,
+    theme: &Theme,
+) -> io::Result<Songbook> {
+    let mut unprintable = BTreeSet::new();
+    let columns = columns.max(1);
+    let pages: Vec<Vec<Vec<Vec<SongLine>>>> =
+        songs.iter().map(|song| paginate(song, columns)).collect();
+
+    let (doc, page, layer) = PdfDocument::new(title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Songs");
+    let fonts = Fonts::new(&doc)?;
+    let contents_pages = songs.len().div_ceil(CONTENTS_ROWS).max(1);
+    let mut number = contents_pages;
+    let mut contents = vec![];
+    for (song, song_pages) in songs.iter().zip(&pages) {
+        contents.push((heading(song), number + 1));
+        number += song_pages.len();
+    }
+
+    let mut layer = doc.get_page(page).get_layer(layer);
+    layer.use_text(
+        printable(title, &mut unprintable),
+        24.0,
+        Mm(MARGIN),
+        Mm(PAGE_HEIGHT - MARGIN - 10.0),
+        &fonts.heading,
+    );
+    for (i, rows) in contents.chunks(CONTENTS_ROWS).enumerate() {
+        if i > 0 {
+            layer = new_page(&doc);
+        }
+        let mut y = PAGE_HEIGHT - MARGIN - 25.0;
+        let row_chars = ((PAGE_WIDTH - 2.0 * MARGIN) / CONTENTS_CHAR_WIDTH) as usize;
+        for (name, number) in rows {
+            let number = number.to_string();
+            // Long titles are cut off before they run into the page number
+            let name = truncate(
+                &printable(name, &mut unprintable),
+                row_chars.saturating_sub(number.len() + 2),
+            );
+            layer.use_text(name, CONTENTS_SIZE, Mm(MARGIN), Mm(y), &fonts.regular);
+            let x = PAGE_WIDTH - MARGIN - number.len() as f32 * CONTENTS_CHAR_WIDTH;
+            layer.use_text(number, CONTENTS_SIZE, Mm(x), Mm(y), &fonts.regular);
+            y -= 6.0;
+        }
+    }
+
+    let stride = layout::wrap_width(page_chars(), columns, 0) as f32 * CHAR_WIDTH;
+    let mut number = contents_pages;
+    for (song, song_pages) in songs.iter().zip(&pages) {
+        for (i, page) in song_pages.iter().enumerate() {
+            number += 1;
+            let layer = new_page(&doc);
+            let top = PAGE_HEIGHT - MARGIN;
+            let name = match i {
+                0 => heading(song),
+                _ => format!("{} (continued)", heading(song)),
+            };
+            layer.set_fill_color(print_color(None));
+            let name = printable(&name, &mut unprintable);
+            layer.use_text(name, 16.0, Mm(MARGIN), Mm(top - 6.0), &fonts.heading);
+            let details = printable(&details(song), &mut unprintable);
+            if !details.is_empty() {
+                layer.use_text(details, 9.0, Mm(MARGIN), Mm(top - 11.0), &fonts.text);
+            }
+            for (c, column) in page.iter().enumerate() {
+                let x = MARGIN + c as f32 * stride;
+                let mut y = top - HEADER;
+                for row in column.iter().flat_map(|line| line.format(theme)) {
+                    draw_row(&layer, &row, x, y, &fonts, &mut unprintable);
+                    y -= LINE_HEIGHT;
+                }
+            }
+            layer.set_fill_color(print_color(None));
+            let number = number.to_string();
+            let x = (PAGE_WIDTH - number.len() as f32 * CHAR_WIDTH) / 2.0;
+            layer.use_text(number, FONT_SIZE, Mm(x), Mm(MARGIN), &fonts.regular);
+        }
+    }
+    Ok(Songbook {
+        pdf: doc.save_to_bytes().map_err(pdf_error)?,
+        unprintable: unprintable.into_iter().collect(),
+    })
+}
+
+/// `text` with the characters the built-in fonts don't have replaced by `?`, adding them
+/// to `unprintable`
+fn printable(text: &str, unprintable: &mut BTreeSet<char>) -> String {
+    text.chars()
+        .map(|c| {
+            let (_, _, missing) = WINDOWS_1252.encode(c.encode_utf8(&mut [0; 4]));
+            if missing {
+                unprintable.insert(c);
+                return '?';
+            }
+            c
+        })
+        .collect()
+}
+
+/// `text` cut off with an ellipsis to be at most `chars` characters long
+fn truncate(text: &str, chars: usize) -> String {
+    match text.chars().count() > chars {
+        true => text
+            .chars()
+            .take(chars.saturating_sub(1))
+            .chain(Some('…'))
+            .collect(),
+        false => text.to_string(),
+    }
+}
+
+/// Characters of song text that fit on a line across the page
+fn page_chars() -> usize {
+    ((PAGE_WIDTH - 2.0 * MARGIN) / CHAR_WIDTH) as usize
+}
+
+/// Rows of song text that fit in a column
+fn page_rows() -> usize {
+    ((PAGE_HEIGHT - 2.0 * MARGIN - HEADER - FOOTER) / LINE_HEIGHT) as usize
+}
+
+/// Wraps the song to fit `columns` columns and splits it into pages of columns. A song
+/// always gets a page, even if it's empty.
+fn paginate(song: &Song, columns: usize) -> Vec<Vec<Vec<SongLine>>> {
+    let width = layout::wrap_width(page_chars(), columns, COLUMN_PADDING);
+    let lines: Vec<SongLine> = song
+        .content
+        .iter()
+        .flat_map(|line| line.wrap(width))
+        .collect();
+    let metrics: Vec<LineMetrics> = lines.iter().map(SongLine::metrics).collect();
+    let mut pages: Vec<Vec<Vec<SongLine>>> = layout::pages(&metrics, page_rows())
+        .chunks(columns)
+        .map(|page| {
+            page.iter()
+                .map(|range| lines[range.clone()].to_vec())
+                .collect()
+        })
+        .collect();
+    if pages.is_empty() {
+        pages.push(vec![]);
+    }
+    pages
+}
+
+fn new_page(doc: &PdfDocumentReference) -> PdfLayerReference {
+    let (page, layer) = doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Songs");
+    doc.get_page(page).get_layer(layer)
+}
+
+/// Title of the song as shown above it in the TUI
+fn heading(song: &Song) -> String {
+    match song.subtitle.is_empty() {
+        true => song.title.clone(),
+        false => format!("{} - {}", song.title, song.subtitle),
+    }
+}
+
+/// Key and capo of the song, printed below its title
+fn details(song: &Song) -> String {
+    let mut details = vec![];
+    if let Some(key) = song.key_name() {
+        details.push(format!("Key: {}", key));
+    }
+    if let Some(capo) = song.capo {
+        details.push(format!("Capo: {}", capo));
+    }
+    details.join("    ")
+}
+
+/// Draws a row of formatted song text with its top left at `x`, `y`, adding the
+/// characters that can't be printed to `unprintable`
+fn draw_row(
+    layer: &PdfLayerReference,
+    row: &Spans,
+    x: f32,
+    y: f32,
+    fonts: &Fonts,
+    unprintable: &mut BTreeSet<char>,
+) {
+    let mut column = 0;
+    for span in &row.0 {
+        if !span.content.trim().is_empty() {
+            layer.set_fill_color(print_color(span.style.fg));
+            layer.use_text(
+                printable(&span.content, unprintable),
+                FONT_SIZE,
+                Mm(x + column as f32 * CHAR_WIDTH),
+                Mm(y - FONT_SIZE * MM_PER_PT),
+                fonts.for_style(span.style),
+            );
+        }
+        column += span.content.width();
+    }
+}
+
+/// Color that reads well on white paper for a terminal color. Black, white and the
+/// default color are all printed black.
+fn print_color(color: Option<Color>) -> PdfColor {
+    let (r, g, b) = match color {
+        Some(Color::Red) | Some(Color::LightRed) => (0.75, 0.0, 0.0),
+        Some(Color::Green) | Some(Color::LightGreen) => (0.0, 0.5, 0.0),
+        Some(Color::Yellow) | Some(Color::LightYellow) => (0.6, 0.45, 0.0),
+        Some(Color::Blue) | Some(Color::LightBlue) => (0.0, 0.0, 0.75),
+        Some(Color::Magenta) | Some(Color::LightMagenta) => (0.6, 0.0, 0.6),
+        Some(Color::Cyan) | Some(Color::LightCyan) => (0.0, 0.45, 0.55),
+        Some(Color::DarkGray) => (0.4, 0.4, 0.4),
+        Some(Color::Rgb(r, g, b)) => (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0),
+        _ => (0.0, 0.0, 0.0),
+    };
+    PdfColor::Rgb(Rgb::new(r, g, b, None))
+}
+
+fn pdf_error(error: printpdf::Error) -> io::Error {
+    io::Error::other(format!("Couldn't write the PDF: {:?}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_songs_continue_on_the_next_page() {
+        let text: String = (0..page_rows() * 2)
+            .map(|i| format!("Line {}\n", i))
+            .collect();
+        let song = Song::from(format!("{{title: Long}}\n{}", text));
+        assert_eq!(paginate(&song, 2).len(), 1);
+        assert_eq!(paginate(&song, 1).len(), 2);
+        assert_eq!(paginate(&Song::from(String::new()), 2).len(), 1);
+    }
+
+    #[test]
+    fn songbook_is_a_pdf() {
+        let song = Song::from(String::from("{title: Test}\n{key: G}\n[G]Hello [C]world\n"));
+        let book = songbook("Sunday", &[song], 2, &Theme::default()).unwrap();
+        assert!(book.pdf.starts_with(b"%PDF"));
+        assert!(book.unprintable.is_empty());
+    }
+
+    #[test]
+    fn unprintable_characters_are_reported() {
+        let song = Song::from(String::from(
+            "{title: Łza}
+[G]Café → 歌
+",
+        ));
+        let book = songbook("Sunday", &[song], 2, &Theme::default()).unwrap();
+        assert_eq!(book.unprintable, vec!['Ł', '→', '歌']);
+        assert_eq!(truncate("Amazing grace", 8), "Amazing…");
+        assert_eq!(truncate("Amazing", 8), "Amazing");
+    }
+}
@@ -0,0 +1,149 @@
+use crate::{
+    collation::fold,
+    parser::{parse_key, Song},
+};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    static ref RE_FIELD: Regex = Regex::new(r#"(?i)\b([a-z]+):(?:"([^"]*)"?|(\S+))"#).unwrap();
+}
+
+/// Names that can be searched for with `name:value`
+pub const FIELDS: [&str; 13] = [
+    "is",
+    "tag",
+    "title",
+    "artist",
+    "composer",
+    "album",
+    "year",
+    "tempo",
+    "time",
+    "key",
+    "capo",
+    "ccli",
+    "copyright",
+];
+
+/// Search input split into the filters on song fields, like `artist:hillsong key:G`,
+/// and the rest of the text
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Query {
+    pub text: String,
+    /// Field names and the value they should contain, lowercase and without diacritics
+    pub fields: Vec<(String, String)>,
+}
+
+impl Query {
+    /// Takes the `name:value` pairs with a known field name out of `input`. Values with
+    /// spaces can be quoted, like `artist:"hillsong united"`.
+    pub fn parse(input: &str) -> Self {
+        let mut fields = vec![];
+        let text = RE_FIELD.replace_all(input, |captures: &regex::Captures| {
+            let name = captures[1].to_lowercase();
+            if !FIELDS.contains(&name.as_ref()) {
+                return captures[0].to_string();
+            }
+            let value = captures.get(2).or_else(|| captures.get(3)).unwrap();
+            fields.push((name, fold(value.as_str())));
+            String::new()
+        });
+        Query {
+            text: text.split_whitespace().collect::<Vec<_>>().join(" "),
+            fields,
+        }
+    }
+
+    /// Whether only starred songs should be found, with `is:starred`
+    pub fn starred(&self) -> bool {
+        self.fields
+            .iter()
+            .any(|(name, value)| name == "is" && value == "starred")
+    }
+
+    /// Whether a song with `fields`, see `song_fields`, has every field that's searched
+    /// for. Keys have to be the same key and tags the same tag, other fields only have to
+    /// contain the value. `is:` filters aren't about the song itself and are left to the
+    /// caller.
+    pub fn matches(&self, fields: &[(String, String)]) -> bool {
+        self.fields.iter().all(|(name, value)| {
+            let mut values = fields
+                .iter()
+                .filter(|(field, _)| field == name)
+                .map(|(_, field)| field);
+            match name.as_ref() {
+                "is" => true,
+                "tag" => values.any(|tag| fold(tag) == *value),
+                "key" => {
+                    value.is_empty()
+                        || parse_key(value)
+                            .is_some_and(|key| values.any(|field| parse_key(field) == Some(key)))
+                }
+                _ => values.any(|field| fold(field).contains(value)),
+            }
+        })
+    }
+}
+
+/// Values of the fields of `song` that can be searched for, with a pair for each tag.
+/// They're kept in the index, so searching doesn't have to parse every song.
+pub fn song_fields(song: &Song) -> Vec<(String, String)> {
+    let metadata = &song.metadata;
+    let values = vec![
+        ("title", Some(song.title.clone())),
+        ("key", song.key_name()),
+        ("capo", Some(song.capo.unwrap_or(0).to_string())),
+        ("artist", metadata.artist.clone()),
+        ("composer", metadata.composer.clone()),
+        ("album", metadata.album.clone()),
+        ("year", metadata.year.clone()),
+        ("tempo", metadata.tempo.clone()),
+        ("time", metadata.time.clone()),
+        ("ccli", metadata.ccli.clone()),
+        ("copyright", metadata.copyright.clone()),
+    ];
+    let tags = song.tags.iter().map(|tag| ("tag", Some(tag.clone())));
+    values
+        .into_iter()
+        .chain(tags)
+        .filter_map(|(name, value)| Some((name.to_string(), value?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_are_taken_out_of_the_text() {
+        let query = Query::parse(r#"artist:Hillsong grace key:G album:"The Peace Project""#);
+        assert_eq!(query.text, "grace");
+        assert_eq!(
+            query.fields,
+            vec![
+                ("artist".to_string(), "hillsong".to_string()),
+                ("key".to_string(), "g".to_string()),
+                ("album".to_string(), "the peace project".to_string()),
+            ]
+        );
+        assert_eq!(Query::parse("Time: 10:30").text, "Time: 10:30");
+    }
+
+    #[test]
+    fn songs_match_every_field() {
+        let song = Song::from(
+            "{title: Oceans}\n{artist: Hillsong United}\n{key: D}\n{tag: Worship}\n[D]Spirit"
+                .to_string(),
+        );
+        let fields = song_fields(&song);
+        assert!(Query::parse("artist:hillsong key:D").matches(&fields));
+        assert!(Query::parse("title:ocean").matches(&fields));
+        assert!(Query::parse("tag:worship").matches(&fields));
+        assert!(!Query::parse("artist:hillsong key:G").matches(&fields));
+        assert!(!Query::parse("key:Dm").matches(&fields));
+        assert!(!Query::parse("album:x").matches(&fields));
+        assert!(Query::parse("is:starred").starred());
+        assert!(Query::parse("is:starred title:oceans").matches(&fields));
+    }
+}
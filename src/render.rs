@@ -0,0 +1,179 @@
+use crate::{conf::Config, parser::Song, ui};
+use tui::{
+    backend::TestBackend,
+    buffer::Buffer,
+    style::{Color, Modifier},
+    Terminal,
+};
+
+/// Renders a song into a buffer of the given size without a real terminal, using the
+/// same drawing code as the TUI.
+pub fn render_song(song: &Song, config: &Config, width: u16, height: u16) -> Buffer {
+    let mut terminal =
+        Terminal::new(TestBackend::new(width, height)).expect("Test backend can't fail");
+    terminal
+        .draw(|f| {
+            let size = f.size();
+            ui::draw_song_view(f, Some(song), config, 0, size);
+        })
+        .expect("Test backend can't fail");
+    terminal.backend().buffer().clone()
+}
+
+/// Converts a rendered buffer to text, one line per row with trailing spaces removed.
+/// When `ansi` is set, colors and modifiers are kept as ANSI escape sequences.
+pub fn buffer_to_string(buffer: &Buffer, ansi: bool) -> String {
+    let area = buffer.area;
+    let mut output = String::new();
+    for y in area.top()..area.bottom() {
+        let mut line = String::new();
+        let mut style = None;
+        for x in area.left()..area.right() {
+            let cell = buffer.get(x, y);
+            if ansi && style != Some((cell.fg, cell.bg, cell.modifier)) {
+                style = Some((cell.fg, cell.bg, cell.modifier));
+                line.push_str(&sgr(cell.fg, cell.bg, cell.modifier));
+            }
+            line.push_str(&cell.symbol);
+        }
+        if ansi {
+            line.push_str("\x1b[0m");
+        } else {
+            line.truncate(line.trim_end().len());
+        }
+        output.push_str(&line);
+        output.push('\n');
+    }
+    output
+}
+
+/// Select Graphic Rendition sequence that resets the style and then applies the given one
+fn sgr(fg: Color, bg: Color, modifier: Modifier) -> String {
+    let mut codes = vec![String::from("0")];
+    let modifiers = [
+        (Modifier::BOLD, "1"),
+        (Modifier::DIM, "2"),
+        (Modifier::ITALIC, "3"),
+        (Modifier::UNDERLINED, "4"),
+        (Modifier::SLOW_BLINK, "5"),
+        (Modifier::RAPID_BLINK, "6"),
+        (Modifier::REVERSED, "7"),
+        (Modifier::HIDDEN, "8"),
+        (Modifier::CROSSED_OUT, "9"),
+    ];
+    for (m, code) in modifiers.iter() {
+        if modifier.contains(*m) {
+            codes.push(code.to_string());
+        }
+    }
+    if let Some(code) = color_code(fg, 30) {
+        codes.push(code);
+    }
+    if let Some(code) = color_code(bg, 40) {
+        codes.push(code);
+    }
+    format!("\x1b[{}m", codes.join(";"))
+}
+
+fn color_code(color: Color, base: u8) -> Option<String> {
+    let code = match color {
+        Color::Reset => return None,
+        Color::Black => base,
+        Color::Red => base + 1,
+        Color::Green => base + 2,
+        Color::Yellow => base + 3,
+        Color::Blue => base + 4,
+        Color::Magenta => base + 5,
+        Color::Cyan => base + 6,
+        Color::Gray => base + 7,
+        Color::DarkGray => base + 60,
+        Color::LightRed => base + 61,
+        Color::LightGreen => base + 62,
+        Color::LightYellow => base + 63,
+        Color::LightBlue => base + 64,
+        Color::LightMagenta => base + 65,
+        Color::LightCyan => base + 66,
+        Color::White => base + 67,
+        Color::Indexed(i) => return Some(format!("{};5;{}", base + 8, i)),
+        Color::Rgb(r, g, b) => return Some(format!("{};2;{};{};{}", base + 8, r, g, b)),
+    };
+    Some(code.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conf::Gutter;
+
+    #[test]
+    fn renders_plain_text() {
+        let song = Song::from(String::from(
+            "{title: Test}\n{subtitle: Me}\n[G]Hello [C]world\n",
+        ));
+        let buffer = render_song(&song, &Config::default(), 30, 6);
+        assert_eq!(
+            buffer_to_string(&buffer, false),
+            "┌Test - Me───────────────────┐
+│G     C                     │
+│Hello world                 │
+│                            │
+│Chords: G  C                │
+└────────────────────────────┘
+"
+        );
+    }
+
+    #[test]
+    fn column_rule_is_drawn_between_columns() {
+        let song = Song::from(String::from("{title: Test}\nOne\nTwo\nThree\nFour\n"));
+        let config = Config {
+            columns: Some(2),
+            column_rule: Some(String::from("|")),
+            chord_index: false,
+            ..Config::default()
+        };
+        let buffer = render_song(&song, &config, 20, 6);
+        assert_eq!(
+            buffer_to_string(&buffer, false),
+            "┌Test - ───────────┐
+│One    | Three    │
+│Two    | Four     │
+│       |          │
+│       |          │
+└──────────────────┘
+"
+        );
+    }
+
+    #[test]
+    fn gutter_shows_line_numbers_next_to_lyrics() {
+        let song = Song::from(String::from("{title: Test}\n[G]One\n\nTwo\n"));
+        let config = Config {
+            gutter: Gutter::Numbers,
+            chord_index: false,
+            ..Config::default()
+        };
+        let buffer = render_song(&song, &config, 20, 8);
+        assert_eq!(
+            buffer_to_string(&buffer, false),
+            "┌Test - ───────────┐
+│  G               │
+│1 One             │
+│                  │
+│3 Two             │
+│                  │
+│                  │
+└──────────────────┘
+"
+        );
+    }
+
+    #[test]
+    fn ansi_output_contains_styles() {
+        let song = Song::from(String::from("{title: Test}\n[G]Hello\n"));
+        let buffer = render_song(&song, &Config::default(), 20, 4);
+        let output = buffer_to_string(&buffer, true);
+        assert!(output.contains("\x1b[0;34mG"));
+        assert!(output.ends_with("\x1b[0m\n"));
+    }
+}
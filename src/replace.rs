@@ -0,0 +1,127 @@
+use crate::{app::FileType, encoding, index::Index};
+use regex::{NoExpand, Regex};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Search and replacement for a library-wide find and replace. Matches never span
+/// multiple lines.
+pub struct Replacer {
+    regex: Regex,
+    replacement: String,
+    /// Whether `$1` style references in the replacement are expanded
+    expand: bool,
+}
+
+impl Replacer {
+    /// Replaces `pattern` literally, or as a regex with capture group references in the
+    /// replacement when `regex` is set
+    pub fn new(pattern: &str, replacement: &str, regex: bool) -> Result<Self, regex::Error> {
+        let pattern = match regex {
+            true => pattern.to_string(),
+            false => regex::escape(pattern),
+        };
+        Ok(Replacer {
+            regex: Regex::new(&pattern)?,
+            replacement: replacement.to_string(),
+            expand: regex,
+        })
+    }
+
+    fn replace_line(&self, line: &str) -> String {
+        match self.expand {
+            true => self.regex.replace_all(line, self.replacement.as_str()),
+            false => self.regex.replace_all(line, NoExpand(&self.replacement)),
+        }
+        .into_owned()
+    }
+
+    /// Applies the replacement to every line of `text`, keeping its line endings
+    pub fn replace(&self, text: &str) -> String {
+        text.split_inclusive('\n')
+            .map(|line| {
+                let content = line.trim_end_matches(['\r', '\n']);
+                self.replace_line(content) + &line[content.len()..]
+            })
+            .collect()
+    }
+
+    /// Finds every song and playlist in the index that would change
+    pub fn find(&self, index: &Index) -> Vec<Change> {
+        let mut changes: Vec<Change> = index
+            .iter()
+            .filter(|(_, files)| !matches!(files, [FileType::Folder(_)]))
+            .filter_map(|(path, _)| self.change(path).ok().flatten())
+            .collect();
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        changes
+    }
+
+    fn change(&self, path: &Path) -> io::Result<Option<Change>> {
+        let original = encoding::read_to_string(path)?.text;
+        if !self.regex.is_match(&original) {
+            return Ok(None);
+        }
+        let replaced = self.replace(&original);
+        Ok((replaced != original).then(|| Change {
+            path: path.to_path_buf(),
+            original,
+            replaced,
+        }))
+    }
+}
+
+/// Pending replacement in a single file
+pub struct Change {
+    pub path: PathBuf,
+    pub original: String,
+    pub replaced: String,
+}
+
+impl Change {
+    /// Line numbers and contents of the lines that change, before and after
+    pub fn changed_lines(&self) -> Vec<(usize, &str, &str)> {
+        self.original
+            .lines()
+            .zip(self.replaced.lines())
+            .enumerate()
+            .filter(|(_, (before, after))| before != after)
+            .map(|(i, (before, after))| (i + 1, before, after))
+            .collect()
+    }
+
+    pub fn apply(&self) -> io::Result<()> {
+        fs::write(&self.path, &self.replaced)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literal_replacement() {
+        let replacer = Replacer::new("a.b", "$1", false).unwrap();
+        assert_eq!(replacer.replace("a.b axb\r\na.b"), "$1 axb\r\n$1");
+    }
+
+    #[test]
+    fn regex_replacement_stays_within_lines() {
+        let replacer = Replacer::new(r"(\w+) (\w+)$", "$2 $1", true).unwrap();
+        assert_eq!(
+            replacer.replace("one two\nthree four\n"),
+            "two one\nfour three\n"
+        );
+    }
+
+    #[test]
+    fn lists_changed_lines() {
+        let change = Change {
+            path: PathBuf::new(),
+            original: String::from("a\nb\nc"),
+            replaced: String::from("a\nB\nc"),
+        };
+        assert_eq!(change.changed_lines(), vec![(2, "b", "B")]);
+    }
+}
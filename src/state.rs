@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// Version of the state file format, bump this when the layout changes incompatibly
+const STATE_VERSION: u32 = 1;
+
+/// Maximum amount of songs remembered in the recently opened list
+const MAX_RECENT: usize = 50;
+
+/// State that is remembered between runs. Unlike `Config` this is written by gpro
+/// itself and isn't meant to be edited by hand.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SavedState {
+    pub version: u32,
+    /// Names of recently opened songs, most recent first
+    pub recent: Vec<String>,
+    pub favorites: Vec<String>,
+    /// Transposition in semitones per song name
+    pub transpositions: HashMap<String, i32>,
+    /// Scroll position per song name
+    pub scroll: HashMap<String, usize>,
+    pub session: Session,
+}
+
+/// What was open when gpro was last closed
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub song: Option<String>,
+    /// Width the song list was resized to
+    pub sidebar_width: Option<u16>,
+    /// Names of the songs open in tabs
+    pub tabs: Vec<String>,
+}
+
+impl Default for SavedState {
+    fn default() -> Self {
+        SavedState {
+            version: STATE_VERSION,
+            recent: vec![],
+            favorites: vec![],
+            transpositions: HashMap::new(),
+            scroll: HashMap::new(),
+            session: Session::default(),
+        }
+    }
+}
+
+impl SavedState {
+    /// Location of the state file, following the XDG base directory spec
+    pub fn default_path() -> Option<PathBuf> {
+        match env::var("XDG_STATE_HOME") {
+            Ok(state) => Some(PathBuf::from(state)),
+            Err(_) => env::var("HOME")
+                .ok()
+                .map(|home| PathBuf::from(home).join(".local").join("state")),
+        }
+        .map(|state| state.join("gpro").join("state.yml"))
+    }
+
+    /// Loads the state from `file`. A state file written by a newer version of gpro is
+    /// ignored rather than risking misinterpreting it.
+    pub fn load(file: &Path) -> Result<SavedState, Box<dyn std::error::Error>> {
+        let contents = fs::read_to_string(file)?;
+        let state: SavedState = serde_yaml::from_str(&contents)?;
+        if state.version > STATE_VERSION {
+            return Err(Box::new(std::io::Error::other(format!(
+                "State file version {} is newer than supported version {}",
+                state.version, STATE_VERSION
+            ))));
+        }
+        Ok(SavedState {
+            version: STATE_VERSION,
+            ..state
+        })
+    }
+
+    pub fn save(&self, file: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(file, serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Remembers `name` as the most recently opened song
+    pub fn opened(&mut self, name: &str) {
+        self.recent.retain(|recent| recent != name);
+        self.recent.insert(0, name.to_string());
+        self.recent.truncate(MAX_RECENT);
+        self.session.song = Some(name.to_string());
+    }
+}
@@ -0,0 +1,56 @@
+use crate::parser::Song;
+
+/// How often each chord is played in one or more songs
+pub struct ChordStats {
+    /// What the statistics are about, like a song or playlist title
+    pub name: String,
+    /// Chords with how often they occur, most frequent first. Chords that are played
+    /// equally often keep the order they first appear in.
+    pub counts: Vec<(String, usize)>,
+}
+
+impl ChordStats {
+    pub fn from_songs<'a>(name: &str, songs: impl IntoIterator<Item = &'a Song>) -> Self {
+        let mut counts: Vec<(String, usize)> = vec![];
+        for chord in songs.into_iter().flat_map(Song::chords) {
+            match counts.iter_mut().find(|(c, _)| c == chord) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((chord.to_string(), 1)),
+            }
+        }
+        counts.sort_by(|(_, a), (_, b)| b.cmp(a));
+        ChordStats {
+            name: name.to_string(),
+            counts,
+        }
+    }
+
+    pub fn unique(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().map(|(_, count)| count).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_chords_across_songs() {
+        let a = Song::from(String::from("[G]la [C]la [G]la [D]"));
+        let b = Song::from(String::from("{key: G}\n{Capo-Bass_Guitar: 2}\n[A]la [E]la"));
+        let stats = ChordStats::from_songs("Set", [&a, &b]);
+        let counts: Vec<(&str, usize)> = stats
+            .counts
+            .iter()
+            .map(|(chord, count)| (chord.as_str(), *count))
+            .collect();
+        // Capo 2 turns the A and E into G and D
+        assert_eq!(counts, vec![("G", 3), ("D", 2), ("C", 1)]);
+        assert_eq!(stats.unique(), 3);
+        assert_eq!(stats.total(), 6);
+    }
+}
@@ -1,21 +1,40 @@
 use crate::{
-    app::{App, FileType},
-    conf::Theme,
+    app::{App, AppState, FileType, TEXT_SEARCH_PREFIX},
+    chords::{self, Instrument},
+    conf::{Config, Gutter, SortMode, Theme},
+    files::FileAction,
+    layout::{self, LineMetrics, Shown},
     parser::*,
 };
+use lazy_static::lazy_static;
+use std::{collections::HashMap, ops::Range, sync::Mutex};
 use tui::{
     backend::Backend,
-    layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Style},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
     text::{Span, Spans, Text},
-    widgets::{Block, Borders, List, ListItem, Paragraph},
+    widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Tabs, Wrap},
     Frame,
 };
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+lazy_static! {
+    /// Song lines wrapped by `wrap`, for each width they were wrapped to
+    static ref WRAPPED: Mutex<HashMap<usize, HashMap<SongLine, Wrapped>>> = Mutex::default();
+}
+
+/// Widths and lines per width `wrap` remembers, enough for a few songs in a few panes
+const WRAPPED_WIDTHS: usize = 8;
+const WRAPPED_LINES: usize = 4096;
+
+/// Parts of a song line wrapped to a width, each with its metrics
+type Wrapped = Vec<(SongLine, LineMetrics)>;
 
 pub fn draw_song_list<B>(f: &mut Frame<B>, app: &mut App, layout_chunk: Rect)
 where
     B: Backend,
 {
+    let playlist = app.get_nav().current().playlist;
     // Format search results into Vec<ListItem>
     let searchresults: Vec<ListItem> = app
         .get_nav()
@@ -23,20 +42,48 @@ where
         .files
         .iter()
         .map(|file| {
-            ListItem::new(Spans::from(match file {
-                FileType::Folder(_) => Span::styled(
-                    app.config.icons.folder.clone() + &file.name(),
-                    app.config.theme.folder.to_style(),
-                ),
-                FileType::Song(_) => Span::styled(
-                    app.config.icons.song.clone() + &file.name(),
-                    app.config.theme.song.to_style(),
-                ),
-                FileType::Playlist(_) => Span::styled(
-                    app.config.icons.playlist.clone() + &file.name(),
-                    app.config.theme.playlist.to_style(),
-                ),
-            }))
+            let (icon, style) = match file {
+                FileType::Folder(_) => (&app.config.icons.folder, &app.config.theme.folder),
+                // Playlist entries that aren't in the library stand out
+                FileType::Song(name) if playlist && !app.has_song(name) => {
+                    (&app.config.icons.song, &app.config.theme.warning)
+                }
+                FileType::Song(_) => (&app.config.icons.song, &app.config.theme.song),
+                FileType::Playlist(_) => (&app.config.icons.playlist, &app.config.theme.playlist),
+                FileType::Tag(_) => (&app.config.icons.tag, &app.config.theme.folder),
+            };
+            let mut spans = vec![Span::styled(icon.clone(), style.to_style())];
+            // Characters that matched the search stand out in the results
+            let matches = match app.state {
+                AppState::Searching => app.search_matches.get(file),
+                _ => None,
+            };
+            for (i, c) in file.name().chars().enumerate() {
+                let style = match matches {
+                    Some(matches) if matches.contains(&i) => style
+                        .to_style()
+                        .patch(app.config.theme.search_match.to_style()),
+                    _ => style.to_style(),
+                };
+                spans.push(Span::styled(c.to_string(), style));
+            }
+            if app.is_favorite(file) {
+                spans.push(Span::styled(
+                    app.config.icons.favorite.clone(),
+                    style.to_style(),
+                ));
+            }
+            let mut lines = vec![Spans::from(spans)];
+            // Results found by their text show where
+            if app.state == AppState::Searching {
+                if let Some(snippet) = app.search_snippets.get(file) {
+                    lines.push(Spans::from(Span::styled(
+                        format!("  {}", snippet),
+                        app.config.theme.comment.to_style(),
+                    )));
+                }
+            }
+            ListItem::new(Text::from(lines))
         })
         .collect();
 
@@ -44,7 +91,7 @@ where
     let songlist = List::new(searchresults)
         .block(
             Block::default()
-                .title(app.get_nav().current().name.clone())
+                .title(list_title(app))
                 .borders(Borders::ALL),
         )
         .highlight_style(Style::default().bg(Color::DarkGray));
@@ -56,6 +103,19 @@ where
     );
 }
 
+/// Name of the open folder, along with the sort order if it isn't by title
+fn list_title(app: &App) -> String {
+    let folder = app.get_nav().current();
+    let mut title = folder.name.clone();
+    if app.sort != SortMode::Title && (folder.path.is_some() || folder.view.is_some()) {
+        title += &format!(" (by {})", app.sort.label());
+    }
+    if app.indexing {
+        title += " (indexing…)";
+    }
+    title
+}
+
 pub fn draw_search_bar<B>(f: &mut Frame<B>, app: &mut App, layout_chunk: Rect)
 where
     B: Backend,
@@ -63,15 +123,16 @@ where
     // Only show last characters that fit in search box
     let inner_size = (layout_chunk.width - 3) as usize; // Two border pixels, one cursor pixel
     let input = &app.input;
-    let input_length = input.chars().count();
-    let mut inputtext = &input[..];
-    if input_length >= inner_size {
-        inputtext = &input[input_length - inner_size..];
-    }
-
+    let title = match (&app.search_error, app.regex_search) {
+        (Some(_), _) => "Invalid regex",
+        (None, true) if input.starts_with(TEXT_SEARCH_PREFIX) => "Regex in lyrics and chords",
+        (None, true) => "Regex",
+        (None, false) if input.starts_with(TEXT_SEARCH_PREFIX) => "Search lyrics and chords",
+        (None, false) => "Search",
+    };
     // Add cursor if search box is selected
     let input = vec![
-        Span::from(inputtext),
+        Span::from(tail(input, inner_size)),
         Span::styled("|", app.config.theme.selected.to_style()),
     ];
 
@@ -80,7 +141,7 @@ where
         Block::default()
             .borders(Borders::ALL)
             .border_style(app.config.theme.selected.to_style())
-            .title(Span::from("Search")),
+            .title(Span::from(title)),
     );
 
     f.render_widget(searchbox, layout_chunk);
@@ -95,12 +156,17 @@ where
         .border_style(app.config.theme.selected.to_style())
         .title(Span::from("Transpose"));
 
+    // A key that's being typed replaces the current one, the song is shown in it on enter
     let transpose = Paragraph::new(Text::from(match &app.song {
-        Some(song) => match song.key {
+        Some(_) if !app.key_input.is_empty() => Spans::from(vec![
+            Span::from(app.key_input.as_str()),
+            Span::styled("|", app.config.theme.selected.to_style()),
+        ]),
+        Some(song) => Spans::from(match song.key_name() {
             Some(key) => key.to_string(),
             None => String::from("No key found"),
-        },
-        None => String::from("No song selected"),
+        }),
+        None => Spans::from("No song selected"),
     }))
     .block(transpose_block);
     //    match &app.song {
@@ -112,106 +178,1212 @@ where
     f.render_widget(transpose, layout_chunk)
 }
 
+/// Single line with the mode on the left and the key, capo, transposition and scroll
+/// position of the open song on the right
+pub fn draw_status_bar<B>(f: &mut Frame<B>, app: &App, layout_chunk: Rect)
+where
+    B: Backend,
+{
+    if let Some((message, _)) = &app.notification {
+        let notification = Paragraph::new(Span::raw(format!(" {}  (Esc)", message)))
+            .style(app.config.theme.notification.to_style());
+        f.render_widget(notification, layout_chunk);
+        return;
+    }
+    let mut modes = vec![app.state.label().to_string()];
+    if app.lyrics_only {
+        modes.push("lyrics only".to_string());
+    }
+    if app.chords_only {
+        modes.push("chords only".to_string());
+    }
+    if app.nashville {
+        modes.push("Nashville".to_string());
+    }
+    if app.auto_scroll {
+        modes.push(format!("auto-scroll {:.1}s", app.scroll_interval));
+    }
+    if app.config.paging {
+        modes.push("pages".to_string());
+    }
+    if !app.config.themes.is_empty() {
+        modes.push(app.config.theme.name.clone());
+    }
+    if let Some(setlist) = &app.setlist {
+        modes.push(format!(
+            "{} {}/{}",
+            setlist.title,
+            setlist.position + 1,
+            setlist.songs.len()
+        ));
+        match setlist.next() {
+            Some(next) if app.config.setlist_preview => modes.push(format!("next: {}", next)),
+            _ => (),
+        }
+    }
+    let mut status = vec![];
+    if let Some(song) = &app.song {
+        if let Some(key) = song.key_name() {
+            status.push(format!("Key {}", key));
+        }
+        if let Some(capo) = song.capo {
+            status.push(format!("Capo {}", capo));
+        }
+        if song.signed_offset() != 0 {
+            status.push(format!("Transposed {:+}", song.signed_offset()));
+        }
+        if let Some(section) = app.current_section() {
+            status.push(section);
+        }
+        let lines = app.displayed_song().map_or(0, |song| song.content.len());
+        status.push(format!("Line {}/{}", (app.scroll + 1).min(lines), lines));
+        if let Some((page, pages)) = app.page() {
+            status.push(format!("Page {}/{}", page + 1, pages));
+        }
+    }
+    let left = format!(" {}", modes.join(" · "));
+    let right = format!("{} ", status.join(" · "));
+    let padding = (layout_chunk.width as usize).saturating_sub(left.width() + right.width());
+    let bar = Paragraph::new(Span::raw(format!(
+        "{}{}{}",
+        left,
+        " ".repeat(padding),
+        right
+    )))
+    .style(app.config.theme.status_bar.to_style());
+    f.render_widget(bar, layout_chunk);
+}
+
 pub fn draw_song<B>(f: &mut Frame<B>, app: &App, layout_chunk: Rect)
 where
     B: Backend,
 {
-    match &app.song {
+    // Problems in the song file are shown below the part of the song that could be read
+    let layout_chunk = match &app.song_error {
+        Some(error) => {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(0), Constraint::Length(3)].as_ref())
+                .split(layout_chunk);
+            let error = Paragraph::new(Span::styled(
+                error.to_string(),
+                app.config.theme.comment.to_style(),
+            ))
+            .block(Block::default().borders(Borders::ALL).title("Parse error"));
+            f.render_widget(error, chunks[1]);
+            chunks[0]
+        }
+        None => layout_chunk,
+    };
+    // Tabs are only shown once there is more than one
+    let tabs = &app.saved.session.tabs;
+    let layout_chunk = match tabs.len() > 1 && layout_chunk.height > 3 {
+        true => {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(1), Constraint::Min(0)].as_ref())
+                .split(layout_chunk);
+            let titles = tabs.iter().map(|tab| Spans::from(tab.as_str())).collect();
+            let tabs = Tabs::new(titles)
+                .select(app.tab)
+                .style(app.config.theme.song.to_style())
+                .highlight_style(app.config.theme.selected.to_style());
+            f.render_widget(tabs, chunks[0]);
+            chunks[1]
+        }
+        false => layout_chunk,
+    };
+    // The song without focus is dimmed
+    let layout_chunk = match &app.split {
+        Some(split) => {
+            let chunks = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)].as_ref())
+                .split(layout_chunk);
+            let (focused, other) = match app.split_left {
+                true => (chunks[0], chunks[1]),
+                false => (chunks[1], chunks[0]),
+            };
+            draw_song_view(f, Some(&split.song), &app.config, split.scroll, other);
+            let dim = Block::default().style(Style::default().add_modifier(Modifier::DIM));
+            f.render_widget(dim, other);
+            focused
+        }
+        None => layout_chunk,
+    };
+    if let Some(preview) = app.preview() {
+        draw_song_view(f, Some(&preview), &app.config, 0, layout_chunk);
+        let dim = Block::default().style(Style::default().add_modifier(Modifier::DIM));
+        f.render_widget(dim, layout_chunk);
+        return;
+    }
+    let song = app.displayed_song();
+    if song.is_none() {
+        draw_empty_state(f, app, layout_chunk);
+        app.shown.take();
+        return;
+    }
+    if !app.show_notes
+        && !app.show_source_comments
+        && !app.show_diagnostics
+        && !app.show_chord_diagrams
+    {
+        let shown = draw_song_view(f, song.as_deref(), &app.config, app.scroll, layout_chunk);
+        app.shown.replace(shown);
+        return;
+    }
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Min(40), Constraint::Percentage(30)].as_ref())
+        .split(layout_chunk);
+    let shown = draw_song_view(f, song.as_deref(), &app.config, app.scroll, chunks[0]);
+    app.shown.replace(shown);
+
+    let mut panes = vec![];
+    if app.show_notes {
+        let notes = app.song.iter().flat_map(|song| song.notes.iter().cloned());
+        panes.push(("Notes", "No notes", notes.collect::<Vec<_>>()));
+    }
+    if app.show_source_comments {
+        let comments = app.song.iter().flat_map(|song| {
+            song.source_comments
+                .iter()
+                .map(|(line, comment)| format!("{}: {}", line, comment))
+        });
+        panes.push(("Source comments", "No comments", comments.collect()));
+    }
+    if app.show_diagnostics {
+        let chords = app.song.iter().flat_map(|song| {
+            song.invalid_chords
+                .iter()
+                .map(|(line, chord)| format!("{}: [{}] is not a chord", line, chord))
+        });
+        panes.push(("Diagnostics", "No problems", chords.collect()));
+    }
+    if app.show_chord_diagrams {
+        let width = chunks[1].width.saturating_sub(2) as usize;
+        let lines = match &app.song {
+            Some(song) => {
+                let instrument = Instrument::from_config(
+                    app.config.instrument.as_deref(),
+                    app.config.tuning.as_deref(),
+                );
+                diagram_lines(song, &instrument, width)
+            }
+            None => vec![],
+        };
+        panes.push(("Chord diagrams", "No chords", lines));
+    }
+    let constraints = vec![Constraint::Ratio(1, panes.len() as u32); panes.len()];
+    let pane_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(constraints)
+        .split(chunks[1]);
+    for ((title, empty, lines), chunk) in panes.into_iter().zip(pane_chunks) {
+        draw_side_pane(f, title, empty, lines, &app.config.theme, chunk);
+    }
+}
+
+/// Diagrams of every chord in the song, as many next to each other as fit in `width`
+fn diagram_lines(song: &Song, instrument: &Instrument, width: usize) -> Vec<String> {
+    let (diagrams, missing) = chords::song_diagrams(song, instrument);
+    let diagrams: Vec<Vec<String>> = diagrams.iter().map(|def| def.diagram()).collect();
+    let diagram_width = diagrams
+        .iter()
+        .flatten()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    let per_row = ((width + 3) / (diagram_width + 3)).max(1);
+    let mut lines = vec![];
+    for row in diagrams.chunks(per_row) {
+        let height = row.iter().map(Vec::len).max().unwrap_or(0);
+        for i in 0..height {
+            let line: Vec<String> = row
+                .iter()
+                .map(|diagram| {
+                    let line = diagram.get(i).map(String::as_str).unwrap_or("");
+                    format!("{:width$}", line, width = diagram_width)
+                })
+                .collect();
+            lines.push(line.join("   ").trim_end().to_string());
+        }
+        lines.push(String::new());
+    }
+    if !missing.is_empty() {
+        lines.push(format!("No diagram for {}", missing.join(", ")));
+    }
+    lines
+}
+
+/// Draws text next to the song, like its notes
+fn draw_side_pane<B>(
+    f: &mut Frame<B>,
+    title: &str,
+    empty: &str,
+    lines: Vec<String>,
+    theme: &Theme,
+    layout_chunk: Rect,
+) where
+    B: Backend,
+{
+    let text: Vec<Spans> = match lines.is_empty() {
+        false => lines.into_iter().map(Spans::from).collect(),
+        true => vec![Spans::from(Span::styled(
+            empty.to_string(),
+            theme.comment.to_style(),
+        ))],
+    };
+    let pane = Paragraph::new(text).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title.to_string()),
+    );
+    f.render_widget(pane, layout_chunk);
+}
+
+/// Draws the built-in editor with a live preview of the song next to it
+pub fn draw_editor<B>(f: &mut Frame<B>, app: &mut App, layout_chunk: Rect)
+where
+    B: Backend,
+{
+    let editor = match &mut app.editor {
+        Some(editor) => editor,
+        None => return,
+    };
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+        .split(layout_chunk);
+
+    let inner_height = chunks[0].height.saturating_sub(2);
+    let inner_width = chunks[0].width.saturating_sub(2);
+    editor.scroll_to_cursor(inner_height as usize);
+    let (row, col) = editor.cursor;
+    // Wide characters take up two cells before the cursor
+    let col: String = editor.lines[row].chars().take(col).collect();
+    let col = col.width();
+    let x_scroll = (col as u16).saturating_sub(inner_width.saturating_sub(1));
+
+    let lines: Vec<Spans> = editor
+        .lines
+        .iter()
+        .skip(editor.scroll)
+        .take(inner_height as usize)
+        .map(|line| Spans::from(line.as_str()))
+        .collect();
+    let title = format!(
+        "{}{}",
+        editor
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default(),
+        if editor.modified { " [+]" } else { "" }
+    );
+    let text = Paragraph::new(lines).scroll((0, x_scroll)).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(app.config.theme.selected.to_style())
+            .title(Span::from(title)),
+    );
+    f.render_widget(text, chunks[0]);
+    f.set_cursor(
+        chunks[0].x + 1 + col as u16 - x_scroll,
+        chunks[0].y + 1 + (row - editor.scroll) as u16,
+    );
+
+    let (preview, _) = Song::parse(editor.text(), None, app.config.instrument.as_deref());
+    draw_song_view(f, Some(&preview), &app.config, 0, chunks[1]);
+}
+
+/// Draws the input for a file operation, or the confirmation when deleting
+pub fn draw_prompt<B>(f: &mut Frame<B>, app: &App, layout_chunk: Rect)
+where
+    B: Backend,
+{
+    let prompt = match &app.prompt {
+        Some(prompt) => prompt,
+        None => return,
+    };
+    let file_name = prompt
+        .path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let (title, input) = match prompt.action {
+        FileAction::Delete => (
+            format!("Delete {}?", file_name),
+            vec![Span::styled("y/n", app.config.theme.selected.to_style())],
+        ),
+        action => {
+            // Only show last characters that fit in the box
+            let inner_size = layout_chunk.width.saturating_sub(3) as usize;
+            (
+                action.label().to_string(),
+                vec![
+                    Span::from(tail(&prompt.input, inner_size).to_string()),
+                    Span::styled("|", app.config.theme.selected.to_style()),
+                ],
+            )
+        }
+    };
+    let (title, border_style) = match &prompt.error {
+        Some(error) => (error.clone(), app.config.theme.comment.to_style()),
+        None => (title, app.config.theme.selected.to_style()),
+    };
+    let promptbox = Paragraph::new(Text::from(Spans::from(input))).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(border_style)
+            .title(Span::from(title)),
+    );
+    f.render_widget(promptbox, layout_chunk);
+}
+
+/// Explains what to do when no song is open, or why there are no songs to open
+fn draw_empty_state<B>(f: &mut Frame<B>, app: &App, layout_chunk: Rect)
+where
+    B: Backend,
+{
+    let keys = &app.config.keybinds;
+    let roots = app.config.roots();
+    let missing: Vec<_> = roots.iter().filter(|root| !root.is_dir()).collect();
+    let (title, mut lines) = if !missing.is_empty() {
+        let mut lines = vec![String::from("These song folders don't exist:")];
+        lines.extend(missing.iter().map(|root| format!("  {}", root.display())));
+        lines.push(String::new());
+        lines.push(String::from(
+            "Set `path` in the config to the folder with your songs,",
+        ));
+        lines.push(String::from("or open one with `gpro view <folder>`."));
+        ("Library not found", lines)
+    } else if app.indexing && app.library_is_empty() {
+        (
+            "Scanning",
+            vec![String::from(
+                "Looking for songs, this only takes long the first time…",
+            )],
+        )
+    } else if app.library_is_empty() {
+        let mut lines = vec![String::from("No songs were found in:")];
+        lines.extend(roots.iter().map(|root| format!("  {}", root.display())));
+        lines.push(String::new());
+        lines.push(String::from(
+            "Add ChordPro or plain text songs there, or point `path`",
+        ));
+        lines.push(String::from("in the config to another folder."));
+        ("No songs", lines)
+    } else {
+        let lines = vec![
+            format!(
+                "{} / {}  choose a song, {} opens it",
+                keys.up.name(),
+                keys.down.name(),
+                keys.next.name()
+            ),
+            format!(
+                "{}  searches by name, or by text after '{}'",
+                keys.search.name(),
+                TEXT_SEARCH_PREFIX
+            ),
+            format!("{}  shows all keys", keys.help.name()),
+        ];
+        ("No song open", lines)
+    };
+    lines.push(String::new());
+    lines.push(match &app.config.source {
+        Some(source) => format!("Config: {}", source.display()),
+        None => String::from("Config: defaults, no config file was found"),
+    });
+
+    let width = lines.iter().map(|line| line.width()).max().unwrap_or(0) as u16 + 4;
+    let height = lines.len() as u16 + 2;
+    let lines: Vec<Spans> = lines.into_iter().map(Spans::from).collect();
+    f.render_widget(Block::default().borders(Borders::ALL), layout_chunk);
+    let message = Paragraph::new(lines).block(
+        Block::default()
+            .title(Span::styled(title, app.config.theme.title.to_style()))
+            .borders(Borders::ALL),
+    );
+    f.render_widget(message, centered(layout_chunk, width, height));
+}
+
+/// End of `text` that fits in `width` cells
+fn tail(text: &str, width: usize) -> &str {
+    let mut used = 0;
+    let start = text
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| {
+            used += c.width().unwrap_or(0);
+            used <= width
+        })
+        .last()
+        .map_or(text.len(), |(i, _)| i);
+    &text[start..]
+}
+
+/// Area of at most `width` by `height` in the center of `container`
+fn centered(container: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(container.width);
+    let height = height.min(container.height);
+    Rect::new(
+        container.x + (container.width - width) / 2,
+        container.y + (container.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+/// Draws the problems found in the open song, with a hint to fix them if possible
+pub fn draw_lints<B>(f: &mut Frame<B>, app: &App, layout_chunk: Rect)
+where
+    B: Backend,
+{
+    let mut lines: Vec<Spans> = app
+        .lints
+        .iter()
+        .map(|lint| {
+            let style = match lint.kind.fixable() {
+                true => app.config.theme.lyrics.to_style(),
+                false => app.config.theme.comment.to_style(),
+            };
+            Spans::from(vec![
+                Span::styled(
+                    format!("{:>4} ", lint.line),
+                    app.config.theme.title.to_style(),
+                ),
+                Span::styled(lint.message.as_str(), style),
+            ])
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push(Spans::from("No problems found"));
+    }
+    let title = match app.lints.iter().any(|lint| lint.kind.fixable()) {
+        true => "Problems (f to fix)",
+        false => "Problems",
+    };
+    let area = centered(layout_chunk, 70, lines.len() as u16 + 2);
+    let popup = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(app.config.theme.selected.to_style())
+            .title(Span::from(title)),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(popup, area);
+}
+
+/// Draws every keybind with its action, in as many columns as needed to fit
+pub fn draw_help<B>(f: &mut Frame<B>, app: &App, layout_chunk: Rect)
+where
+    B: Backend,
+{
+    let keybinds = app.config.keybinds.list();
+    let key_width = keybinds
+        .iter()
+        .map(|(key, _)| key.width())
+        .max()
+        .unwrap_or(0);
+    let entry_width = keybinds
+        .iter()
+        .map(|(_, action)| key_width + 2 + action.width())
+        .max()
+        .unwrap_or(0)
+        + 3;
+    let inner_width = layout_chunk.width.saturating_sub(2) as usize;
+    let inner_height = layout_chunk.height.saturating_sub(2).max(1) as usize;
+    // As few columns as fit in the height, rows past the bottom are cut off if the
+    // columns don't fit next to each other
+    let columns = keybinds
+        .len()
+        .div_ceil(inner_height)
+        .clamp(1, (inner_width / entry_width).max(1));
+    let rows = keybinds.len().div_ceil(columns);
+    let lines: Vec<Spans> = (0..rows)
+        .map(|row| {
+            let spans = (0..columns)
+                .filter_map(|column| keybinds.get(column * rows + row))
+                .flat_map(|(key, action)| {
+                    vec![
+                        Span::styled(
+                            format!("{:>width$}  ", key, width = key_width),
+                            app.config.theme.chord.to_style(),
+                        ),
+                        Span::raw(format!(
+                            "{:width$}",
+                            action,
+                            width = entry_width - key_width - 2
+                        )),
+                    ]
+                })
+                .collect::<Vec<_>>();
+            Spans::from(spans)
+        })
+        .collect();
+    let area = centered(
+        layout_chunk,
+        (columns * entry_width + 2) as u16,
+        rows as u16 + 2,
+    );
+    let popup = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(app.config.theme.selected.to_style())
+            .title(Span::from("Keybinds (Esc to close)")),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(popup, area);
+}
+
+/// Draws the presented part of the song over the whole screen, its lyrics centered and
+/// its name and number at the bottom
+pub fn draw_presentation<B>(f: &mut Frame<B>, app: &App, layout_chunk: Rect)
+where
+    B: Backend,
+{
+    f.render_widget(Clear, layout_chunk);
+    let (song, part, count) = match app.presented() {
+        Some(presented) => presented,
+        None => return,
+    };
+    let name = song
+        .outline()
+        .into_iter()
+        .find(|(line, _)| *line == part.start)
+        .map(|(_, name)| name);
+    // The name of the part is shown at the bottom instead of as the first line
+    let skip = song.content[part.start].header().is_some() as usize;
+    let style = app
+        .config
+        .theme
+        .lyrics
+        .to_style()
+        .add_modifier(Modifier::BOLD);
+    let mut lines: Vec<Spans> = song.content[part.clone()]
+        .iter()
+        .skip(skip)
+        .map(|line| Spans::from(Span::styled(line.text().trim().to_string(), style)))
+        .collect();
+    while lines.last().is_some_and(|line| line.width() == 0) {
+        lines.pop();
+    }
+    let footer = format!(
+        "{}{}/{}",
+        name.map(|name| format!("{} · ", name)).unwrap_or_default(),
+        app.slide.min(count - 1) + 1,
+        count
+    );
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+        .split(layout_chunk);
+    let height = (lines.len() as u16).min(chunks[0].height);
+    let area = Rect::new(
+        chunks[0].x,
+        chunks[0].y + (chunks[0].height - height) / 2,
+        chunks[0].width,
+        height,
+    );
+    f.render_widget(Paragraph::new(lines).alignment(Alignment::Center), area);
+    f.render_widget(
+        Paragraph::new(Span::styled(footer, app.config.theme.section.to_style()))
+            .alignment(Alignment::Center),
+        chunks[1],
+    );
+}
+
+/// Draws the parts of the open song to jump to, numbered for the first nine
+pub fn draw_sections<B>(f: &mut Frame<B>, app: &App, layout_chunk: Rect)
+where
+    B: Backend,
+{
+    let sections = app.sections();
+    let lines: Vec<Spans> = sections
+        .iter()
+        .enumerate()
+        .map(|(i, (_, name))| {
+            let number = match i {
+                0..=8 => format!("{} ", i + 1),
+                _ => "  ".to_string(),
+            };
+            let style = match i == app.section_selected {
+                true => app.config.theme.selected.to_style(),
+                false => app.config.theme.lyrics.to_style(),
+            };
+            Spans::from(vec![
+                Span::styled(number, app.config.theme.title.to_style()),
+                Span::styled(name.as_str(), style),
+            ])
+        })
+        .collect();
+    let width = sections
+        .iter()
+        .map(|(_, name)| name.width() as u16 + 4)
+        .max()
+        .unwrap_or(0)
+        .max(20);
+    let area = centered(layout_chunk, width, lines.len() as u16 + 2);
+    let popup = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(app.config.theme.selected.to_style())
+            .title(Span::from("Go to")),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(popup, area);
+}
+
+/// Draws a histogram of how often each chord is played
+pub fn draw_stats<B>(f: &mut Frame<B>, app: &App, layout_chunk: Rect)
+where
+    B: Backend,
+{
+    let stats = match &app.stats {
+        Some(stats) => stats,
+        None => return,
+    };
+    let area = centered(layout_chunk, 50, stats.counts.len() as u16 + 4);
+    let chord_width = stats
+        .counts
+        .iter()
+        .map(|(chord, _)| chord.chars().count())
+        .max()
+        .unwrap_or(0);
+    let max = stats.counts.first().map(|(_, count)| *count).unwrap_or(1);
+    // Leave room for the borders, chord name and count
+    let bar_width = (area.width as usize).saturating_sub(chord_width + 10);
+    let mut lines = vec![
+        Spans::from(format!(
+            "{} unique chords, {} in total",
+            stats.unique(),
+            stats.total()
+        )),
+        Spans::default(),
+    ];
+    lines.extend(stats.counts.iter().map(|(chord, count)| {
+        Spans::from(vec![
+            Span::styled(
+                format!("{:width$} ", chord, width = chord_width),
+                app.config.theme.chord.to_style(),
+            ),
+            Span::from("█".repeat((count * bar_width).div_ceil(max))),
+            Span::from(format!(" {}", count)),
+        ])
+    }));
+    let title = match app.file_nav.current().path.is_none() {
+        true => format!("Chords in {} (Tab for playlist/song)", stats.name),
+        false => format!("Chords in {}", stats.name),
+    };
+    let popup = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(app.config.theme.selected.to_style())
+            .title(Span::from(title)),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(popup, area);
+}
+
+/// Draws the metadata popup centered over `layout_chunk`
+pub fn draw_metadata<B>(f: &mut Frame<B>, app: &App, layout_chunk: Rect)
+where
+    B: Backend,
+{
+    let form = match &app.metadata {
+        Some(form) => form,
+        None => return,
+    };
+    let area = centered(layout_chunk, 50, form.fields.len() as u16 + 2);
+    let label_width = form
+        .fields
+        .iter()
+        .map(|(label, _)| label.len())
+        .max()
+        .unwrap_or(0);
+    let lines: Vec<Spans> = form
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(i, (label, value))| {
+            let mut spans = vec![
+                Span::styled(
+                    format!("{:width$} ", label, width = label_width),
+                    app.config.theme.title.to_style(),
+                ),
+                Span::from(value.as_str()),
+            ];
+            if i == form.selected {
+                spans.push(Span::styled("|", app.config.theme.selected.to_style()));
+            }
+            Spans::from(spans)
+        })
+        .collect();
+    let popup = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(app.config.theme.selected.to_style())
+            .title(Span::from("Metadata")),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(popup, area);
+}
+
+/// Draws `song` starting at line `scroll` of its content, or the page that line is on,
+/// and returns what fit on screen. It doesn't depend on the rest of the app state, so it
+/// can also be rendered headless.
+pub fn draw_song_view<B>(
+    f: &mut Frame<B>,
+    song: Option<&Song>,
+    config: &Config,
+    scroll: usize,
+    layout_chunk: Rect,
+) -> Shown
+where
+    B: Backend,
+{
+    match song {
         Some(song) => {
-            let song_block = Block::default()
-                .title(Span::styled(
+            let scroll = scroll.min(song.content.len().saturating_sub(1));
+            let mut title = vec![
+                Span::styled(
                     format!("{} - {}", song.title.as_str(), song.subtitle.as_str()),
-                    app.config.theme.title.to_style(),
-                ))
-                .borders(Borders::ALL);
+                    config.theme.title.to_style(),
+                ),
+                Span::styled(
+                    match song.capo {
+                        Some(capo) => format!(" (capo {})", capo),
+                        None => String::new(),
+                    },
+                    config.theme.section.to_style(),
+                ),
+                Span::styled(
+                    match (song.signed_offset(), song.key_name()) {
+                        (0, _) => String::new(),
+                        (offset, Some(key)) => format!(" ({:+} to {})", offset, key),
+                        (offset, None) => format!(" ({:+})", offset),
+                    },
+                    config.theme.section.to_style(),
+                ),
+                Span::styled(
+                    match song.capo_suggestion() {
+                        Some((capo, key)) if config.capo_suggestion => {
+                            format!(" (capo {} – play in {})", capo, key)
+                        }
+                        _ => String::new(),
+                    },
+                    config.theme.section.to_style(),
+                ),
+                Span::styled(
+                    match song.converted_from {
+                        Some(encoding) => format!(" (converted from {})", encoding),
+                        None => String::new(),
+                    },
+                    config.theme.comment.to_style(),
+                ),
+            ];
+            let song_block = Block::default().borders(Borders::ALL);
 
-            let song_rect = song_block.inner(layout_chunk);
-            let text = wrap_lines(&song.content, song_rect, app.config.extra_column_size);
+            let mut song_rect = song_block.inner(layout_chunk);
+            let chords = song.unique_chords();
+            if config.chord_index && !chords.is_empty() && song_rect.height > 2 {
+                let mut index = vec![Span::styled("Chords: ", config.theme.section.to_style())];
+                for chord in chords {
+                    index.push(Span::styled(
+                        chord.to_string(),
+                        config.theme.chord.to_style(),
+                    ));
+                    index.push(Span::raw("  "));
+                }
+                let width: usize = index.iter().map(Span::width).sum();
+                let height = (width as u16).div_ceil(song_rect.width.max(1)).min(3);
+                let chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Min(0), Constraint::Length(height)].as_ref())
+                    .split(song_rect);
+                song_rect = chunks[0];
+                f.render_widget(
+                    Paragraph::new(Spans::from(index)).wrap(Wrap { trim: true }),
+                    chunks[1],
+                );
+            }
+            if !song.definitions.is_empty() {
+                let diagrams = chord_diagrams(song, &config.theme);
+                let height = (diagrams.len() as u16).min(song_rect.height / 2);
+                let chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Min(0), Constraint::Length(height)].as_ref())
+                    .split(song_rect);
+                song_rect = chunks[0];
+                f.render_widget(Paragraph::new(diagrams), chunks[1]);
+            }
+            let metadata = song.metadata.fields();
+            if !metadata.is_empty() && song_rect.height > 2 {
+                let chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Length(2), Constraint::Min(0)].as_ref())
+                    .split(song_rect);
+                song_rect = chunks[1];
+                let mut header = vec![];
+                for (i, (label, value)) in metadata.into_iter().enumerate() {
+                    if i > 0 {
+                        header.push(Span::raw("  "));
+                    }
+                    header.push(Span::styled(
+                        format!("{}: ", label),
+                        config.theme.section.to_style(),
+                    ));
+                    header.push(Span::styled(
+                        value.to_string(),
+                        config.theme.lyrics.to_style(),
+                    ));
+                }
+                f.render_widget(Paragraph::new(Spans::from(header)), chunks[0]);
+            }
+            let labels = gutter_labels(song, config.gutter);
+            let gutter = gutter_width(&labels);
+            let mut shown = scroll..song.content.len();
+            let mut pages = vec![];
+            let mut columns = config.columns;
+            if config.paging {
+                pages = page_lines(&song.content, song_rect, config.column_padding, gutter);
+                if let Some(page) = pages.iter().rfind(|page| page.start <= scroll) {
+                    shown = page.clone();
+                }
+                columns = Some(1);
+            }
+            let (text, fits) = wrap_lines(
+                &song.content[shown.clone()],
+                labels.get(shown).unwrap_or_default(),
+                song_rect,
+                columns,
+                config.column_padding,
+            );
+            // Where the song is scrolled to, when it doesn't fit on screen
+            let mut position = None;
+            if pages.len() > 1 {
+                let page = pages.iter().rposition(|page| page.start <= scroll);
+                position = Some((page.unwrap_or(0), pages.len()));
+                title.push(Span::styled(
+                    format!(" (page {}/{})", page.unwrap_or(0) + 1, pages.len()),
+                    config.theme.section.to_style(),
+                ));
+            } else if !config.paging && (scroll > 0 || !fits) {
+                position = Some((scroll, song.content.len()));
+                title.push(Span::styled(
+                    format!(" (line {}/{})", scroll + 1, song.content.len()),
+                    config.theme.section.to_style(),
+                ));
+            }
+            let song_block = song_block.title(Spans::from(title));
 
             let constraints: Vec<Constraint> = text
                 .iter()
                 .map(|column| {
-                    Constraint::Length(column.width() as u16 + app.config.column_padding as u16)
+                    Constraint::Length(column.width() as u16 + config.column_padding as u16)
                 })
                 .collect();
 
             let song_layout = Layout::default()
                 .direction(Direction::Horizontal)
-                .margin(1)
-                .constraints(constraints.as_ref())
-                .split(layout_chunk);
+                .constraints(constraints)
+                .split(song_rect);
 
             for (i, column) in song_layout.iter().enumerate() {
                 f.render_widget(
-                    Paragraph::new(Text::from(text[i].to_spans(&app.config.theme))),
+                    Paragraph::new(Text::from(text[i].to_spans(&config.theme))),
                     *column,
                 );
             }
+            if let Some(rule) = config
+                .column_rule
+                .as_deref()
+                .filter(|_| config.column_padding > 0)
+            {
+                let rules = vec![Spans::from(rule); song_rect.height as usize];
+                for column in song_layout.iter().skip(1).filter(|column| column.width > 0) {
+                    let padding = config.column_padding as u16;
+                    let x = column.x - padding + (padding - 1) / 2;
+                    f.render_widget(
+                        Paragraph::new(rules.clone()).style(config.theme.column_rule.to_style()),
+                        Rect::new(x, song_rect.y, 1, song_rect.height),
+                    );
+                }
+            }
             f.render_widget(song_block, layout_chunk);
+            match position {
+                Some((current, total)) if config.scrollbar && layout_chunk.height > 2 => {
+                    let track = layout_chunk.height as usize - 2;
+                    let y = current * (track - 1) / total.saturating_sub(1).max(1);
+                    f.render_widget(
+                        Paragraph::new("█").style(config.theme.scrollbar.to_style()),
+                        Rect::new(
+                            layout_chunk.right() - 1,
+                            layout_chunk.y + 1 + y as u16,
+                            1,
+                            1,
+                        ),
+                    );
+                }
+                _ => (),
+            }
+            let mut used = 0;
+            let columns = text
+                .iter()
+                .take_while(|column| {
+                    used += column.width() + config.column_padding;
+                    used <= song_rect.width as usize
+                })
+                .count();
+            Shown { columns, pages }
+        }
+        None => {
+            f.render_widget(Block::default().borders(Borders::ALL), layout_chunk);
+            Shown::default()
         }
-        None => f.render_widget(Block::default().borders(Borders::ALL), layout_chunk),
     }
 }
 
+/// Diagrams of the chords the song defines, side by side
+fn chord_diagrams<'a>(song: &Song, theme: &Theme) -> Vec<Spans<'a>> {
+    let diagrams: Vec<Vec<String>> = song
+        .definitions
+        .iter()
+        .map(|definition| definition.diagram())
+        .collect();
+    let height = diagrams
+        .iter()
+        .map(|diagram| diagram.len())
+        .max()
+        .unwrap_or(0);
+    (0..height)
+        .map(|row| {
+            Spans::from(
+                diagrams
+                    .iter()
+                    .flat_map(|diagram| {
+                        let width = diagram.iter().map(|line| line.chars().count()).max();
+                        let width = width.unwrap_or(0);
+                        let line = diagram.get(row).map(String::as_str).unwrap_or("");
+                        let style = match row {
+                            0 => theme.chord.to_style(),
+                            _ => theme.lyrics.to_style(),
+                        };
+                        [
+                            Span::styled(format!("{:width$}", line, width = width), style),
+                            Span::raw("   "),
+                        ]
+                    })
+                    .collect::<Vec<Span>>(),
+            )
+        })
+        .collect()
+}
+
 #[derive(Debug, Default)]
 pub struct Column {
     content: Vec<SongLine>,
+    /// Gutter label of each line, empty for the rest of a wrapped line
+    labels: Vec<String>,
+    /// Width of the gutter, which is part of `width`
+    gutter: usize,
+    width: usize,
 }
 
 impl<'a> Column {
     pub fn from(content: Vec<SongLine>) -> Self {
-        Column { content }
+        let width = content.iter().map(|line| line.width()).max().unwrap_or(0);
+        Column {
+            content,
+            labels: vec![],
+            gutter: 0,
+            width,
+        }
     }
 
     pub fn width(&self) -> usize {
-        self.content
-            .iter()
-            .map(|line| line.width())
-            .max()
-            .unwrap_or(0)
+        self.width
     }
 
+    /// Formats the lines, with their gutter label next to the lyrics rather than the
+    /// chords above them
     pub fn to_spans(&self, theme: &Theme) -> Vec<Spans<'a>> {
-        self.content
-            .iter()
-            .cloned()
-            .flat_map(|line| line.format(theme))
-            .collect()
+        let mut spans = vec![];
+        for (i, line) in self.content.iter().enumerate() {
+            let rows = line.format(theme);
+            let last = rows.len().saturating_sub(1);
+            for (row, mut formatted) in rows.into_iter().enumerate() {
+                if self.gutter > 0 {
+                    let label = match row == last {
+                        true => self.labels.get(i).map_or("", String::as_str),
+                        false => "",
+                    };
+                    let label = format!("{:>width$} ", label, width = self.gutter - 1);
+                    formatted
+                        .0
+                        .insert(0, Span::styled(label, theme.gutter.to_style()));
+                }
+                spans.push(formatted);
+            }
+        }
+        spans
     }
 }
 
-pub fn wrap_lines(lines: &[SongLine], container: Rect, extra_column_size: usize) -> Vec<Column> {
-    let height = (container.height - 2) as usize;
-    if lines.is_empty() {
-        return vec![];
+/// Label of every line of `song` in the gutter, none when there's no gutter
+pub fn gutter_labels(song: &Song, gutter: Gutter) -> Vec<String> {
+    let mut labels = vec![String::new(); song.content.len()];
+    match gutter {
+        Gutter::None => return vec![],
+        Gutter::Numbers => {
+            for (i, line) in song.content.iter().enumerate() {
+                if line.width() > 0 {
+                    labels[i] = (i + 1).to_string();
+                }
+            }
+        }
+        Gutter::Marks => {
+            for (i, (line, _)) in song.outline().into_iter().enumerate() {
+                let letter = (b'A' + (i % 26) as u8) as char;
+                labels[line] = match i / 26 {
+                    0 => letter.to_string(),
+                    round => format!("{}{}", letter, round + 1),
+                };
+            }
+        }
+    }
+    labels
+}
+
+/// Width the labels take up in the gutter, including a space after them
+fn gutter_width(labels: &[String]) -> usize {
+    match labels.iter().map(|label| label.width()).max() {
+        Some(width) if width > 0 => width + 1,
+        _ => 0,
+    }
+}
+
+/// `line` wrapped to `max_width` like `SongLine::wrap`, with the metrics of its parts.
+/// Songs are drawn again on every frame but rarely change, so the result is remembered.
+fn wrap(line: &SongLine, max_width: usize) -> Wrapped {
+    let mut cache = WRAPPED.lock().unwrap();
+    if cache.len() >= WRAPPED_WIDTHS && !cache.contains_key(&max_width) {
+        cache.clear();
+    }
+    let lines = cache.entry(max_width).or_default();
+    if let Some(wrapped) = lines.get(line) {
+        return wrapped.clone();
     }
+    if lines.len() >= WRAPPED_LINES {
+        lines.clear();
+    }
+    let wrapped: Wrapped = line
+        .wrap(max_width)
+        .into_iter()
+        .map(|line| {
+            let metrics = line.metrics();
+            (line, metrics)
+        })
+        .collect();
+    lines.insert(line.clone(), wrapped.clone());
+    wrapped
+}
 
-    let mut line_widths: Vec<usize> = lines.iter().map(|line| line.width()).collect();
-    line_widths.sort_unstable();
-    let median_width = line_widths[line_widths.len() / 2];
+/// Splits the lines into pages that each fit in `container` as a single column, next to
+/// a `gutter` that wide. A line that gets wrapped stays on one page.
+pub fn page_lines(
+    lines: &[SongLine],
+    container: Rect,
+    column_padding: usize,
+    gutter: usize,
+) -> Vec<Range<usize>> {
+    let height = container.height.saturating_sub(2) as usize;
+    let max_width = layout::wrap_width(container.width as usize, 1, column_padding)
+        .saturating_sub(gutter)
+        .max(1);
+    let metrics: Vec<LineMetrics> = lines
+        .iter()
+        .map(|line| {
+            let wrapped: Vec<LineMetrics> = wrap(line, max_width)
+                .into_iter()
+                .map(|(_, metrics)| metrics)
+                .collect();
+            LineMetrics {
+                width: wrapped.iter().map(|line| line.width).max().unwrap_or(0),
+                height: wrapped.iter().map(|line| line.height).sum(),
+                column_break: wrapped.first().is_some_and(|line| line.column_break),
+                keep_with_next: wrapped.last().is_some_and(|line| line.keep_with_next),
+            }
+        })
+        .collect();
+    layout::pages(&metrics, height)
+}
 
-    let line_wrapped_text: Vec<SongLine> = lines
+/// Wraps the song to fit `columns` columns, or as many as fit the longest line if it's
+/// not given, and splits it into columns, keeping only the columns (plus one spare)
+/// that fit in `container`, so only what is on screen gets formatted into spans. Every
+/// column gets a gutter with the `labels` of its lines, unless there are none. Also
+/// returns whether every column fits.
+pub fn wrap_lines(
+    lines: &[SongLine],
+    labels: &[String],
+    container: Rect,
+    columns: Option<usize>,
+    column_padding: usize,
+) -> (Vec<Column>, bool) {
+    let height = container.height.saturating_sub(2) as usize;
+    let width = container.width as usize;
+    let gutter = gutter_width(labels);
+    let with_gutter = |mut metrics: LineMetrics| {
+        metrics.width += gutter;
+        metrics
+    };
+    // Not wrapped at all, to find how many columns the lines need
+    let metrics: Vec<LineMetrics> = lines
         .iter()
-        .flat_map(|line| line.wrap(median_width + extra_column_size))
+        .map(|line| with_gutter(wrap(line, usize::MAX)[0].1))
         .collect();
+    let count =
+        columns.unwrap_or_else(|| layout::column_count(&metrics, width, height, column_padding));
+    let max_width = layout::wrap_width(width, count, column_padding);
 
-    let mut column_wrapped_text: Vec<Column> = vec![];
-    let mut columnheight = 0;
-    let mut rest = &line_wrapped_text[..];
-    let mut i = 0;
-    line_wrapped_text.iter().for_each(|line| {
-        if columnheight + line.height() > height {
-            let split_text = rest.split_at(i);
-            rest = split_text.1;
-            column_wrapped_text.push(Column::from(split_text.0.to_vec()));
-            columnheight = 0;
-            i = 0;
-        } else {
-            columnheight += line.height();
-            i += 1;
+    let mut wrapped = vec![];
+    let mut wrapped_labels = vec![];
+    let mut metrics = vec![];
+    for (i, line) in lines.iter().enumerate() {
+        for (part, (line, line_metrics)) in wrap(line, max_width.saturating_sub(gutter).max(1))
+            .into_iter()
+            .enumerate()
+        {
+            wrapped.push(line);
+            metrics.push(with_gutter(line_metrics));
+            wrapped_labels.push(match (part, labels.get(i)) {
+                (0, Some(label)) => label.clone(),
+                _ => String::new(),
+            });
         }
-    });
-    column_wrapped_text.push(Column::from(rest.to_vec()));
+    }
 
-    column_wrapped_text
+    let mut plan = layout::plan_columns(&metrics, height, container.width as usize, column_padding);
+    // A set number of columns divides the width evenly, however wide the lines are
+    if columns.is_some() {
+        for column in plan.columns.iter_mut() {
+            column.width = max_width;
+        }
+    }
+    let fits = plan.width(column_padding) <= container.width as usize;
+    let columns = plan
+        .visible(container.width as usize, column_padding, 1)
+        .columns
+        .into_iter()
+        .map(|column| Column {
+            content: wrapped[column.lines.clone()].to_vec(),
+            labels: wrapped_labels[column.lines].to_vec(),
+            gutter,
+            width: column.width,
+        })
+        .collect();
+    (columns, fits)
 }
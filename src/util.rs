@@ -1,6 +1,7 @@
 #![allow(dead_code)]
 
-use std::io;
+use std::io::{self, Read};
+use std::path::PathBuf;
 use std::sync::mpsc;
 use std::sync::{
     atomic::{AtomicBool, Ordering},
@@ -9,20 +10,34 @@ use std::sync::{
 use std::thread;
 use std::time::Duration;
 
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use signal_hook::{consts::SIGWINCH, iterator::Signals};
 use termion::event::Key;
 use termion::input::TermRead;
 
+use crate::index::Index;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
 pub enum Event<I> {
     Input(I),
     Tick,
+    /// The library was scanned in the background
+    Indexed(Index),
+    /// Files in the library were created, modified or removed
+    Changed(Vec<PathBuf>),
+    Resize,
 }
 
 /// A small event handler that wrap termion input and tick events. Each event
 /// type is handled in its own thread and returned to a common `Receiver`
 pub struct Events {
     rx: mpsc::Receiver<Event<Key>>,
+    tx: mpsc::Sender<Event<Key>>,
     input_handle: thread::JoinHandle<()>,
     ignore_exit_key: Arc<AtomicBool>,
+    paused: Arc<AtomicBool>,
+    input_parked: Arc<AtomicBool>,
     tick_handle: thread::JoinHandle<()>,
 }
 
@@ -41,6 +56,12 @@ impl Default for Config {
     }
 }
 
+impl Default for Events {
+    fn default() -> Events {
+        Events::new()
+    }
+}
+
 impl Events {
     pub fn new() -> Events {
         Events::with_config(Config::default())
@@ -49,23 +70,57 @@ impl Events {
     pub fn with_config(config: Config) -> Events {
         let (tx, rx) = mpsc::channel();
         let ignore_exit_key = Arc::new(AtomicBool::new(false));
+        let paused = Arc::new(AtomicBool::new(false));
+        let input_parked = Arc::new(AtomicBool::new(false));
         let input_handle = {
             let tx = tx.clone();
             let ignore_exit_key = ignore_exit_key.clone();
+            let paused = paused.clone();
+            let input_parked = input_parked.clone();
             thread::spawn(move || {
-                let stdin = io::stdin();
-                for key in stdin.keys().flatten() {
-                    if let Err(err) = tx.send(Event::Input(key)) {
-                        eprintln!("{}", err);
-                        return;
+                let mut stdin = io::stdin();
+                let mut buf = [0; 64];
+                loop {
+                    // Only read stdin when there is input, so the terminal can be handed
+                    // over to another program while input is paused
+                    if paused.load(Ordering::Relaxed) {
+                        input_parked.store(true, Ordering::Relaxed);
+                        thread::sleep(POLL_INTERVAL);
+                        continue;
                     }
-                    if !ignore_exit_key.load(Ordering::Relaxed) && key == config.exit_key {
-                        return;
+                    input_parked.store(false, Ordering::Relaxed);
+                    if !stdin_ready(POLL_INTERVAL) {
+                        continue;
+                    }
+                    let n = match stdin.read(&mut buf) {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => n,
+                    };
+                    for key in (&buf[..n]).keys().flatten() {
+                        if let Err(err) = tx.send(Event::Input(key)) {
+                            eprintln!("{}", err);
+                            return;
+                        }
+                        if !ignore_exit_key.load(Ordering::Relaxed) && key == config.exit_key {
+                            return;
+                        }
                     }
                 }
             })
         };
+        // Redraw right away on SIGWINCH instead of at the next tick
+        if let Ok(mut signals) = Signals::new([SIGWINCH]) {
+            let tx = tx.clone();
+            thread::spawn(move || {
+                for _ in signals.forever() {
+                    if tx.send(Event::Resize).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
         let tick_handle = {
+            let tx = tx.clone();
             thread::spawn(move || loop {
                 if tx.send(Event::Tick).is_err() {
                     break;
@@ -75,8 +130,11 @@ impl Events {
         };
         Events {
             rx,
+            tx,
             input_handle,
             ignore_exit_key,
+            paused,
+            input_parked,
             tick_handle,
         }
     }
@@ -85,6 +143,28 @@ impl Events {
         self.rx.recv()
     }
 
+    /// Sends a `Changed` event whenever something changes in one of the folders `roots`,
+    /// as long as the returned watcher is kept
+    pub fn watch(&self, roots: &[PathBuf]) -> notify::Result<RecommendedWatcher> {
+        let tx = self.tx.clone();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            if let Ok(notify::Event { kind, paths, .. }) = event {
+                if !kind.is_access() {
+                    let _ = tx.send(Event::Changed(paths));
+                }
+            }
+        })?;
+        for root in roots.iter().filter(|root| root.is_dir()) {
+            watcher.watch(root, RecursiveMode::Recursive)?;
+        }
+        Ok(watcher)
+    }
+
+    /// Sender for events from other threads, like the background indexer
+    pub fn sender(&self) -> mpsc::Sender<Event<Key>> {
+        self.tx.clone()
+    }
+
     pub fn disable_exit_key(&mut self) {
         self.ignore_exit_key.store(true, Ordering::Relaxed);
     }
@@ -92,4 +172,28 @@ impl Events {
     pub fn enable_exit_key(&mut self) {
         self.ignore_exit_key.store(false, Ordering::Relaxed);
     }
+
+    /// Stops reading from stdin, returning once the input thread is no longer reading so
+    /// another program can safely take over the terminal. The input thread stops on its
+    /// own when stdin is closed, then there's nothing to wait for.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+        while !self.input_parked.load(Ordering::Relaxed) && !self.input_handle.is_finished() {
+            thread::sleep(POLL_INTERVAL / 4);
+        }
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Waits up to `timeout` for stdin to become readable
+fn stdin_ready(timeout: Duration) -> bool {
+    let mut fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    unsafe { libc::poll(&mut fd, 1, timeout.as_millis() as libc::c_int) > 0 }
 }